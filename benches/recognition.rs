@@ -0,0 +1,81 @@
+// Per-frame latency of piece recognition on the fixture boards: threads spawned for every
+// frame (`find_all_pieces_with_thresholds`) against the persistent worker pool (`Recognizer`).
+//
+// Run with `cargo bench --bench recognition`.
+use cheatess_core::engine::Color;
+use cheatess_core::geometry::BoardGeometry;
+use cheatess_core::procimg::{self, Mat};
+use cheatess_core::recognizer::Recognizer;
+use opencv::{imgcodecs, imgproc};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const FRAMES: usize = 200;
+const BOARDS: [&str; 2] = [
+    "templates/boards/original/init.png",
+    "templates/boards/original/white_moved.png",
+];
+
+fn load_board(path: &str) -> Mat {
+    let raw = imgcodecs::imread(path, imgcodecs::IMREAD_UNCHANGED).expect("Fixture not found");
+    let mut gray = Mat::default();
+    imgproc::cvt_color(&raw, &mut gray, imgproc::COLOR_RGBA2GRAY, 0).unwrap();
+    let geometry = BoardGeometry::detect(&gray).unwrap();
+    procimg::normalize_board(&gray, &geometry).unwrap()
+}
+
+/// Runs recognition on the boards in turn and returns the median and the mean latency.
+fn measure(mut recognize: impl FnMut(&Mat) -> [[char; 8]; 8], boards: &[Mat]) -> [Duration; 2] {
+    // warm up (caches, lazily allocated buffers)
+    for board in boards {
+        recognize(board);
+    }
+
+    let mut times = (0..FRAMES)
+        .map(|i| {
+            let start = Instant::now();
+            std::hint::black_box(recognize(&boards[i % boards.len()]));
+            start.elapsed()
+        })
+        .collect::<Vec<_>>();
+    times.sort();
+    [
+        times[FRAMES / 2],
+        times.iter().sum::<Duration>() / FRAMES as u32,
+    ]
+}
+
+fn main() {
+    let boards = BOARDS.map(load_board);
+    let geometry = BoardGeometry::canonical();
+    let pieces: HashMap<char, Arc<Mat>> =
+        procimg::extract_pieces(&boards[0], &geometry, 5, 127.0, &Color::White)
+            .unwrap()
+            .into_iter()
+            .map(|(sign, piece)| (sign, Arc::new(piece)))
+            .collect();
+    let thresholds = HashMap::new();
+
+    let spawned = measure(
+        |board| {
+            procimg::find_all_pieces_with_thresholds(
+                board,
+                &geometry,
+                &pieces,
+                &thresholds,
+                0.1,
+                100.0,
+            )
+            .unwrap()
+        },
+        &boards,
+    );
+
+    let mut recognizer = Recognizer::new(&pieces, &geometry, &thresholds, 0.1, 100.0);
+    let pooled = measure(|board| recognizer.recognize(board).unwrap(), &boards);
+
+    println!("recognition of {FRAMES} frames (median / mean per frame):");
+    println!("  threads per frame: {:?} / {:?}", spawned[0], spawned[1]);
+    println!("  worker pool:       {:?} / {:?}", pooled[0], pooled[1]);
+}
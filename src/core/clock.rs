@@ -0,0 +1,152 @@
+// Reading of chess clocks shown next to the board. Sites put the clock of each player
+// into the bar above or below the board or into the panel right of the board, so these
+// regions are searched for text looking like a clock (e.g. `3:05`, `1:02:00`, `12.4`).
+use super::ocr::{read_lines, text_mask};
+use super::procimg::Mat;
+use crate::utils::error::CheatessResult;
+use opencv::{
+    core::{Rect, Size},
+    prelude::*,
+};
+use regex::Regex;
+use std::time::Duration;
+
+/// Pattern of the clock: `[H:]M:SS[.d]` or `S.d` (last seconds shown with tenths).
+pub static CLOCK_PATTERN: &str = r"\d{1,2}(:\d\d){1,2}(\.\d)?|\d{1,2}\.\d";
+
+/// Clocks of players as seen on the screen.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Clocks {
+    /// Clock of the player at the top of the board.
+    pub top: Option<Duration>,
+    /// Clock of the player at the bottom of the board.
+    pub bottom: Option<Duration>,
+}
+
+/// Reads clocks of both players around the board region `(x, y, w, h)` of the gray screen.
+pub fn read_clocks(gray_screen: &Mat, board: &(u32, u32, u32, u32)) -> CheatessResult<Clocks> {
+    let screen = gray_screen.size()?;
+    let clocks = Clocks {
+        top: read_clock(gray_screen, &search_regions(board, screen, true))?,
+        bottom: read_clock(gray_screen, &search_regions(board, screen, false))?,
+    };
+    log::debug!("Read clocks: {clocks:?}");
+    Ok(clocks)
+}
+
+/// Regions searched for the clock of the top (or bottom) player, in order: the bar next
+/// to the board and the half of the panel right of the board. Regions are clipped to the
+/// screen, those outside of it are left out.
+pub fn search_regions(board: &(u32, u32, u32, u32), screen: Size, top: bool) -> Vec<Rect> {
+    let (x, y, w, h) = (
+        board.0 as i32,
+        board.1 as i32,
+        board.2 as i32,
+        board.3 as i32,
+    );
+    let bar = h / 8;
+    let regions = if top {
+        [
+            Rect::new(x, y - bar, w, bar),
+            Rect::new(x + w, y, w / 2, h / 2),
+        ]
+    } else {
+        [
+            Rect::new(x, y + h, w, bar),
+            Rect::new(x + w, y + h / 2, w / 2, h / 2),
+        ]
+    };
+
+    let screen = Rect::new(0, 0, screen.width, screen.height);
+    regions
+        .into_iter()
+        .map(|region| region & screen)
+        .filter(|region| region.width > 0 && region.height > 0)
+        .collect()
+}
+
+/// Returns the first clock found in the regions.
+fn read_clock(gray_screen: &Mat, regions: &[Rect]) -> CheatessResult<Option<Duration>> {
+    let pattern = Regex::new(CLOCK_PATTERN)?;
+    for region in regions {
+        for line in read_lines(&text_mask(&Mat::roi(gray_screen, *region)?)?)? {
+            let clock = pattern
+                .find_iter(&line)
+                .find_map(|found| parse_clock(found.as_str()));
+            if clock.is_some() {
+                return Ok(clock);
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Parses the clock shown by sites: `M:SS`, `H:MM:SS` (both optionally with tenths of
+/// a second) or `S.d`.
+pub fn parse_clock(text: &str) -> Option<Duration> {
+    let (whole, tenths) = match text.split_once('.') {
+        Some((whole, tenths)) if tenths.len() == 1 => (whole, Some(tenths.parse::<u64>().ok()?)),
+        Some(_) => return None,
+        None => (text, None),
+    };
+
+    let parts = whole
+        .split(':')
+        .map(|part| part.parse::<u64>().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    let seconds = match parts.as_slice() {
+        [s] if tenths.is_some() => *s,
+        [m, s] if *s < 60 => m * 60 + s,
+        [h, m, s] if *m < 60 && *s < 60 => h * 3600 + m * 60 + s,
+        _ => return None,
+    };
+    Some(Duration::from_millis(
+        seconds * 1000 + tenths.unwrap_or(0) * 100,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::procimg::image_buffer_to_gray_mat;
+    use crate::core::render::draw_text;
+    use image::{ImageBuffer, Rgba};
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("3:05", Some(185_000))]
+    #[case("10:00", Some(600_000))]
+    #[case("1:02:03", Some(3_723_000))]
+    #[case("0:09.7", Some(9_700))]
+    #[case("12.4", Some(12_400))]
+    #[case("5:60", None)]
+    #[case("12", None)]
+    #[case("1:2a", None)]
+    fn parse_clock_cases(#[case] text: &str, #[case] millis: Option<u64>) {
+        assert_eq!(parse_clock(text), millis.map(Duration::from_millis));
+    }
+
+    #[rstest]
+    #[case(false)]
+    #[case(true)]
+    fn read_clocks_around_the_board(#[case] side_panel: bool) {
+        let board = (100, 100, 400, 400);
+        let mut screen = ImageBuffer::from_pixel(800, 650, Rgba([40, 40, 40, 255]));
+        for (x, y) in (100..500).flat_map(|x| (100..500).map(move |y| (x, y))) {
+            screen.put_pixel(x, y, Rgba([150, 150, 150, 255]));
+        }
+        let white = Rgba([230, 230, 230, 255]);
+        let (top, bottom) = match side_panel {
+            false => ((300, 65), (300, 515)),
+            true => ((560, 150), (560, 420)),
+        };
+        draw_text(&mut screen, "4:59", top, 3, white);
+        draw_text(&mut screen, "10:03.5", bottom, 3, white);
+
+        let gray = image_buffer_to_gray_mat(screen).unwrap();
+        let clocks = read_clocks(&gray, &board).unwrap();
+
+        assert_eq!(clocks.top, Some(Duration::from_secs(299)));
+        assert_eq!(clocks.bottom, Some(Duration::from_millis(603_500)));
+    }
+}
@@ -8,6 +8,10 @@ pub use crate::utils::printer::{
     AnyBoard, BlackView, DefaultPrinter, PrettyPrinter, Printer, View, WhiteView,
 };
 
+/// Squares (besides the move) which may be misrecognized when the move is taken from
+/// highlighted squares.
+const MAX_MISMATCHED_SQUARES: usize = 2;
+
 pub struct Board<P: Printer, V: View> {
     pub raw: [[char; 8]; 8],
     printer: std::marker::PhantomData<(P, V)>,
@@ -99,7 +103,7 @@ impl<P: Printer, V: View> Board<P, V> {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Color {
     White,
     Black,
@@ -124,23 +128,155 @@ pub fn create_board_from_data<P: Printer + 'static + Send + Sync>(
     }
 }
 
-// Insert piece to array board, based on top left position.
-pub fn register_piece(
-    point: (i32, i32),
-    board_size: (i32, i32),
-    piece: char,
-    board: &mut [[char; 8]; 8],
-) -> CheatessResult<()> {
-    let tile_width = board_size.0 / 8;
-    let tile_height = board_size.1 / 8;
+// Parse piece placement (first field) of FEN to array board seen from white's side.
+pub fn board_from_fen(fen: &str) -> CheatessResult<[[char; 8]; 8]> {
+    let invalid = || CheatessError::InvalidFen(fen.to_string());
+    let placement = fen.split_whitespace().next().ok_or_else(invalid)?;
+
+    let ranks: Vec<&str> = placement.split('/').collect();
+    if ranks.len() != 8 {
+        return Err(invalid());
+    }
+
+    let mut board = [[' '; 8]; 8];
+    for (row, rank) in ranks.iter().enumerate() {
+        let mut col = 0;
+        for c in rank.chars() {
+            match c {
+                '1'..='8' => col += c.to_digit(10).unwrap() as usize,
+                'p' | 'n' | 'b' | 'r' | 'q' | 'k' | 'P' | 'N' | 'B' | 'R' | 'Q' | 'K' => {
+                    if col >= 8 {
+                        return Err(invalid());
+                    }
+                    board[row][col] = c;
+                    col += 1;
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        if col != 8 {
+            return Err(invalid());
+        }
+    }
+    Ok(board)
+}
+
+// Rotate board by 180 degrees (change between white's and black's point of view).
+pub fn rotate_board(board: &[[char; 8]; 8]) -> [[char; 8]; 8] {
+    let mut rotated = [[' '; 8]; 8];
+    for row in 0..8 {
+        for col in 0..8 {
+            rotated[7 - row][7 - col] = board[row][col];
+        }
+    }
+    rotated
+}
+
+/// Parts of the FEN which can't be read from the board image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PositionSetup {
+    /// Castling rights in FEN format (e.g. `KQkq` or `-`).
+    pub castling: String,
+    /// En passant target square (e.g. `e3` or `-`).
+    pub en_passant: String,
+}
+
+// Create FEN from array board seen from white's side. Move counters are unknown, so the
+// position is treated as a beginning of the game.
+pub fn board_to_fen(board: &[[char; 8]; 8], side_to_move: &Color, setup: &PositionSetup) -> String {
+    let placement = board
+        .iter()
+        .map(|row| {
+            let mut rank = String::new();
+            let mut empty = 0;
+            for &c in row {
+                if c == ' ' {
+                    empty += 1;
+                    continue;
+                }
+                if empty > 0 {
+                    rank.push_str(&empty.to_string());
+                    empty = 0;
+                }
+                rank.push(c);
+            }
+            if empty > 0 {
+                rank.push_str(&empty.to_string());
+            }
+            rank
+        })
+        .collect::<Vec<String>>()
+        .join("/");
+
+    let side = match side_to_move {
+        Color::White => 'w',
+        Color::Black => 'b',
+    };
+    format!(
+        "{placement} {side} {} {} 0 1",
+        setup.castling, setup.en_passant
+    )
+}
 
-    let row = (point.1 / tile_height).clamp(0, 7) as usize;
-    let col = (point.0 / tile_width).clamp(0, 7) as usize;
+// Check if array board (seen from white's side) can be passed to the engine:
+// exactly one king of each color and no pawns on the first and last rank.
+pub fn validate_position(board: &[[char; 8]; 8]) -> CheatessResult<()> {
+    let count = |piece: char| board.iter().flatten().filter(|&&c| c == piece).count();
+    let pawns_on_edges = board[0]
+        .iter()
+        .chain(board[7].iter())
+        .any(|c| c.eq_ignore_ascii_case(&'p'));
 
-    board[col][row] = piece;
+    if count('K') != 1 || count('k') != 1 || pawns_on_edges {
+        return Err(CheatessError::InvalidFen(board_to_fen(
+            board,
+            &Color::White,
+            &PositionSetup {
+                castling: "-".to_string(),
+                en_passant: "-".to_string(),
+            },
+        )));
+    }
     Ok(())
 }
 
+// Castling rights which are still possible in the position (seen from white's side):
+// king and rook stay on their initial squares.
+pub fn infer_castling(board: &[[char; 8]; 8]) -> String {
+    let mut castling = String::new();
+    for (row, king, rook, rights) in [(7, 'K', 'R', ['K', 'Q']), (0, 'k', 'r', ['k', 'q'])] {
+        if board[row][4] != king {
+            continue;
+        }
+        if board[row][7] == rook {
+            castling.push(rights[0]);
+        }
+        if board[row][0] == rook {
+            castling.push(rights[1]);
+        }
+    }
+
+    if castling.is_empty() {
+        "-".to_string()
+    } else {
+        castling
+    }
+}
+
+fn piece_color(piece: char) -> Option<Color> {
+    match piece {
+        'K' | 'Q' | 'R' | 'B' | 'N' | 'P' => Some(Color::White),
+        'k' | 'q' | 'r' | 'b' | 'n' | 'p' => Some(Color::Black),
+        _ => None,
+    }
+}
+
+// Color of the side which made the move in UCI format (the side of the piece on its source square).
+pub fn mover_color(before: &[[char; 8]; 8], uci: &str, player_color: &Color) -> Option<Color> {
+    let ((row, col), _) = move_squares(uci, player_color)?;
+    piece_color(before[row][col])
+}
+
 // Change (x,y) coordiantes to string position representation.
 fn coords_to_position(row: usize, col: usize, player_color: &Color) -> String {
     if player_color == &Color::White {
@@ -154,6 +290,123 @@ fn coords_to_position(row: usize, col: usize, player_color: &Color) -> String {
     }
 }
 
+// Change string position representation to (row, col) coordinates (inverse of `coords_to_position`).
+fn position_to_coords(position: &str, player_color: &Color) -> Option<(usize, usize)> {
+    let &[file @ b'a'..=b'h', rank @ b'1'..=b'8'] = position.as_bytes() else {
+        return None;
+    };
+    let (file, rank) = ((file - b'a') as usize, (rank - b'1') as usize);
+    match player_color {
+        Color::White => Some((7 - rank, file)),
+        Color::Black => Some((rank, 7 - file)),
+    }
+}
+
+// Coordinates of the source and target squares of the move in UCI format.
+pub fn move_squares(uci: &str, player_color: &Color) -> Option<((usize, usize), (usize, usize))> {
+    let from = position_to_coords(uci.get(0..2)?, player_color)?;
+    let to = position_to_coords(uci.get(2..4)?, player_color)?;
+    Some((from, to))
+}
+
+// Reconstruct the move from two highlighted squares (e.g. last move tinted by the website).
+// Source square is the one which is occupied before the move and empty after it, target
+// square is occupied after it. The move has to be legal and the position after it may
+// differ from the recognized one on at most `MAX_MISMATCHED_SQUARES` squares.
+pub fn move_from_squares(
+    before: &[[char; 8]; 8],
+    after: &[[char; 8]; 8],
+    squares: &[(usize, usize)],
+    player_color: &Color,
+) -> Option<String> {
+    let &[a, b] = squares else {
+        return None;
+    };
+    let occupied = |board: &[[char; 8]; 8], (row, col): (usize, usize)| board[row][col] != ' ';
+    let moved = |from, to| occupied(before, from) && !occupied(after, from) && occupied(after, to);
+
+    let (from, to) = if moved(a, b) {
+        (a, b)
+    } else if moved(b, a) {
+        (b, a)
+    } else {
+        return None;
+    };
+
+    let piece = before[from.0][from.1];
+    let promotion = piece.eq_ignore_ascii_case(&'p') && (to.0 == 0 || to.0 == 7);
+    let mv = format!(
+        "{}{}{}",
+        coords_to_position(from.0, from.1, player_color),
+        coords_to_position(to.0, to.1, player_color),
+        if promotion { "q" } else { "" }
+    );
+
+    let white_view = match player_color {
+        Color::White => *before,
+        Color::Black => rotate_board(before),
+    };
+    if !super::san::is_legal(&white_view, &mv, &piece_color(piece)?) {
+        return None;
+    }
+    let applied = apply_move(before, &mv, player_color)?;
+    let mismatched = (0..64)
+        .filter(|i| applied[i / 8][i % 8] != after[i / 8][i % 8])
+        .count();
+    (mismatched <= MAX_MISMATCHED_SQUARES).then_some(mv)
+}
+
+// Apply move in UCI format to the array board (with castling, en passant and promotion).
+pub fn apply_move(
+    board: &[[char; 8]; 8],
+    uci: &str,
+    player_color: &Color,
+) -> Option<[[char; 8]; 8]> {
+    let ((from_row, from_col), (to_row, to_col)) = move_squares(uci, player_color)?;
+    let piece = board[from_row][from_col];
+    if piece == ' ' {
+        return None;
+    }
+
+    let mut after = *board;
+    after[from_row][from_col] = ' ';
+    after[to_row][to_col] = match uci.chars().nth(4) {
+        Some(promoted) if piece.is_ascii_uppercase() => promoted.to_ascii_uppercase(),
+        Some(promoted) => promoted.to_ascii_lowercase(),
+        None => piece,
+    };
+
+    if piece.eq_ignore_ascii_case(&'k') && from_col.abs_diff(to_col) == 2 {
+        let (rook_from, rook_to) = if to_col > from_col {
+            (7, to_col - 1)
+        } else {
+            (0, to_col + 1)
+        };
+        after[to_row][rook_to] = after[to_row][rook_from];
+        after[to_row][rook_from] = ' ';
+    }
+    if piece.eq_ignore_ascii_case(&'p') && from_col != to_col && board[to_row][to_col] == ' ' {
+        after[from_row][to_col] = ' ';
+    }
+    Some(after)
+}
+
+// Side to move based on the highlighted squares of the last move: the piece standing on
+// one of them has just moved, so it's the turn of the other side.
+pub fn side_to_move_after(board: &[[char; 8]; 8], squares: &[(usize, usize)]) -> Option<Color> {
+    let mut colors = squares
+        .iter()
+        .filter_map(|&(row, col)| piece_color(board[row][col]));
+    let mover = colors.next()?;
+    if colors.any(|color| color != mover) {
+        return None;
+    }
+    Some(match mover {
+        Color::White => Color::Black,
+        Color::Black => Color::White,
+    })
+}
+
 #[derive(Debug)]
 pub struct DiffSquare {
     row: usize,
@@ -310,12 +563,7 @@ pub fn detect_move(
 mod tests {
     use super::*;
     use crate::utils::printer::DefaultPrinter;
-    use rstest::{fixture, rstest};
-
-    #[fixture]
-    fn empty_board() -> [[char; 8]; 8] {
-        [[' '; 8]; 8]
-    }
+    use rstest::rstest;
 
     #[rstest]
     #[case(Color::White,[
@@ -343,23 +591,6 @@ mod tests {
         assert_eq!(*board.raw(), result);
     }
 
-    #[rstest]
-    #[case((0,0),(800,800),(0,0))] //top left piece
-    #[case((0,315),(360,360),(0,7))] //top right piece
-    #[case((315,0),(360,360),(7,0))] //bottom left piece
-    #[case((700,700),(800,800),(7,7))] //bottom right piece
-    #[case((180,135),(360,360),(4,3))] //d4 piece
-
-    fn register_piece_correct_insert(
-        #[case] point: (i32, i32),
-        #[case] board_width_height: (i32, i32),
-        #[case] result_row_col: (usize, usize),
-        mut empty_board: [[char; 8]; 8],
-    ) {
-        assert!(register_piece(point, board_width_height, 'X', &mut empty_board).is_ok());
-        assert_eq!(empty_board[result_row_col.0][result_row_col.1], 'X');
-    }
-
     #[rstest]
     #[case(0,0,"a8".to_string(), Color::White)]
     #[case(0, 7,"h8".to_string(), Color::White)]
@@ -736,6 +967,189 @@ mod tests {
         assert_eq!(move_type, MoveType::Castle);
     }
 
+    #[rstest]
+    fn board_from_fen_initial_position() {
+        let board =
+            board_from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+
+        assert_eq!(
+            board,
+            Board::<DefaultPrinter, WhiteView>::default_white().raw
+        );
+    }
+
+    #[rstest]
+    fn board_from_fen_mid_game() {
+        let board = board_from_fen("r3k2r/8/2n5/3pP3/8/5N2/8/R3K2R").unwrap();
+
+        assert_eq!(board[0], ['r', ' ', ' ', ' ', 'k', ' ', ' ', 'r']);
+        assert_eq!(board[2][2], 'n');
+        assert_eq!(board[3][3], 'p');
+        assert_eq!(board[3][4], 'P');
+        assert_eq!(board[5][5], 'N');
+        assert_eq!(board[7], ['R', ' ', ' ', ' ', 'K', ' ', ' ', 'R']);
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP")]
+    #[case("rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR")]
+    #[case("rnbqkbnr/ppppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR")]
+    #[case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNX")]
+    fn board_from_fen_invalid(#[case] fen: &str) {
+        assert!(matches!(
+            board_from_fen(fen),
+            Err(CheatessError::InvalidFen(_))
+        ));
+    }
+
+    #[rstest]
+    #[case(
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        Color::White,
+        "KQkq",
+        "-"
+    )]
+    #[case(
+        "r3k2r/8/2n5/3pP3/8/5N2/8/R3K2R w KQkq d6 0 1",
+        Color::White,
+        "KQkq",
+        "d6"
+    )]
+    #[case("4k3/8/8/8/8/8/8/4K2R b K - 0 1", Color::Black, "K", "-")]
+    fn board_to_fen_round_trip(
+        #[case] fen: &str,
+        #[case] side: Color,
+        #[case] castling: &str,
+        #[case] en_passant: &str,
+    ) {
+        let setup = PositionSetup {
+            castling: castling.to_string(),
+            en_passant: en_passant.to_string(),
+        };
+        let board = board_from_fen(fen).unwrap();
+
+        assert_eq!(board_to_fen(&board, &side, &setup), fen);
+    }
+
+    #[rstest]
+    #[case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR", "KQkq")]
+    #[case("r3k3/8/8/8/8/8/8/R3K2R", "KQq")]
+    #[case("4k2r/8/8/8/8/8/8/R4K1R", "k")]
+    #[case("3k4/8/8/8/8/8/8/4K3", "-")]
+    fn infer_castling_from_position(#[case] fen: &str, #[case] expected: &str) {
+        assert_eq!(infer_castling(&board_from_fen(fen).unwrap()), expected);
+    }
+
+    #[rstest]
+    #[case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR", Ok(()))]
+    #[case("rnbq1bnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR", Err(()))]
+    #[case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKKNR", Err(()))]
+    #[case("rnbqkbnP/pppppppp/8/8/8/8/PPPPPPP1/RNBQKBNR", Err(()))]
+    fn validate_recognized_position(#[case] fen: &str, #[case] expected: Result<(), ()>) {
+        let board = board_from_fen(fen).unwrap();
+
+        assert_eq!(validate_position(&board).map_err(|_| ()), expected);
+    }
+
+    #[rstest]
+    #[case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR", "e2e4", Color::White)]
+    #[case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR", "e7e5", Color::Black)]
+    // en passant empties the square of the captured pawn, which comes first in scan order
+    #[case("4k3/8/8/3pP3/8/8/8/4K3", "e5d6", Color::White)]
+    #[case("4k3/8/8/8/3pP3/8/8/4K3", "d4e3", Color::Black)]
+    fn mover_color_of_move(#[case] before: &str, #[case] mv: &str, #[case] expected: Color) {
+        let before = board_from_fen(before).unwrap();
+
+        assert_eq!(mover_color(&before, mv, &Color::White), Some(expected));
+        assert_eq!(
+            mover_color(&rotate_board(&before), mv, &Color::Black),
+            Some(expected)
+        );
+    }
+
+    #[rstest]
+    #[case("e2e4", Color::White, ((6, 4), (4, 4)))]
+    #[case("e2e4", Color::Black, ((1, 3), (3, 3)))]
+    #[case("a7a8q", Color::White, ((1, 0), (0, 0)))]
+    fn move_squares_of_uci(
+        #[case] uci: &str,
+        #[case] player_color: Color,
+        #[case] expected: ((usize, usize), (usize, usize)),
+    ) {
+        assert_eq!(move_squares(uci, &player_color), Some(expected));
+    }
+
+    #[rstest]
+    #[case(
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR",
+        "e2e4",
+        "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR"
+    )]
+    #[case("r3k2r/8/8/8/8/8/8/R3K2R", "e1g1", "r3k2r/8/8/8/8/8/8/R4RK1")]
+    #[case("r3k2r/8/8/8/8/8/8/R3K2R", "e8c8", "2kr3r/8/8/8/8/8/8/R3K2R")]
+    #[case("4k3/8/8/3pP3/8/8/8/4K3", "e5d6", "4k3/8/3P4/8/8/8/8/4K3")]
+    #[case("4k3/P7/8/8/8/8/8/4K3", "a7a8q", "Q3k3/8/8/8/8/8/8/4K3")]
+    fn apply_move_to_board(#[case] before: &str, #[case] uci: &str, #[case] after: &str) {
+        let before = board_from_fen(before).unwrap();
+        let after = board_from_fen(after).unwrap();
+
+        assert_eq!(apply_move(&before, uci, &Color::White), Some(after));
+        assert_eq!(
+            apply_move(&rotate_board(&before), uci, &Color::Black),
+            Some(rotate_board(&after))
+        );
+    }
+
+    #[rstest]
+    #[case("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR", &[(6, 4), (4, 4)], Some("e2e4"))]
+    #[case("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR", &[(4, 4), (6, 4)], Some("e2e4"))]
+    // misrecognized piece on the target square
+    #[case("rnbqkbnr/pppppppp/8/8/4N3/8/PPPP1PPP/RNBQKBNR", &[(6, 4), (4, 4)], Some("e2e4"))]
+    #[case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR", &[(4, 4)], None)]
+    // target square is empty after the move
+    #[case("rnbqkbnr/pppppppp/8/8/8/8/PPPP1PPP/RNBQKBNR", &[(6, 4), (4, 4)], None)]
+    // the bishop can't jump over the pawn
+    #[case("rnbqkbnr/pppppppp/8/8/2B5/8/PPPPPPPP/RNBQK1NR", &[(7, 5), (4, 2)], None)]
+    // recognized position differs on most squares
+    #[case("8/8/pppppppp/pppppppp/ppppPppp/pppppppp/8/8", &[(6, 4), (4, 4)], None)]
+    // noisy recognition: three squares besides the move are misread
+    #[case("rnbqkbnr/pp1ppppp/8/2N5/4P3/8/PPPP1PPP/RNBQKB1R", &[(6, 4), (4, 4)], None)]
+    fn move_from_highlighted_squares(
+        #[case] after: &str,
+        #[case] squares: &[(usize, usize)],
+        #[case] expected: Option<&str>,
+    ) {
+        let before = Board::<DefaultPrinter, WhiteView>::default_white().raw;
+        let after = board_from_fen(after).unwrap();
+
+        assert_eq!(
+            move_from_squares(&before, &after, squares, &Color::White).as_deref(),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case(&[(6, 4), (4, 4)], Some(Color::Black))]
+    #[case(&[(2, 4), (3, 5)], None)]
+    fn side_to_move_after_highlighted_move(
+        #[case] squares: &[(usize, usize)],
+        #[case] expected: Option<Color>,
+    ) {
+        let board = board_from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR").unwrap();
+
+        assert_eq!(side_to_move_after(&board, squares), expected);
+    }
+
+    #[rstest]
+    fn rotate_board_changes_point_of_view() {
+        let white = Board::<DefaultPrinter, WhiteView>::default_white().raw;
+        let black = Board::<DefaultPrinter, BlackView>::default_black().raw;
+
+        assert_eq!(rotate_board(&white), black);
+        assert_eq!(rotate_board(&black), white);
+    }
+
     #[rstest]
     fn show_board_with_pieces() {
         let mut buf = Vec::new();
@@ -0,0 +1,268 @@
+// Position of squares inside the cropped board image. Boards drawn with a frame
+// or coordinates around them don't start at the border of the image, so the
+// grid lines are detected instead of dividing the image by eight.
+use crate::utils::error::CheatessResult;
+use opencv::core::{Mat, Rect};
+use opencv::prelude::*;
+
+/// Size of the square (in pixels) of the normalized board. Boards are resized to it before
+/// recognition, so templates don't depend on the size of the board on the screen.
+pub const CANONICAL_SQUARE: i32 = 64;
+
+/// Minimal contrast of a grid line (between two neighbouring squares).
+const MIN_LINE_CONTRAST: f64 = 10.0;
+/// Maximal difference between the sizes of squares and their average size.
+const MAX_SQUARE_DEVIATION: f64 = 0.2;
+/// Smaller images (in pixels) can't hold a grid of 8 squares, so it isn't searched for.
+const MIN_GRID_SIZE: usize = 16;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoardGeometry {
+    /// Positions of 9 vertical grid lines (left edges of columns and the right edge of the board).
+    xs: [i32; 9],
+    /// Positions of 9 horizontal grid lines (top edges of rows and the bottom edge of the board).
+    ys: [i32; 9],
+}
+
+impl BoardGeometry {
+    /// Geometry of the board which fills the whole image with 8x8 equal squares.
+    pub fn uniform(width: i32, height: i32) -> Self {
+        let lines =
+            |size: i32| std::array::from_fn(|i| (i as f64 * size as f64 / 8.0).round() as i32);
+        BoardGeometry {
+            xs: lines(width),
+            ys: lines(height),
+        }
+    }
+
+    /// Geometry of the normalized board (see `CANONICAL_SQUARE`).
+    pub fn canonical() -> Self {
+        BoardGeometry::uniform(8 * CANONICAL_SQUARE, 8 * CANONICAL_SQUARE)
+    }
+
+    /// Detects grid lines in the (gray) board image. Falls back to the uniform geometry
+    /// when the grid can't be found.
+    pub fn detect(gray_board: &Mat) -> CheatessResult<Self> {
+        let (width, height) = (gray_board.cols(), gray_board.rows());
+        if width.min(height) < MIN_GRID_SIZE as i32 {
+            return Ok(BoardGeometry::uniform(width, height));
+        }
+        let (vertical, horizontal) = line_profiles(gray_board)?;
+
+        match (find_grid(&vertical), find_grid(&horizontal)) {
+            (Some(xs), Some(ys)) => {
+                let geometry = BoardGeometry { xs, ys };
+                log::debug!("Detected board grid: {geometry:?}");
+                Ok(geometry)
+            }
+            _ => {
+                log::debug!("Board grid not found, squares are assumed to fill the image");
+                Ok(BoardGeometry::uniform(width, height))
+            }
+        }
+    }
+
+    /// Rectangle of the square in the given row and column (as seen on the image).
+    pub fn square(&self, row: usize, col: usize) -> Rect {
+        Rect::new(
+            self.xs[col],
+            self.ys[row],
+            self.xs[col + 1] - self.xs[col],
+            self.ys[row + 1] - self.ys[row],
+        )
+    }
+
+    /// Returns row and column of the square which contains the point.
+    pub fn square_at(&self, x: i32, y: i32) -> Option<(usize, usize)> {
+        let index = |lines: &[i32; 9], v: i32| (0..8).find(|&i| lines[i] <= v && v < lines[i + 1]);
+        Some((index(&self.ys, y)?, index(&self.xs, x)?))
+    }
+
+    /// Region of the image covered by squares (without frame and coordinates).
+    pub fn bounds(&self) -> Rect {
+        Rect::new(
+            self.xs[0],
+            self.ys[0],
+            self.xs[8] - self.xs[0],
+            self.ys[8] - self.ys[0],
+        )
+    }
+
+    /// Average size (width, height) of the square.
+    pub fn square_size(&self) -> (i32, i32) {
+        ((self.xs[8] - self.xs[0]) / 8, (self.ys[8] - self.ys[0]) / 8)
+    }
+}
+
+/// Computes strength of vertical and horizontal lines at every column and row of the image.
+/// Strength is the lower quartile of the brightness differences along the line, so only
+/// lines crossing the whole board (borders of squares) are strong, unlike edges of pieces.
+fn line_profiles(gray: &Mat) -> CheatessResult<(Vec<f64>, Vec<f64>)> {
+    let (width, height) = (gray.cols() as usize, gray.rows() as usize);
+    let gray = gray.try_clone()?;
+    let data = gray.data_bytes()?;
+    let pixel = |x: usize, y: usize| data[y * width + x] as i32;
+
+    let lower_quartile = |mut values: Vec<i32>| {
+        if values.is_empty() {
+            return 0.0;
+        }
+        let index = values.len() / 4;
+        *values.select_nth_unstable(index).1 as f64
+    };
+
+    let mut vertical = vec![0.0; width];
+    for (x, strength) in vertical.iter_mut().enumerate().skip(1) {
+        let diffs = (0..height)
+            .map(|y| (pixel(x, y) - pixel(x - 1, y)).abs())
+            .collect();
+        *strength = lower_quartile(diffs);
+    }
+
+    let mut horizontal = vec![0.0; height];
+    for (y, strength) in horizontal.iter_mut().enumerate().skip(1) {
+        let diffs = (0..width)
+            .map(|x| (pixel(x, y) - pixel(x, y - 1)).abs())
+            .collect();
+        *strength = lower_quartile(diffs);
+    }
+
+    Ok((vertical, horizontal))
+}
+
+/// Finds 9 equally spaced lines in the profile. Board has to take at least 80% of the image.
+/// Only 7 inner lines are searched, because borders of the board without a frame have no edge.
+fn find_grid(profile: &[f64]) -> Option<[i32; 9]> {
+    if profile.len() < MIN_GRID_SIZE {
+        return None;
+    }
+    let len = profile.len() as f64;
+    let strength = |pos: f64| {
+        let pos = pos.round() as usize;
+        profile[pos.saturating_sub(1)..(pos + 2).min(profile.len())]
+            .iter()
+            .fold(0.0, |acc: f64, v| acc.max(*v))
+    };
+
+    let mut best: Option<(f64, f64, f64)> = None;
+    let mut square = len / 10.0;
+    while square <= len / 8.0 {
+        let mut offset = 0.0;
+        while offset + 8.0 * square <= len {
+            let score: f64 = (1..8).map(|k| strength(offset + k as f64 * square)).sum();
+            if best.is_none_or(|(best_score, _, _)| score > best_score) {
+                best = Some((score, square, offset));
+            }
+            offset += 1.0;
+        }
+        square += 0.125;
+    }
+    let (_, square, offset) = best?;
+
+    // snap inner lines to the strongest edge nearby
+    let mut lines = [0i32; 9];
+    for (k, line) in lines.iter_mut().enumerate().take(8).skip(1) {
+        let expected = (offset + k as f64 * square).round() as usize;
+        let from = expected.saturating_sub(1);
+        let to = (expected + 1).min(profile.len() - 1);
+        *line = (from..=to)
+            .max_by(|a, b| profile[*a].total_cmp(&profile[*b]))
+            .unwrap_or(expected) as i32;
+        if profile[*line as usize] < MIN_LINE_CONTRAST {
+            return None;
+        }
+    }
+
+    let average = (lines[7] - lines[1]) as f64 / 6.0;
+    lines[0] = (lines[1] - average.round() as i32).max(0);
+    lines[8] = (lines[7] + average.round() as i32).min(profile.len() as i32);
+
+    let regular = lines
+        .windows(2)
+        .all(|pair| ((pair[1] - pair[0]) as f64 - average).abs() <= average * MAX_SQUARE_DEVIATION);
+    regular.then_some(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::render::{RenderOptions, SpriteSet, Theme};
+    use crate::core::testing::render_gray;
+    use crate::core::tuning::INITIAL_PLACEMENT;
+    use rstest::rstest;
+
+    #[rstest]
+    fn uniform_geometry() {
+        let geometry = BoardGeometry::uniform(512, 500);
+
+        assert_eq!(geometry.square(0, 0), Rect::new(0, 0, 64, 63));
+        assert_eq!(geometry.square(7, 7), Rect::new(448, 438, 64, 62));
+        assert_eq!(geometry.square_at(70, 499), Some((7, 1)));
+        assert_eq!(geometry.square_at(512, 0), None);
+        assert_eq!(geometry.bounds(), Rect::new(0, 0, 512, 500));
+    }
+
+    #[rstest]
+    #[case(Theme::BROWN, 0)]
+    #[case(Theme::GREEN, 24)]
+    #[case(Theme::BLUE, 40)]
+    fn detect_grid_on_rendered_board(#[case] theme: Theme, #[case] border: u32) {
+        let options = RenderOptions {
+            theme,
+            border,
+            ..Default::default()
+        };
+        let (gray, _) = render_gray(INITIAL_PLACEMENT, &SpriteSet::geometric(64), &options);
+
+        let geometry = BoardGeometry::detect(&gray).unwrap();
+        let border = border as i32;
+        for (row, col) in [(0, 0), (3, 5), (7, 7)] {
+            let square = geometry.square(row, col);
+            assert!(
+                (square.x - (border + col as i32 * 64)).abs() <= 1,
+                "{geometry:?}"
+            );
+            assert!(
+                (square.y - (border + row as i32 * 64)).abs() <= 1,
+                "{geometry:?}"
+            );
+            assert!((square.width - 64).abs() <= 1, "{geometry:?}");
+        }
+    }
+
+    #[rstest]
+    fn detect_grid_falls_back_to_uniform() {
+        let gray = Mat::new_rows_cols_with_default(
+            256,
+            256,
+            opencv::core::CV_8UC1,
+            opencv::core::Scalar::all(120.0),
+        )
+        .unwrap();
+
+        assert_eq!(
+            BoardGeometry::detect(&gray).unwrap(),
+            BoardGeometry::uniform(256, 256)
+        );
+    }
+
+    #[rstest]
+    #[case(0, 0)]
+    #[case(5, 5)]
+    #[case(3, 40)]
+    fn detect_grid_on_tiny_image(#[case] rows: i32, #[case] cols: i32) {
+        let gray = Mat::new_rows_cols_with_default(
+            rows,
+            cols,
+            opencv::core::CV_8UC1,
+            opencv::core::Scalar::all(120.0),
+        )
+        .unwrap();
+
+        assert_eq!(
+            BoardGeometry::detect(&gray).unwrap(),
+            BoardGeometry::uniform(gray.cols(), gray.rows())
+        );
+        assert_eq!(find_grid(&[]), None);
+    }
+}
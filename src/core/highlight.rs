@@ -0,0 +1,158 @@
+// Detection of squares tinted by the website: source and target squares of the last
+// move and the square of the king in check. Works on the coloured board, because the
+// tint is often lost after conversion to grayscale.
+use super::geometry::BoardGeometry;
+use super::procimg::Mat;
+use crate::utils::error::CheatessResult;
+use opencv::{core::Rect, prelude::*};
+
+/// Minimal distance (in RGB) between the colour of the square and the colour of other
+/// squares of the same colour to treat it as highlighted.
+const MIN_TINT_DISTANCE: f64 = 30.0;
+/// More highlighted squares mean something else is drawn on the board (arrows, hover, animation).
+const MAX_HIGHLIGHTED: usize = 4;
+/// Minimal drop of the green channel (and its difference to red) of the square in check.
+const MIN_CHECK_SHIFT: f64 = 40.0;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Highlights {
+    /// Squares (row, col as seen on the image) tinted as the last move.
+    pub last_move: Vec<(usize, usize)>,
+    /// Square of the king in check.
+    pub check: Option<(usize, usize)>,
+}
+
+/// Finds highlighted squares on the coloured (BGR) board. Background of every square is
+/// sampled in its corners (pieces rarely cover them) and compared with the typical colour
+/// of squares of the same colour.
+pub fn detect_highlights(
+    color_board: &Mat,
+    geometry: &BoardGeometry,
+) -> CheatessResult<Highlights> {
+    let mut samples = [[[0.0; 3]; 8]; 8];
+    for (row, line) in samples.iter_mut().enumerate() {
+        for (col, sample) in line.iter_mut().enumerate() {
+            *sample = square_background(color_board, &geometry.square(row, col))?;
+        }
+    }
+
+    let mut highlights = Highlights::default();
+    for parity in 0..2 {
+        let squares = (0..64)
+            .map(|i| (i / 8, i % 8))
+            .filter(|(row, col)| (row + col) % 2 == parity)
+            .collect::<Vec<_>>();
+        let base: [f64; 3] = std::array::from_fn(|channel| {
+            median(
+                squares
+                    .iter()
+                    .map(|&(r, c)| samples[r][c][channel])
+                    .collect(),
+            )
+        });
+
+        for (row, col) in squares {
+            let sample = samples[row][col];
+            let shift: [f64; 3] = std::array::from_fn(|channel| sample[channel] - base[channel]);
+            let distance = shift.iter().map(|v| v * v).sum::<f64>().sqrt();
+            if distance < MIN_TINT_DISTANCE {
+                continue;
+            }
+
+            // channels are in BGR order
+            let (green, red) = (shift[1], shift[2]);
+            if -green >= MIN_CHECK_SHIFT && red - green >= MIN_CHECK_SHIFT {
+                highlights.check = Some((row, col));
+            } else {
+                highlights.last_move.push((row, col));
+            }
+        }
+    }
+    highlights.last_move.sort();
+
+    if highlights.last_move.len() + highlights.check.iter().count() > MAX_HIGHLIGHTED {
+        log::debug!("Too many highlighted squares, ignoring them: {highlights:?}");
+        return Ok(Highlights::default());
+    }
+    Ok(highlights)
+}
+
+/// Median (per channel) of average colours of four small patches in corners of the square.
+pub fn square_background(color_board: &Mat, square: &Rect) -> CheatessResult<[f64; 3]> {
+    let (w, h) = ((square.width / 8).max(1), (square.height / 8).max(1));
+    let (dx, dy) = (square.width / 16, square.height / 16);
+    let corners = [
+        (square.x + dx, square.y + dy),
+        (square.x + square.width - dx - w, square.y + dy),
+        (square.x + dx, square.y + square.height - dy - h),
+        (
+            square.x + square.width - dx - w,
+            square.y + square.height - dy - h,
+        ),
+    ];
+
+    let mut patches = vec![];
+    for (x, y) in corners {
+        let patch = Mat::roi(color_board, Rect::new(x, y, w, h))?;
+        patches.push(opencv::core::mean(&patch, &Mat::default())?);
+    }
+    Ok(std::array::from_fn(|channel| {
+        median(patches.iter().map(|p| p[channel]).collect())
+    }))
+}
+
+pub fn median(mut values: Vec<f64>) -> f64 {
+    values.sort_by(|a, b| a.total_cmp(b));
+    let mid = values.len() / 2;
+    if values.len() % 2 == 0 {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::procimg::image_buffer_to_color_mat;
+    use crate::core::render::{render_board, RenderOptions, SpriteSet, Theme};
+    use rstest::rstest;
+
+    fn render_color(fen: &str, options: &RenderOptions) -> Mat {
+        let (image, _) = render_board(fen, &SpriteSet::geometric(64), options).unwrap();
+        image_buffer_to_color_mat(image).unwrap()
+    }
+
+    #[rstest]
+    #[case(Theme::BROWN)]
+    #[case(Theme::GREEN)]
+    #[case(Theme::BLUE)]
+    fn detect_last_move_and_check(#[case] theme: Theme) {
+        let options = RenderOptions {
+            theme,
+            noise: 4,
+            highlights: vec![(6, 5), (4, 7)],
+            check: Some((0, 4)),
+            ..Default::default()
+        };
+        let board = render_color("rnbqkbnr/ppppp1pp/8/5p1Q/8/4P3/PPPP1PPP/RNB1KBNR", &options);
+
+        let highlights = detect_highlights(&board, &BoardGeometry::canonical()).unwrap();
+        assert_eq!(highlights.last_move, vec![(4, 7), (6, 5)]);
+        assert_eq!(highlights.check, Some((0, 4)));
+    }
+
+    #[rstest]
+    fn no_highlights_on_plain_board() {
+        let options = RenderOptions {
+            noise: 4,
+            ..Default::default()
+        };
+        let board = render_color("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR", &options);
+
+        assert_eq!(
+            detect_highlights(&board, &BoardGeometry::canonical()).unwrap(),
+            Highlights::default()
+        );
+    }
+}
@@ -0,0 +1,187 @@
+// Record of moves detected during a session, together with engine evaluations.
+// Used to print the reconstructed game when analysis is finished.
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MoveRecord {
+    pub uci: String,
+    /// Stockfish evaluation (from white's perspective) after the move.
+    pub eval: String,
+    /// Position of the frame in the analyzed recording (if source provides it).
+    pub timestamp: Option<Duration>,
+    /// Time left on the clock of the side which made the move (if clocks are read).
+    pub clock: Option<Duration>,
+}
+
+#[derive(Debug, Default)]
+pub struct GameHistory {
+    /// PGN headers (tag pairs), e.g. names of players.
+    headers: Vec<(String, String)>,
+    moves: Vec<MoveRecord>,
+}
+
+impl GameHistory {
+    pub fn push(&mut self, record: MoveRecord) {
+        self.moves.push(record);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.moves.is_empty()
+    }
+
+    /// Sets the PGN header, replacing its previous value.
+    pub fn set_header(&mut self, name: &str, value: &str) {
+        match self.headers.iter_mut().find(|(header, _)| header == name) {
+            Some((_, old)) => *old = value.to_string(),
+            None => self.headers.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    /// Returns moves in numbered pairs with evaluation (and timestamp) comments, e.g.
+    /// `1. e2e4 {0.3} e7e5 {0.25}`. Read clocks are added as PGN `%clk` commands and
+    /// headers are put before the moves. Moves without evaluation (made before the
+    /// analysis started) have no comment.
+    pub fn annotated(&self) -> String {
+        let moves = self
+            .moves
+            .chunks(2)
+            .enumerate()
+            .map(|(i, chunk)| {
+                let moves = chunk
+                    .iter()
+                    .map(format_record)
+                    .collect::<Vec<String>>()
+                    .join(" ");
+                format!("{}. {moves}", i + 1)
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        if self.headers.is_empty() {
+            return moves;
+        }
+
+        let headers = self
+            .headers
+            .iter()
+            .map(|(name, value)| format!("[{name} \"{value}\"]"))
+            .collect::<Vec<String>>()
+            .join("\n");
+        format!("{headers}\n\n{moves}")
+    }
+}
+
+fn format_record(record: &MoveRecord) -> String {
+    let clock = record
+        .clock
+        .map(|clock| format!(" [%clk {}]", format_clock(clock)))
+        .unwrap_or_default();
+    match record.timestamp {
+        None if record.eval.is_empty() && clock.is_empty() => record.uci.clone(),
+        Some(ts) => format!(
+            "{} {{{}, {}{clock}}}",
+            record.uci,
+            record.eval,
+            format_timestamp(ts)
+        ),
+        None => format!("{} {{{}{clock}}}", record.uci, record.eval),
+    }
+}
+
+/// Formats the clock as `H:MM:SS`, with tenths of a second when they are known.
+fn format_clock(clock: Duration) -> String {
+    let secs = clock.as_secs();
+    let tenths = clock.subsec_millis() / 100;
+    let time = format!("{}:{:02}:{:02}", secs / 3600, (secs / 60) % 60, secs % 60);
+    if tenths > 0 {
+        format!("{time}.{tenths}")
+    } else {
+        time
+    }
+}
+
+fn format_timestamp(ts: Duration) -> String {
+    let secs = ts.as_secs();
+    format!(
+        "{:02}:{:02}:{:02}",
+        secs / 3600,
+        (secs / 60) % 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn record(uci: &str, eval: &str, timestamp: Option<u64>) -> MoveRecord {
+        MoveRecord {
+            uci: uci.to_string(),
+            eval: eval.to_string(),
+            timestamp: timestamp.map(Duration::from_secs),
+            clock: None,
+        }
+    }
+
+    #[rstest]
+    fn annotated_history_groups_moves_in_pairs() {
+        let mut history = GameHistory::default();
+        history.push(record("e2e4", "0.3", None));
+        history.push(record("e7e5", "0.25", None));
+        history.push(record("g1f3", "0.4", None));
+
+        assert_eq!(
+            history.annotated(),
+            "1. e2e4 {0.3} e7e5 {0.25}\n2. g1f3 {0.4}"
+        );
+    }
+
+    #[rstest]
+    fn annotated_history_contains_timestamps() {
+        let mut history = GameHistory::default();
+        history.push(record("d2d4", "0.2", Some(3725)));
+
+        assert_eq!(history.annotated(), "1. d2d4 {0.2, 01:02:05}");
+    }
+
+    #[rstest]
+    fn annotated_history_contains_clocks() {
+        let mut history = GameHistory::default();
+        history.push(MoveRecord {
+            clock: Some(Duration::from_secs(299)),
+            ..record("e2e4", "0.3", None)
+        });
+        history.push(MoveRecord {
+            clock: Some(Duration::from_millis(9_700)),
+            ..record("e7e5", "0.25", Some(3725))
+        });
+
+        assert_eq!(
+            history.annotated(),
+            "1. e2e4 {0.3 [%clk 0:04:59]} e7e5 {0.25, 01:02:05 [%clk 0:00:09.7]}"
+        );
+    }
+
+    #[rstest]
+    fn annotated_history_with_headers_and_synced_moves() {
+        let mut history = GameHistory::default();
+        history.set_header("White", "Magnus");
+        history.set_header("Black", "hikaru");
+        history.set_header("White", "Carlsen");
+        history.push(record("e2e4", "", None));
+        history.push(record("e7e5", "0.25", None));
+
+        assert_eq!(
+            history.annotated(),
+            "[White \"Carlsen\"]\n[Black \"hikaru\"]\n\n1. e2e4 e7e5 {0.25}"
+        );
+    }
+
+    #[rstest]
+    fn empty_history() {
+        let history = GameHistory::default();
+
+        assert!(history.is_empty());
+        assert_eq!(history.annotated(), "");
+    }
+}
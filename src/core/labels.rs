@@ -0,0 +1,266 @@
+// Reading of rank and file labels (1-8, a-h) which most sites draw in the corners of
+// squares at the edge of the board. Labels are drawn with the colour of the other
+// squares, so they are separated from pieces by colour and compared with a small
+// built-in font. Read labels tell the orientation of the board for sure and confirm
+// that squares were detected correctly.
+use super::engine::Color;
+use super::geometry::BoardGeometry;
+use super::highlight::{median, square_background};
+use super::ocr::{closest_glyph, component_cells, components, templates, Cells};
+use super::orientation::Orientation;
+use super::procimg::Mat;
+use crate::utils::error::CheatessResult;
+use opencv::{
+    core::{Rect, Scalar},
+    prelude::*,
+};
+
+/// Part of the square (at every side) searched for a label in its corner.
+const CORNER: f64 = 0.4;
+/// Maximal difference between the brightness of a label and the colour of other squares.
+const COLOR_TOLERANCE: f64 = 12.0;
+/// Smaller spots are noise, not labels.
+const MIN_GLYPH_PIXELS: i32 = 12;
+/// Net number of labels agreeing with one orientation which gives the full confidence.
+const LABELS_FOR_CONFIDENCE: f64 = 6.0;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Labels {
+    /// Labels of columns as seen on the image (left to right).
+    pub files: [Option<char>; 8],
+    /// Labels of rows as seen on the image (top to bottom).
+    pub ranks: [Option<char>; 8],
+}
+
+impl Labels {
+    /// Number of labels which match the expected ones for the player color.
+    fn agreeing(&self, player_color: &Color) -> usize {
+        (0..8)
+            .filter(|&i| self.files[i] == Some(expected_file(i, player_color)))
+            .count()
+            + (0..8)
+                .filter(|&i| self.ranks[i] == Some(expected_rank(i, player_color)))
+                .count()
+    }
+
+    pub fn count(&self) -> usize {
+        self.files.iter().chain(&self.ranks).flatten().count()
+    }
+
+    pub fn orientation(&self) -> Option<Orientation> {
+        let (white, black) = (self.agreeing(&Color::White), self.agreeing(&Color::Black));
+        Orientation::from_signal((white as f64 - black as f64) / LABELS_FOR_CONFIDENCE)
+    }
+
+    /// Checks if (almost) all read labels are at their places for one orientation. When they
+    /// aren't, squares were probably detected wrongly.
+    pub fn match_squares(&self) -> bool {
+        let best = self
+            .agreeing(&Color::White)
+            .max(self.agreeing(&Color::Black));
+        best * 4 >= self.count() * 3
+    }
+}
+
+/// File label of the column (as seen on the image).
+pub fn expected_file(col: usize, player_color: &Color) -> char {
+    match player_color {
+        Color::White => (b'a' + col as u8) as char,
+        Color::Black => (b'h' - col as u8) as char,
+    }
+}
+
+/// Rank label of the row (as seen on the image).
+pub fn expected_rank(row: usize, player_color: &Color) -> char {
+    match player_color {
+        Color::White => (b'8' - row as u8) as char,
+        Color::Black => (b'1' + row as u8) as char,
+    }
+}
+
+/// Reads labels of files in the bottom row and of ranks in the left and right column.
+pub fn read_labels(gray_board: &Mat, geometry: &BoardGeometry) -> CheatessResult<Labels> {
+    let mut backgrounds = [[0.0; 8]; 8];
+    for (row, line) in backgrounds.iter_mut().enumerate() {
+        for (col, background) in line.iter_mut().enumerate() {
+            *background = square_background(gray_board, &geometry.square(row, col))?[0];
+        }
+    }
+    let parity_background = |parity: usize| {
+        median(
+            (0..64)
+                .filter(|i| (i / 8 + i % 8) % 2 == parity)
+                .map(|i| backgrounds[i / 8][i % 8])
+                .collect(),
+        )
+    };
+    let label_colors = [parity_background(1), parity_background(0)];
+    let templates = templates()?;
+
+    let mut labels = Labels::default();
+    for (col, file) in labels.files.iter_mut().enumerate() {
+        let square = geometry.square(7, col);
+        *file = read_corners(
+            gray_board,
+            &[corner(&square, false, true), corner(&square, true, true)],
+            label_colors[(7 + col) % 2],
+            &templates,
+            |c| ('a'..='h').contains(&c),
+        )?;
+    }
+    for (row, rank) in labels.ranks.iter_mut().enumerate() {
+        let (left, right) = (geometry.square(row, 0), geometry.square(row, 7));
+        *rank = read_corners(
+            gray_board,
+            &[corner(&left, false, false)],
+            label_colors[row % 2],
+            &templates,
+            |c| ('1'..='8').contains(&c),
+        )?
+        .or(read_corners(
+            gray_board,
+            &[corner(&right, true, false)],
+            label_colors[(row + 7) % 2],
+            &templates,
+            |c| ('1'..='8').contains(&c),
+        )?);
+    }
+
+    log::debug!("Read board labels: {labels:?}");
+    Ok(labels)
+}
+
+/// Corner of the square where labels are drawn.
+fn corner(square: &Rect, right: bool, bottom: bool) -> Rect {
+    let (w, h) = (
+        (square.width as f64 * CORNER) as i32,
+        (square.height as f64 * CORNER) as i32,
+    );
+    Rect::new(
+        if right {
+            square.x + square.width - w
+        } else {
+            square.x
+        },
+        if bottom {
+            square.y + square.height - h
+        } else {
+            square.y
+        },
+        w,
+        h,
+    )
+}
+
+/// Returns the best glyph (among allowed ones) found in any of the corners.
+fn read_corners(
+    gray_board: &Mat,
+    corners: &[Rect],
+    label_color: f64,
+    templates: &[(char, Cells)],
+    allowed: fn(char) -> bool,
+) -> CheatessResult<Option<char>> {
+    let mut best: Option<(char, u32)> = None;
+    for region in corners {
+        let Some(cells) = glyph_cells(&Mat::roi(gray_board, *region)?, label_color)? else {
+            continue;
+        };
+        if let Some((glyph, distance)) = closest_glyph(&cells, templates, allowed) {
+            if best.is_none_or(|(_, d)| distance < d) {
+                best = Some((glyph, distance));
+            }
+        }
+    }
+    Ok(best.map(|(glyph, _)| glyph))
+}
+
+/// Finds the largest spot of the label colour in the corner and scales it to the glyph grid.
+fn glyph_cells(patch: &Mat, label_color: f64) -> CheatessResult<Option<Cells>> {
+    let mut mask = Mat::default();
+    opencv::core::in_range(
+        patch,
+        &Scalar::all(label_color - COLOR_TOLERANCE),
+        &Scalar::all(label_color + COLOR_TOLERANCE),
+        &mut mask,
+    )?;
+
+    let (labels, spots) = components(&mask)?;
+    // labels are drawn inside the corner, spots reaching its edges are parts of pieces
+    let largest = spots
+        .iter()
+        .filter(|spot| {
+            let bounds = spot.bounds;
+            bounds.x > 0
+                && bounds.y > 0
+                && bounds.x + bounds.width < patch.cols()
+                && bounds.y + bounds.height < patch.rows()
+        })
+        .filter(|spot| spot.area >= MIN_GLYPH_PIXELS)
+        .max_by_key(|spot| spot.area);
+    largest
+        .map(|spot| component_cells(&labels, spot))
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::render::{RenderOptions, SpriteSet, Theme};
+    use crate::core::testing::render_gray;
+    use crate::core::tuning::INITIAL_PLACEMENT;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(Theme::BROWN, Color::White)]
+    #[case(Theme::GREEN, Color::Black)]
+    #[case(Theme::BLUE, Color::White)]
+    fn read_labels_of_rendered_board(#[case] theme: Theme, #[case] player_color: Color) {
+        let options = RenderOptions {
+            theme,
+            player_color,
+            labels: true,
+            noise: 4,
+            ..Default::default()
+        };
+        let (gray, _) = render_gray(INITIAL_PLACEMENT, &SpriteSet::geometric(64), &options);
+        let labels = read_labels(&gray, &BoardGeometry::canonical()).unwrap();
+
+        assert_eq!(
+            labels.files,
+            std::array::from_fn(|col| Some(expected_file(col, &player_color)))
+        );
+        assert_eq!(
+            labels.ranks,
+            std::array::from_fn(|row| Some(expected_rank(row, &player_color)))
+        );
+        assert!(labels.match_squares());
+
+        let orientation = labels.orientation().unwrap();
+        assert_eq!(orientation.player_color, player_color);
+        assert_eq!(orientation.confidence, 1.0);
+    }
+
+    #[rstest]
+    fn no_labels_on_plain_board() {
+        let (gray, _) = render_gray(
+            INITIAL_PLACEMENT,
+            &SpriteSet::geometric(64),
+            &RenderOptions::default(),
+        );
+        let labels = read_labels(&gray, &BoardGeometry::canonical()).unwrap();
+
+        assert_eq!(labels.count(), 0);
+        assert_eq!(labels.orientation(), None);
+    }
+
+    #[rstest]
+    fn labels_shifted_from_squares() {
+        let mut labels = Labels::default();
+        for i in 0..8 {
+            labels.files[i] = Some(expected_file((i + 3) % 8, &Color::White));
+            labels.ranks[i] = Some(expected_rank(i, &Color::White));
+        }
+
+        assert!(!labels.match_squares());
+    }
+}
@@ -1,3 +1,19 @@
+pub mod clock;
 pub mod engine;
+pub mod geometry;
+pub mod highlight;
+pub mod history;
+pub mod labels;
+pub mod ocr;
+pub mod orientation;
 pub mod procimg;
+pub mod recognition;
+pub mod recognizer;
+#[cfg(test)]
+pub mod render;
+pub mod san;
+pub mod sidebar;
 pub mod stockfish;
+#[cfg(test)]
+pub mod testing;
+pub mod tuning;
@@ -0,0 +1,432 @@
+// Small built-in text recognizer for the few things sites print next to the board
+// (board labels, clocks, move list, player names). Glyphs are connected components of
+// a binary image, each one is cropped, scaled to the 5x7 grid and compared with the
+// built-in font.
+use super::procimg::Mat;
+use crate::utils::error::CheatessResult;
+use opencv::{
+    core::{Rect, Scalar, Size, CV_32S, CV_8UC1},
+    imgproc,
+    prelude::*,
+};
+
+pub const GLYPH_WIDTH: usize = 5;
+pub const GLYPH_HEIGHT: usize = 7;
+
+/// Glyph scaled to the grid (`true` for pixels of the text).
+pub type Cells = [[bool; GLYPH_WIDTH]; GLYPH_HEIGHT];
+
+/// Built-in 5x7 font. Capital O is left out, it can't be told from zero.
+#[rustfmt::skip]
+pub static GLYPHS: &[(char, [&str; GLYPH_HEIGHT])] = &[
+    ('0', [".###.", "#...#", "#...#", "#...#", "#...#", "#...#", ".###."]),
+    ('1', ["..#..", ".##..", "..#..", "..#..", "..#..", "..#..", ".###."]),
+    ('2', [".###.", "#...#", "....#", "...#.", "..#..", ".#...", "#####"]),
+    ('3', ["####.", "....#", "....#", ".###.", "....#", "....#", "####."]),
+    ('4', ["...#.", "..##.", ".#.#.", "#..#.", "#####", "...#.", "...#."]),
+    ('5', ["#####", "#....", "####.", "....#", "....#", "#...#", ".###."]),
+    ('6', ["..##.", ".#...", "#....", "####.", "#...#", "#...#", ".###."]),
+    ('7', ["#####", "....#", "...#.", "..#..", ".#...", ".#...", ".#..."]),
+    ('8', [".###.", "#...#", "#...#", ".###.", "#...#", "#...#", ".###."]),
+    ('9', [".###.", "#...#", "#...#", ".####", "....#", "...#.", ".##.."]),
+    ('a', [".....", ".....", ".###.", "....#", ".####", "#...#", ".####"]),
+    ('b', ["#....", "#....", "####.", "#...#", "#...#", "#...#", "####."]),
+    ('c', [".....", ".....", ".###.", "#....", "#....", "#....", ".###."]),
+    ('d', ["....#", "....#", ".####", "#...#", "#...#", "#...#", ".####"]),
+    ('e', [".....", ".....", ".###.", "#...#", "#####", "#....", ".###."]),
+    ('f', ["..##.", ".#...", "####.", ".#...", ".#...", ".#...", ".#..."]),
+    ('g', [".....", ".####", "#...#", "#...#", ".####", "....#", ".###."]),
+    ('h', ["#....", "#....", "####.", "#...#", "#...#", "#...#", "#...#"]),
+    ('i', ["..#..", ".....", ".##..", "..#..", "..#..", "..#..", ".###."]),
+    ('j', ["...#.", ".....", "..##.", "...#.", "...#.", "#..#.", ".##.."]),
+    ('k', ["#....", "#....", "#..#.", "#.#..", "##...", "#.#..", "#..#."]),
+    ('l', [".#...", ".#...", ".#...", ".#...", ".#...", ".#...", "..##."]),
+    ('m', [".....", ".....", "##.#.", "#.#.#", "#.#.#", "#...#", "#...#"]),
+    ('n', [".....", ".....", "#.##.", "##..#", "#...#", "#...#", "#...#"]),
+    ('o', [".....", ".....", ".###.", "#...#", "#...#", "#...#", ".###."]),
+    ('p', [".....", ".....", "####.", "#...#", "####.", "#....", "#...."]),
+    ('q', [".....", ".....", ".####", "#...#", ".####", "....#", "....#"]),
+    ('r', [".....", ".....", "#.##.", "##..#", "#....", "#....", "#...."]),
+    ('s', [".....", ".....", ".####", "#....", ".###.", "....#", "####."]),
+    ('t', [".#...", ".#...", "####.", ".#...", ".#...", ".#..#", "..##."]),
+    ('u', [".....", ".....", "#...#", "#...#", "#...#", "#..##", ".##.#"]),
+    ('v', [".....", ".....", "#...#", "#...#", "#...#", ".#.#.", "..#.."]),
+    ('w', [".....", ".....", "#...#", "#...#", "#.#.#", "#.#.#", ".#.#."]),
+    ('x', [".....", ".....", "#...#", ".#.#.", "..#..", ".#.#.", "#...#"]),
+    ('y', [".....", ".....", "#...#", "#...#", ".####", "....#", ".###."]),
+    ('z', [".....", ".....", "#####", "...#.", "..#..", ".#...", "#####"]),
+    ('A', [".###.", "#...#", "#...#", "#####", "#...#", "#...#", "#...#"]),
+    ('B', ["####.", "#...#", "#...#", "####.", "#...#", "#...#", "####."]),
+    ('C', [".###.", "#...#", "#....", "#....", "#....", "#...#", ".###."]),
+    ('D', ["####.", "#...#", "#...#", "#...#", "#...#", "#...#", "####."]),
+    ('E', ["#####", "#....", "#....", "####.", "#....", "#....", "#####"]),
+    ('F', ["#####", "#....", "#....", "####.", "#....", "#....", "#...."]),
+    ('G', [".###.", "#...#", "#....", "#.###", "#...#", "#...#", ".####"]),
+    ('H', ["#...#", "#...#", "#...#", "#####", "#...#", "#...#", "#...#"]),
+    ('I', [".###.", "..#..", "..#..", "..#..", "..#..", "..#..", ".###."]),
+    ('J', ["..###", "...#.", "...#.", "...#.", "...#.", "#..#.", ".##.."]),
+    ('K', ["#...#", "#..#.", "#.#..", "##...", "#.#..", "#..#.", "#...#"]),
+    ('L', ["#....", "#....", "#....", "#....", "#....", "#....", "#####"]),
+    ('M', ["#...#", "##.##", "#.#.#", "#.#.#", "#...#", "#...#", "#...#"]),
+    ('N', ["#...#", "#...#", "##..#", "#.#.#", "#..##", "#...#", "#...#"]),
+    ('P', ["####.", "#...#", "#...#", "####.", "#....", "#....", "#...."]),
+    ('Q', [".###.", "#...#", "#...#", "#...#", "#.#.#", "#..#.", ".##.#"]),
+    ('R', ["####.", "#...#", "#...#", "####.", "#.#..", "#..#.", "#...#"]),
+    ('S', [".####", "#....", "#....", ".###.", "....#", "....#", "####."]),
+    ('T', ["#####", "..#..", "..#..", "..#..", "..#..", "..#..", "..#.."]),
+    ('U', ["#...#", "#...#", "#...#", "#...#", "#...#", "#...#", ".###."]),
+    ('V', ["#...#", "#...#", "#...#", "#...#", "#...#", ".#.#.", "..#.."]),
+    ('W', ["#...#", "#...#", "#...#", "#.#.#", "#.#.#", "#.#.#", ".#.#."]),
+    ('X', ["#...#", "#...#", ".#.#.", "..#..", ".#.#.", "#...#", "#...#"]),
+    ('Y', ["#...#", "#...#", ".#.#.", "..#..", "..#..", "..#..", "..#.."]),
+    ('Z', ["#####", "....#", "...#.", "..#..", ".#...", "#....", "#####"]),
+    ('+', [".....", "..#..", "..#..", "#####", "..#..", "..#..", "....."]),
+    ('#', [".....", ".#.#.", "#####", ".#.#.", "#####", ".#.#.", "....."]),
+    ('=', [".....", ".....", "#####", ".....", "#####", ".....", "....."]),
+    ('-', [".....", ".....", ".....", ".###.", ".....", ".....", "....."]),
+    ('(', ["...#.", "..#..", ".#...", ".#...", ".#...", "..#..", "...#."]),
+    (')', [".#...", "..#..", "...#.", "...#.", "...#.", "..#..", ".#..."]),
+];
+
+/// Punctuation made of dots. It isn't matched with the font (a dot has no shape), dots
+/// are recognized by their size instead.
+#[rustfmt::skip]
+pub static DOTS: [(char, [&str; GLYPH_HEIGHT]); 2] = [
+    (':', [".....", "..#..", ".....", ".....", ".....", "..#..", "....."]),
+    ('.', [".....", ".....", ".....", ".....", ".....", ".....", "..#.."]),
+];
+
+/// Maximal number of cells (of 35) which may differ from the glyph.
+const MAX_GLYPH_DISTANCE: u32 = 6;
+/// Spots smaller than this part of the line height (in both directions) are dots.
+const DOT_SIZE: f64 = 0.35;
+/// Gaps between glyphs wider than this part of the line height separate words.
+const SPACE_WIDTH: f64 = 0.7;
+/// Maximal ratio between width to height ratios of the spot and of the glyph. Scaling to
+/// the grid loses the proportions, which tell e.g. `o` from `0` or `x` from `X`.
+const MAX_ASPECT_RATIO: f64 = 1.2;
+/// Character of glyphs which don't match any of the font.
+pub const UNKNOWN: char = '?';
+
+/// Connected spot of the binary image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Component {
+    /// Value of the spot's pixels in the labels image.
+    pub label: i32,
+    pub bounds: Rect,
+    pub area: i32,
+}
+
+/// Finds connected spots of the binary image. Returns the image of labels and the spots
+/// (without background).
+pub fn components(mask: &Mat) -> CheatessResult<(Mat, Vec<Component>)> {
+    let (mut labels, mut stats, mut centroids) = (Mat::default(), Mat::default(), Mat::default());
+    let count = imgproc::connected_components_with_stats(
+        mask,
+        &mut labels,
+        &mut stats,
+        &mut centroids,
+        8,
+        CV_32S,
+    )?;
+
+    let mut components = vec![];
+    for label in 1..count {
+        let stat = |stat: i32| stats.at_2d::<i32>(label, stat).copied();
+        components.push(Component {
+            label,
+            bounds: Rect::new(
+                stat(imgproc::CC_STAT_LEFT)?,
+                stat(imgproc::CC_STAT_TOP)?,
+                stat(imgproc::CC_STAT_WIDTH)?,
+                stat(imgproc::CC_STAT_HEIGHT)?,
+            ),
+            area: stat(imgproc::CC_STAT_AREA)?,
+        });
+    }
+    Ok((labels, components))
+}
+
+/// Crops the spot from the image of labels and scales it to the glyph grid.
+pub fn component_cells(labels: &Mat, component: &Component) -> CheatessResult<Cells> {
+    spots_cells(labels, std::slice::from_ref(component))
+}
+
+/// Crops spots of one glyph (e.g. `i` and its dot) from the image of labels and scales
+/// them together to the glyph grid.
+fn spots_cells(labels: &Mat, spots: &[Component]) -> CheatessResult<Cells> {
+    let bounds = spots
+        .iter()
+        .map(|spot| spot.bounds)
+        .reduce(|a, b| a | b)
+        .unwrap_or_default();
+    let patch = Mat::roi(labels, bounds)?;
+
+    let mut glyph =
+        Mat::new_rows_cols_with_default(bounds.height, bounds.width, CV_8UC1, Scalar::all(0.0))?;
+    for spot in spots {
+        let (mut pixels, mut joined) = (Mat::default(), Mat::default());
+        opencv::core::compare(
+            &patch,
+            &Scalar::all(spot.label as f64),
+            &mut pixels,
+            opencv::core::CMP_EQ,
+        )?;
+        opencv::core::bitwise_or(&glyph, &pixels, &mut joined, &Mat::default())?;
+        glyph = joined;
+    }
+    to_cells(&glyph)
+}
+
+/// Returns the glyph (among allowed ones) closest to the cells together with the number
+/// of differing cells, unless all of them differ too much.
+pub fn closest_glyph(
+    cells: &Cells,
+    templates: &[(char, Cells)],
+    allowed: impl Fn(char) -> bool,
+) -> Option<(char, u32)> {
+    templates
+        .iter()
+        .filter(|(glyph, _)| allowed(*glyph))
+        .map(|(glyph, template)| {
+            let distance = (0..GLYPH_HEIGHT * GLYPH_WIDTH)
+                .filter(|i| {
+                    cells[i / GLYPH_WIDTH][i % GLYPH_WIDTH]
+                        != template[i / GLYPH_WIDTH][i % GLYPH_WIDTH]
+                })
+                .count() as u32;
+            (*glyph, distance)
+        })
+        .filter(|(_, distance)| *distance <= MAX_GLYPH_DISTANCE)
+        .min_by_key(|(_, distance)| *distance)
+}
+
+/// Checks if the glyph of the font has about the same proportions as the spot.
+pub fn similar_aspect(glyph: char, bounds: &Rect) -> bool {
+    let Some((_, rows)) = GLYPHS.iter().find(|(c, _)| *c == glyph) else {
+        return false;
+    };
+    let cells = glyph_bounds(rows);
+    let expected = cells.width as f64 / cells.height as f64;
+    let aspect = bounds.width as f64 / bounds.height as f64;
+    expected.max(aspect) / expected.min(aspect) <= MAX_ASPECT_RATIO
+}
+
+/// Reads lines of text (from top to bottom) from the binary image with white text.
+/// Words are separated by single spaces, glyphs which aren't in the font are `UNKNOWN`.
+pub fn read_lines(mask: &Mat) -> CheatessResult<Vec<String>> {
+    let (labels, mut spots) = components(mask)?;
+    let templates = templates()?;
+
+    // tall spots start lines, smaller ones (dots, short letters) join the nearest line,
+    // which may be a bit above them (dot of `i` in a line of short letters)
+    spots.sort_by_key(|spot| -spot.bounds.height);
+    let mut lines: Vec<(Rect, Vec<Component>)> = vec![];
+    for spot in spots {
+        let centre = spot.bounds.y + spot.bounds.height / 2;
+        let line_centre = |line: &Rect| line.y + line.height / 2;
+        match lines
+            .iter_mut()
+            .filter(|(line, _)| {
+                line.y - line.height / 2 <= centre
+                    && centre < line.y + line.height + line.height / 2
+            })
+            .min_by_key(|(line, _)| (line_centre(line) - centre).abs())
+        {
+            Some((_, members)) => members.push(spot),
+            None => lines.push((spot.bounds, vec![spot])),
+        }
+    }
+    lines.sort_by_key(|(line, _)| line.y);
+
+    let mut text = vec![];
+    for (line, mut members) in lines {
+        members.sort_by_key(|spot| spot.bounds.x);
+        let height = line.height as f64;
+        let is_dot = |spot: &Component| {
+            (spot.bounds.width as f64) < height * DOT_SIZE
+                && (spot.bounds.height as f64) < height * DOT_SIZE
+        };
+
+        // spots one above the other make a single glyph (`i`, `=`, colon)
+        let mut glyphs: Vec<Vec<Component>> = vec![];
+        for spot in members {
+            match glyphs.last_mut() {
+                Some(glyph)
+                    if glyph
+                        .iter()
+                        .any(|other| spot.bounds.x < other.bounds.x + other.bounds.width) =>
+                {
+                    glyph.push(spot)
+                }
+                _ => glyphs.push(vec![spot]),
+            }
+        }
+
+        let mut read = String::new();
+        let mut last: Option<(i32, bool)> = None;
+        for spots in glyphs {
+            let bounds = spots
+                .iter()
+                .map(|spot| spot.bounds)
+                .reduce(|a, b| a | b)
+                .unwrap_or_default();
+            let dots = spots.iter().all(is_dot);
+            // dots of an ellipsis (`1...`) are spaced wider than letters
+            if last.is_some_and(|(end, last_dots)| {
+                !(dots && last_dots) && (bounds.x - end) as f64 > height * SPACE_WIDTH
+            }) {
+                read.push(' ');
+            }
+
+            read.push(match (dots, spots.len()) {
+                (true, 1) => '.',
+                (true, 2) => ':',
+                (true, _) => UNKNOWN,
+                (false, _) => {
+                    let cells = spots_cells(&labels, &spots)?;
+                    closest_glyph(&cells, &templates, |glyph| similar_aspect(glyph, &bounds))
+                        .map_or(UNKNOWN, |(glyph, _)| glyph)
+                }
+            });
+            let end = bounds.x + bounds.width;
+            last = Some((last.map_or(end, |(last, _)| last.max(end)), dots));
+        }
+        text.push(read);
+    }
+    Ok(text)
+}
+
+/// Separates text from the background: text may be dark or light, but it always covers
+/// the smaller part of the region.
+pub fn text_mask(patch: &Mat) -> CheatessResult<Mat> {
+    let mut mask = Mat::default();
+    imgproc::threshold(
+        patch,
+        &mut mask,
+        0.0,
+        255.0,
+        imgproc::THRESH_BINARY | imgproc::THRESH_OTSU,
+    )?;
+    if opencv::core::count_non_zero(&mask)? * 2 > patch.rows() * patch.cols() {
+        let mut inverted = Mat::default();
+        opencv::core::bitwise_not(&mask, &mut inverted, &Mat::default())?;
+        mask = inverted;
+    }
+    Ok(mask)
+}
+
+/// Scales the binary image to the glyph grid.
+fn to_cells(glyph: &Mat) -> CheatessResult<Cells> {
+    let mut scaled = Mat::default();
+    imgproc::resize(
+        glyph,
+        &mut scaled,
+        Size::new(GLYPH_WIDTH as i32, GLYPH_HEIGHT as i32),
+        0.0,
+        0.0,
+        imgproc::INTER_AREA,
+    )?;
+
+    let mut cells = [[false; GLYPH_WIDTH]; GLYPH_HEIGHT];
+    for (y, line) in cells.iter_mut().enumerate() {
+        for (x, cell) in line.iter_mut().enumerate() {
+            *cell = *scaled.at_2d::<u8>(y as i32, x as i32)? > 127;
+        }
+    }
+    Ok(cells)
+}
+
+/// Bounds of the glyph's pixels in cells of the grid.
+fn glyph_bounds(rows: &[&str; GLYPH_HEIGHT]) -> Rect {
+    let (mut min, mut max) = ((usize::MAX, usize::MAX), (0, 0));
+    for (y, row) in rows.iter().enumerate() {
+        for (x, _) in row.chars().enumerate().filter(|(_, c)| *c == '#') {
+            min = (min.0.min(x), min.1.min(y));
+            max = (max.0.max(x), max.1.max(y));
+        }
+    }
+    Rect::new(
+        min.0 as i32,
+        min.1 as i32,
+        (max.0 - min.0 + 1) as i32,
+        (max.1 - min.1 + 1) as i32,
+    )
+}
+
+/// Glyphs of the font cropped to their bounds and scaled to the grid, the same way as
+/// read spots.
+pub fn templates() -> CheatessResult<Vec<(char, Cells)>> {
+    const SCALE: usize = 4;
+    GLYPHS
+        .iter()
+        .map(|(glyph, rows)| {
+            let mut mat = Mat::new_rows_cols_with_default(
+                (GLYPH_HEIGHT * SCALE) as i32,
+                (GLYPH_WIDTH * SCALE) as i32,
+                CV_8UC1,
+                Scalar::all(0.0),
+            )?;
+            for (y, row) in rows.iter().enumerate() {
+                for (x, _) in row.chars().enumerate().filter(|(_, c)| *c == '#') {
+                    for i in 0..SCALE * SCALE {
+                        let (py, px) = (y * SCALE + i / SCALE, x * SCALE + i % SCALE);
+                        *mat.at_2d_mut::<u8>(py as i32, px as i32)? = 255;
+                    }
+                }
+            }
+            let cells = glyph_bounds(rows);
+            let bounds = Rect::new(
+                cells.x * SCALE as i32,
+                cells.y * SCALE as i32,
+                cells.width * SCALE as i32,
+                cells.height * SCALE as i32,
+            );
+            Ok((*glyph, to_cells(&Mat::roi(&mat, bounds)?.try_clone()?)?))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::procimg::image_buffer_to_gray_mat;
+    use crate::core::render::draw_text;
+    use image::{ImageBuffer, Rgba};
+    use rstest::rstest;
+
+    fn render_mask(lines: &[&str], scale: u32) -> Mat {
+        let mut image = ImageBuffer::from_pixel(400, 120, Rgba([30, 30, 30, 255]));
+        for (i, line) in lines.iter().enumerate() {
+            let y = 10 + i as u32 * (GLYPH_HEIGHT as u32 + 4) * scale;
+            draw_text(&mut image, line, (10, y), scale, Rgba([230, 230, 230, 255]));
+        }
+        let gray = image_buffer_to_gray_mat(image).unwrap();
+        let mut mask = Mat::default();
+        imgproc::threshold(&gray, &mut mask, 127.0, 255.0, imgproc::THRESH_BINARY).unwrap();
+        mask
+    }
+
+    #[rstest]
+    #[case(&["10:05"], 3)]
+    #[case(&["1:02:59.8"], 2)]
+    #[case(&["abc 123", "h8 e4"], 3)]
+    #[case(&["Magnus (2850)", "1. e4 Nf6+", "2. Bxc4 0-0-0#", "12... exd8=Q"], 2)]
+    #[case(&["hikaru", "jiminy 2749"], 2)]
+    fn read_rendered_lines(#[case] lines: &[&str], #[case] scale: u32) {
+        let read = read_lines(&render_mask(lines, scale)).unwrap();
+
+        assert_eq!(read, lines);
+    }
+
+    #[rstest]
+    fn templates_cover_the_whole_font() {
+        let templates = templates().unwrap();
+
+        for ((glyph, cells), (_, rows)) in templates.iter().zip(GLYPHS) {
+            let bounds = glyph_bounds(rows);
+            let closest = closest_glyph(cells, &templates, |other| similar_aspect(other, &bounds));
+            assert_eq!(closest, Some((*glyph, 0)));
+        }
+    }
+}
@@ -0,0 +1,202 @@
+// Orientation of the board (which side is at the bottom). Every source of evidence
+// (colours of pieces in the initial position, placement of recognized kings and pawns)
+// gives a guess with a confidence, so the caller can ask the user when it's unsure.
+use super::engine::Color;
+use super::geometry::BoardGeometry;
+use super::procimg::Mat;
+use crate::utils::error::CheatessResult;
+use opencv::core::Rect;
+
+/// Orientations with lower confidence should be confirmed by the user.
+pub const MIN_CONFIDENCE: f64 = 0.5;
+/// Difference of brightness of pieces of both sides which gives the full confidence.
+const FULL_BRIGHTNESS_DIFFERENCE: f64 = 100.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Orientation {
+    /// Side at the bottom of the board.
+    pub player_color: Color,
+    /// From 0 (a pure guess) to 1 (certain).
+    pub confidence: f64,
+}
+
+impl Orientation {
+    /// Creates orientation from a signal: positive means white at the bottom, its absolute
+    /// value (clamped to 1) is the confidence.
+    pub fn from_signal(signal: f64) -> Option<Self> {
+        let player_color = if signal > 0.0 {
+            Color::White
+        } else if signal < 0.0 {
+            Color::Black
+        } else {
+            return None;
+        };
+        Some(Orientation {
+            player_color,
+            confidence: signal.abs().min(1.0),
+        })
+    }
+
+    fn signal(&self) -> f64 {
+        match self.player_color {
+            Color::White => self.confidence,
+            Color::Black => -self.confidence,
+        }
+    }
+
+    pub fn is_sure(&self) -> bool {
+        self.confidence >= MIN_CONFIDENCE
+    }
+}
+
+/// Orientation of the board in the initial position. Centres of squares in two top and two
+/// bottom rows are covered by pieces, so the side whose pieces are brighter is white.
+/// Doesn't depend on colours of squares, because both sides stand on the same squares.
+pub fn from_initial_position(
+    gray_board: &Mat,
+    geometry: &BoardGeometry,
+) -> CheatessResult<Option<Orientation>> {
+    let brightness = |rows: [usize; 2]| -> CheatessResult<f64> {
+        let mut sum = 0.0;
+        for row in rows {
+            for col in 0..8 {
+                let centre = piece_centre(&geometry.square(row, col));
+                sum += opencv::core::mean(&Mat::roi(gray_board, centre)?, &Mat::default())?[0];
+            }
+        }
+        Ok(sum / 16.0)
+    };
+
+    let (top, bottom) = (brightness([0, 1])?, brightness([6, 7])?);
+    log::debug!("Brightness of pieces: top {top:.1}, bottom {bottom:.1}");
+    Ok(Orientation::from_signal(
+        (bottom - top) / FULL_BRIGHTNESS_DIFFERENCE,
+    ))
+}
+
+/// Part of the square covered by the body of every piece.
+fn piece_centre(square: &Rect) -> Rect {
+    Rect::new(
+        square.x + square.width * 35 / 100,
+        square.y + square.height * 45 / 100,
+        (square.width * 30 / 100).max(1),
+        (square.height * 35 / 100).max(1),
+    )
+}
+
+/// Orientation from the recognized position (as seen on the screen): kings and pawns of
+/// the player are usually closer to the bottom, pawns which haven't moved yet stand on
+/// the second row from the bottom (or from the top for the opponent).
+pub fn from_position(board: &[[char; 8]; 8]) -> Option<Orientation> {
+    let rows = |piece: char| {
+        (0..64)
+            .filter(|i| board[i / 8][i % 8] == piece)
+            .map(|i| i / 8)
+            .collect::<Vec<usize>>()
+    };
+    let average = |rows: &[usize]| {
+        (!rows.is_empty()).then(|| rows.iter().sum::<usize>() as f64 / rows.len() as f64)
+    };
+
+    let (white_pawns, black_pawns) = (rows('P'), rows('p'));
+    let mut signals = vec![];
+    if let (Some(white), Some(black)) = (average(&white_pawns), average(&black_pawns)) {
+        signals.push((white - black) / 4.0);
+    }
+    if let (Some(white), Some(black)) = (average(&rows('K')), average(&rows('k'))) {
+        signals.push((white - black) / 7.0);
+    }
+
+    let on_row = |pawns: &[usize], row: usize| pawns.iter().filter(|&&r| r == row).count() as f64;
+    let home_white_bottom = on_row(&white_pawns, 6) + on_row(&black_pawns, 1);
+    let home_black_bottom = on_row(&white_pawns, 1) + on_row(&black_pawns, 6);
+    if home_white_bottom + home_black_bottom > 0.0 {
+        signals.push((home_white_bottom - home_black_bottom) / 8.0);
+    }
+
+    if signals.is_empty() {
+        return None;
+    }
+    Orientation::from_signal(signals.iter().sum::<f64>() / signals.len() as f64)
+}
+
+/// Combines orientations from different sources. Agreeing ones strengthen each other,
+/// opposite ones lower the confidence.
+pub fn combine(orientations: &[Option<Orientation>]) -> Option<Orientation> {
+    Orientation::from_signal(orientations.iter().flatten().map(Orientation::signal).sum())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::engine::{board_from_fen, rotate_board};
+    use crate::core::render::{RenderOptions, SpriteSet, Theme};
+    use crate::core::testing::render_gray;
+    use crate::core::tuning::INITIAL_PLACEMENT;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(Theme::BROWN, Color::White)]
+    #[case(Theme::BROWN, Color::Black)]
+    #[case(Theme::GREEN, Color::White)]
+    #[case(Theme::GREEN, Color::Black)]
+    #[case(Theme::BLUE, Color::Black)]
+    fn orientation_of_initial_position(#[case] theme: Theme, #[case] player_color: Color) {
+        let options = RenderOptions {
+            theme,
+            player_color,
+            noise: 6,
+            ..Default::default()
+        };
+        let (gray, _) = render_gray(INITIAL_PLACEMENT, &SpriteSet::geometric(64), &options);
+
+        let orientation = from_initial_position(&gray, &BoardGeometry::canonical())
+            .unwrap()
+            .unwrap();
+        assert_eq!(orientation.player_color, player_color);
+        assert!(orientation.is_sure(), "{orientation:?}");
+    }
+
+    #[rstest]
+    #[case("r4rk1/pp3ppp/2n5/8/3P4/2N5/PP3PPP/R4RK1", true)]
+    #[case("8/8/8/3k4/4K3/8/8/8", false)]
+    #[case("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR", true)]
+    fn orientation_from_position(#[case] fen: &str, #[case] sure: bool) {
+        let board = board_from_fen(fen).unwrap();
+
+        let white = from_position(&board).unwrap();
+        assert_eq!(white.player_color, Color::White);
+        assert_eq!(white.is_sure(), sure, "{white:?}");
+
+        let black = from_position(&rotate_board(&board)).unwrap();
+        assert_eq!(black.player_color, Color::Black);
+        assert_eq!(black.confidence, white.confidence);
+    }
+
+    #[rstest]
+    fn orientation_of_empty_position() {
+        assert_eq!(from_position(&[[' '; 8]; 8]), None);
+    }
+
+    #[rstest]
+    fn combine_orientations() {
+        let white = Some(Orientation {
+            player_color: Color::White,
+            confidence: 0.4,
+        });
+        let black = Some(Orientation {
+            player_color: Color::Black,
+            confidence: 0.3,
+        });
+
+        let agreeing = combine(&[white, white, None]).unwrap();
+        assert_eq!(agreeing.player_color, Color::White);
+        assert!(agreeing.is_sure());
+
+        let opposite = combine(&[white, black]).unwrap();
+        assert_eq!(opposite.player_color, Color::White);
+        assert!(!opposite.is_sure());
+
+        assert_eq!(combine(&[None, None]), None);
+    }
+}
@@ -1,17 +1,21 @@
-use super::engine::{register_piece, Color};
-use crate::utils::error::CheatessResult;
+use super::engine::Color;
+use super::geometry::{BoardGeometry, CANONICAL_SQUARE};
+use crate::utils::error::{CheatessError, CheatessResult};
 use image::{ImageBuffer, Rgba};
 
 pub use opencv::core::Mat;
 use opencv::imgproc;
 use opencv::{
-    core::{min_max_loc, Point, Rect, Scalar, CV_8UC4},
+    core::{min_max_loc, Point, Point2f, Rect, Scalar, Size, CV_8UC4},
     highgui::{self, destroy_window},
     prelude::*,
 };
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 use std::thread;
 
+/// Corners of the board: top-left, top-right, bottom-right and bottom-left.
+pub type Corners = [Point2f; 4];
+
 static WHITE_NAMED_FIELDS: [((usize, usize), char); 12] = [
     ((0, 0), 'r'),
     ((1, 0), 'n'),
@@ -57,78 +61,154 @@ pub fn show(image: &Mat, destroy: bool, title: &str) -> CheatessResult<()> {
 
 pub fn convert_board_to_bin(gray_board: &Mat, board_threshold: f64) -> CheatessResult<Mat> {
     let mut bin_board = Mat::default();
+    convert_board_to_bin_into(gray_board, board_threshold, &mut bin_board)?;
+    Ok(bin_board)
+}
+
+/// Same as `convert_board_to_bin`, but writes into the given matrix, which is reused
+/// when it already has the size of the board.
+pub fn convert_board_to_bin_into(
+    gray_board: &Mat,
+    board_threshold: f64,
+    bin_board: &mut Mat,
+) -> CheatessResult<()> {
     imgproc::threshold(
         &gray_board,
-        &mut bin_board,
+        bin_board,
         board_threshold,
         255.0,
         imgproc::THRESH_BINARY,
     )?;
-
-    Ok(bin_board)
+    Ok(())
 }
 
 /// Finds all chess pieces on the board by performing template matching for each piece.
 /// It uses multithreading to speed up the process by processing each piece in a separate thread.
 /// The function returns a 2D array representing the chessboard, where each cell contains the piece's symbol.
 /// If a cell is empty, it contains a space character.
+/// When more templates match the same square, the one with the best (lowest) score wins,
+/// so the result doesn't depend on the order in which threads finish.
 pub fn find_all_pieces(
     gray_board: &Mat,
     pieces: &std::collections::HashMap<char, Arc<Mat>>,
     piece_threshold: f64,
     board_threshold: f64,
 ) -> CheatessResult<[[char; 8]; 8]> {
-    let bin_board = convert_board_to_bin(gray_board, board_threshold)?;
+    find_all_pieces_with_thresholds(
+        gray_board,
+        &BoardGeometry::uniform(gray_board.cols(), gray_board.rows()),
+        pieces,
+        &std::collections::HashMap::new(),
+        piece_threshold,
+        board_threshold,
+    )
+}
 
-    let result = Arc::new(Mutex::new([[' '; 8]; 8]));
+/// Same as `find_all_pieces`, but squares are taken from the given geometry and pieces
+/// listed in `piece_thresholds` are matched with their own threshold instead of the global
+/// `piece_threshold`.
+pub fn find_all_pieces_with_thresholds(
+    gray_board: &Mat,
+    geometry: &BoardGeometry,
+    pieces: &std::collections::HashMap<char, Arc<Mat>>,
+    piece_thresholds: &std::collections::HashMap<char, f64>,
+    piece_threshold: f64,
+    board_threshold: f64,
+) -> CheatessResult<[[char; 8]; 8]> {
+    let bin_board = convert_board_to_bin(gray_board, board_threshold)?;
     let bin_board = Arc::new(bin_board);
 
     let mut handles = vec![];
     for (sign, piece_arc) in pieces.iter() {
         let board = Arc::clone(&bin_board);
-        let result_ref = Arc::clone(&result);
         let piece = Arc::clone(piece_arc);
+        let geometry = geometry.clone();
         let sign = *sign;
+        let piece_threshold = piece_thresholds
+            .get(&sign)
+            .copied()
+            .unwrap_or(piece_threshold);
 
         let handle = thread::spawn(move || {
-            let local_result = find_piece_location(&board, &piece, piece_threshold, sign)
+            let scores = find_piece_location(&board, &piece, &geometry, piece_threshold)
                 .expect("Failed to find piece location");
-
-            let mut res = result_ref.lock().unwrap();
-            for row in 0..8 {
-                for col in 0..8 {
-                    if local_result[row][col] != ' ' && res[row][col] == ' ' {
-                        res[row][col] = local_result[row][col];
-                    }
-                }
-            }
+            (sign, scores)
         });
 
         handles.push(handle);
     }
 
-    for handle in handles {
-        handle.join().unwrap();
+    let found = handles
+        .into_iter()
+        .map(|handle| handle.join().unwrap())
+        .collect::<Vec<_>>();
+    Ok(best_matches(found))
+}
+
+/// Picks the piece with the best (lowest) score on every square from scores of all pieces.
+pub fn best_matches(mut found: Vec<(char, [[Option<f64>; 8]; 8])>) -> [[char; 8]; 8] {
+    found.sort_by_key(|(sign, _)| *sign);
+
+    let mut best: [[Option<(char, f64)>; 8]; 8] = [[None; 8]; 8];
+    for (sign, scores) in found {
+        for (row, col) in (0..8).flat_map(|row| (0..8).map(move |col| (row, col))) {
+            let Some(score) = scores[row][col] else {
+                continue;
+            };
+            match best[row][col] {
+                Some((winner, winner_score)) if winner_score <= score => {
+                    log::trace!(
+                        "Square ({row}, {col}): {sign} ({score:.4}) lost to {winner} ({winner_score:.4})"
+                    );
+                }
+                previous => {
+                    if let Some((loser, loser_score)) = previous {
+                        log::trace!(
+                            "Square ({row}, {col}): {loser} ({loser_score:.4}) lost to {sign} ({score:.4})"
+                        );
+                    }
+                    best[row][col] = Some((sign, score));
+                }
+            }
+        }
     }
-    let out = *result.lock().unwrap();
-    Ok(out)
+
+    best.map(|row| row.map(|square| square.map_or(' ', |(sign, _)| sign)))
 }
 
+/// Returns the best (lowest) score of the piece in every square where it was found.
 /// Both images (board and piece) are already binary thresholded, so mask is not needed.
-fn find_piece_location(
+pub fn find_piece_location(
     board_image: &Mat,
     piece_image: &Mat,
+    geometry: &BoardGeometry,
     threshold: f64,
-    symbol: char,
-) -> CheatessResult<[[char; 8]; 8]> {
-    let mut result: [[char; 8]; 8] = [[' '; 8]; 8];
+) -> CheatessResult<[[Option<f64>; 8]; 8]> {
+    find_piece_location_into(
+        board_image,
+        piece_image,
+        geometry,
+        threshold,
+        &mut Mat::default(),
+    )
+}
+
+/// Same as `find_piece_location`, but the result of template matching is written into
+/// the given matrix, which is reused when it already has the right size.
+pub fn find_piece_location_into(
+    board_image: &Mat,
+    piece_image: &Mat,
+    geometry: &BoardGeometry,
+    threshold: f64,
+    matched: &mut Mat,
+) -> CheatessResult<[[Option<f64>; 8]; 8]> {
+    let mut result: [[Option<f64>; 8]; 8] = [[None; 8]; 8];
     let empty_mask = Mat::default();
 
-    let mut matched = Mat::default();
     imgproc::match_template(
         board_image,
         piece_image,
-        &mut matched,
+        matched,
         imgproc::TM_SQDIFF_NORMED,
         &empty_mask,
     )?;
@@ -138,12 +218,11 @@ fn find_piece_location(
     let mut min_loc = Point::default();
     let mut max_loc = Point::default();
 
-    let board_size = board_image.size()?;
     let matched_size = matched.size()?;
     let poison_val = Scalar::all(1.0);
 
     min_max_loc(
-        &matched,
+        &*matched,
         Some(&mut min_val),
         Some(&mut max_val),
         Some(&mut min_loc),
@@ -158,16 +237,18 @@ fn find_piece_location(
 
         let top_left = min_loc;
 
-        register_piece(
-            (top_left.y, top_left.x), // Note: OpenCV uses (y, x) for coordinates
-            (board_size.width, board_size.height),
-            symbol,
-            &mut result,
-        )?;
+        match geometry.square_at(top_left.x, top_left.y) {
+            Some((row, col)) => {
+                let score = result[row][col].get_or_insert(min_val);
+                *score = score.min(min_val);
+            }
+            None => log::trace!("Piece found outside of the board at {top_left:?}"),
+        }
 
         let top_x = top_left.x.clamp(0, matched_size.width - 1);
         let top_y = top_left.y.clamp(0, matched_size.height - 1);
 
+        // board is normalized to the canonical size, so poisoned area is smaller than a square
         let rect_x = (top_x - 22).max(0);
         let rect_y = (top_y - 22).max(0);
         let rect_w = 45.min(matched_size.width - rect_x);
@@ -179,7 +260,7 @@ fn find_piece_location(
         result_slice.set_to(&poison_val, &empty_mask)?;
 
         min_max_loc(
-            &matched,
+            &*matched,
             Some(&mut min_val),
             Some(&mut max_val),
             Some(&mut min_loc),
@@ -191,34 +272,70 @@ fn find_piece_location(
     Ok(result)
 }
 
+/// Same as `find_piece_location_into`, but only the given squares are searched: the piece
+/// is matched at positions whose top-left corner lies inside the square, like in the
+/// whole board search.
+pub fn find_piece_in_squares(
+    board_image: &Mat,
+    piece_image: &Mat,
+    geometry: &BoardGeometry,
+    threshold: f64,
+    squares: &[(usize, usize)],
+    matched: &mut Mat,
+) -> CheatessResult<[[Option<f64>; 8]; 8]> {
+    let mut result: [[Option<f64>; 8]; 8] = [[None; 8]; 8];
+    let empty_mask = Mat::default();
+
+    for &(row, col) in squares {
+        let square = geometry.square(row, col);
+        let region = Rect::new(
+            square.x,
+            square.y,
+            (square.width + piece_image.cols() - 1).min(board_image.cols() - square.x),
+            (square.height + piece_image.rows() - 1).min(board_image.rows() - square.y),
+        );
+        if region.width < piece_image.cols() || region.height < piece_image.rows() {
+            continue;
+        }
+
+        imgproc::match_template(
+            &Mat::roi(board_image, region)?,
+            piece_image,
+            matched,
+            imgproc::TM_SQDIFF_NORMED,
+            &empty_mask,
+        )?;
+        let mut min_val = 0.0;
+        min_max_loc(
+            &*matched,
+            Some(&mut min_val),
+            None,
+            None::<&mut Point>,
+            None,
+            &empty_mask,
+        )?;
+        if min_val < threshold {
+            result[row][col] = Some(min_val);
+        }
+    }
+
+    Ok(result)
+}
+
+/// Guesses the side at the bottom of the board in the initial position from the piece
+/// in the bottom-left corner: the black rook has more dark pixels than the white one.
+/// Unlike `orientation::from_initial_position`, it doesn't tell how sure the guess is.
 pub fn detect_player_color(gray_board: &Mat) -> CheatessResult<Color> {
-    let mut bin_board = Mat::default();
+    let square = BoardGeometry::uniform(gray_board.cols(), gray_board.rows()).square(7, 0);
+    let mut bin_square = Mat::default();
     imgproc::threshold(
-        &gray_board,
-        &mut bin_board,
+        &Mat::roi(gray_board, square)?,
+        &mut bin_square,
         50.0,
         255.0,
-        imgproc::THRESH_BINARY,
+        imgproc::THRESH_BINARY_INV,
     )?;
-
-    let img = bin_board;
-    let width = img.cols();
-    let height = img.rows();
-    let square_width = width / 8;
-    let square_height = height / 8;
-
-    let roi = Rect::new(0, height - square_height, square_width, square_height);
-    let cropped = img.roi(roi).unwrap().try_clone()?;
-
-    let total_pixels = cropped.rows() * cropped.cols();
-    let black_pixels = cropped
-        .data_bytes()
-        .unwrap()
-        .iter()
-        .filter(|&&p| p == 0)
-        .count();
-
-    let black_ratio = black_pixels as f32 / total_pixels as f32;
+    let black_ratio = opencv::core::count_non_zero(&bin_square)? as f32 / square.area() as f32;
 
     // white rook: 0.14, black rook: 0.26
     if black_ratio > 0.2 {
@@ -228,12 +345,45 @@ pub fn detect_player_color(gray_board: &Mat) -> CheatessResult<Color> {
     }
 }
 
-/// Captures the screen and returns the region of the chessboard
-/// with the following steps:
+/// Returns the region of the largest board visible on the screen, i.e. the bounding
+/// rectangle of `get_board_corners`.
+pub fn get_board_region(gray: &Mat) -> CheatessResult<(u32, u32, u32, u32)> {
+    let (x_start, y_start, width, height) = quad_bounding_rect(&get_board_corners(gray)?);
+
+    log::trace!(
+        "Board corners parameters: top-left=({},{}) top-right=({},{}) bottom-left=({},{}) bottom-right=({},{})",
+        x_start, y_start,
+        x_start+width,y_start,
+        x_start,y_start+height,
+        x_start+width,y_start+height
+    );
+    Ok((x_start, y_start, width, height))
+}
+
+/// Returns corners of the largest board visible on the screen. Corners keep the
+/// perspective of the board (e.g. on photos or skewed captures), `quad_bounding_rect`
+/// gives its region.
+pub fn get_board_corners(gray: &Mat) -> CheatessResult<Corners> {
+    get_board_quads(gray)?
+        .into_iter()
+        .max_by_key(|quad| {
+            let region = quad_bounding_rect(quad);
+            region.2 * region.3
+        })
+        .ok_or(CheatessError::BoardNotFound)
+}
+
+/// Returns ordered corners of all boards visible on the screen (e.g. tournament overview),
+/// ordered from top-left to bottom-right. Boards are found with the following steps:
 /// - apply Canny edge detection to find the edges
 /// - find contours in the edge-detected image
 /// - approximate the contours to find quadrilaterals
-pub fn get_board_region(gray: &Mat) -> CheatessResult<(u32, u32, u32, u32)> {
+///
+/// Every square-like convex quadrilateral (not smaller than `MIN_BOARD_SIZE`) is
+/// a candidate; candidates which overlap a bigger one (e.g. lie inside it) are dropped.
+pub fn get_board_quads(gray: &Mat) -> CheatessResult<Vec<Corners>> {
+    static MIN_BOARD_SIZE: u32 = 64;
+
     let mut edges = Mat::default();
     imgproc::canny(&gray, &mut edges, 50.0, 150.0, 3, false)?;
 
@@ -246,9 +396,7 @@ pub fn get_board_region(gray: &Mat) -> CheatessResult<(u32, u32, u32, u32)> {
         Point::new(0, 0),
     )?;
 
-    let mut max_area = 0.0;
-    let mut best_quad = vec![];
-
+    let mut candidates: Vec<Corners> = vec![];
     for contour in contours {
         let mut approx = opencv::core::Vector::<Point>::new();
         imgproc::approx_poly_dp(
@@ -259,96 +407,330 @@ pub fn get_board_region(gray: &Mat) -> CheatessResult<(u32, u32, u32, u32)> {
         )?;
 
         if approx.len() == 4 && imgproc::is_contour_convex(&approx)? {
-            let area = imgproc::contour_area(&approx, false)?;
             let bounding = imgproc::bounding_rect(&approx)?;
 
             let aspect_ratio = bounding.width as f32 / bounding.height as f32;
-            if area > max_area && aspect_ratio > 0.8 && aspect_ratio < 1.2 {
-                max_area = area;
-                best_quad = approx.to_vec();
+            if aspect_ratio > 0.8
+                && aspect_ratio < 1.2
+                && bounding.width as u32 >= MIN_BOARD_SIZE
+                && bounding.height as u32 >= MIN_BOARD_SIZE
+            {
+                let points = approx
+                    .iter()
+                    .map(|p| Point2f::new(p.x as f32, p.y as f32))
+                    .collect::<Vec<_>>();
+                candidates.push(order_corners(&points));
             }
         }
     }
 
-    let x_start = best_quad[0].x as u32;
-    let y_start = best_quad[0].y as u32;
-    let width = best_quad[2].x as u32 - x_start;
-    let height = best_quad[2].y as u32 - y_start;
+    candidates.sort_by_key(|quad| {
+        let region = quad_bounding_rect(quad);
+        std::cmp::Reverse(region.2 * region.3)
+    });
+    let mut quads: Vec<Corners> = vec![];
+    for candidate in candidates {
+        let region = quad_bounding_rect(&candidate);
+        if !quads
+            .iter()
+            .any(|quad| overlaps(&quad_bounding_rect(quad), &region))
+        {
+            quads.push(candidate);
+        }
+    }
+    quads.sort_by_key(|quad| {
+        let region = quad_bounding_rect(quad);
+        (region.1, region.0)
+    });
+
+    log::debug!("Found {} board candidates: {quads:?}", quads.len());
+    Ok(quads)
+}
+
+/// Orders four points as top-left, top-right, bottom-right and bottom-left corner.
+pub fn order_corners(points: &[Point2f]) -> Corners {
+    let by = |key: fn(&Point2f) -> f32, max: bool| {
+        let mut sorted = points.to_vec();
+        sorted.sort_by(|a, b| key(a).total_cmp(&key(b)));
+        if max {
+            sorted[sorted.len() - 1]
+        } else {
+            sorted[0]
+        }
+    };
+
+    [
+        by(|p| p.x + p.y, false),
+        by(|p| p.x - p.y, true),
+        by(|p| p.x + p.y, true),
+        by(|p| p.x - p.y, false),
+    ]
+}
 
+/// Returns axis-aligned bounding box of the corners.
+pub fn quad_bounding_rect(corners: &Corners) -> (u32, u32, u32, u32) {
+    let min_x = corners
+        .iter()
+        .map(|p| p.x)
+        .fold(f32::MAX, f32::min)
+        .max(0.0);
+    let min_y = corners
+        .iter()
+        .map(|p| p.y)
+        .fold(f32::MAX, f32::min)
+        .max(0.0);
+    let max_x = corners.iter().map(|p| p.x).fold(0.0, f32::max);
+    let max_y = corners.iter().map(|p| p.y).fold(0.0, f32::max);
+
+    (
+        min_x as u32,
+        min_y as u32,
+        (max_x - min_x) as u32,
+        (max_y - min_y) as u32,
+    )
+}
+
+/// Warps the board given by its corners to the square, top-down image. Size of the image
+/// is the longest side of the board (rounded down to the multiple of 8).
+pub fn warp_board(gray: &Mat, corners: &Corners) -> CheatessResult<Mat> {
+    let side = |a: &Point2f, b: &Point2f| ((a.x - b.x).powi(2) + (a.y - b.y).powi(2)).sqrt();
+    let longest = (0..4)
+        .map(|i| side(&corners[i], &corners[(i + 1) % 4]))
+        .fold(0.0, f32::max);
+    let size = ((longest as i32) / 8 * 8).max(8);
+
+    let last = (size - 1) as f32;
+    let src = opencv::core::Vector::<Point2f>::from_slice(corners);
+    let dst = opencv::core::Vector::<Point2f>::from_slice(&[
+        Point2f::new(0.0, 0.0),
+        Point2f::new(last, 0.0),
+        Point2f::new(last, last),
+        Point2f::new(0.0, last),
+    ]);
+    let transform = imgproc::get_perspective_transform(&src, &dst, opencv::core::DECOMP_LU)?;
+
+    let mut warped = Mat::default();
+    imgproc::warp_perspective(
+        gray,
+        &mut warped,
+        &transform,
+        Size::new(size, size),
+        imgproc::INTER_LINEAR,
+        opencv::core::BORDER_REPLICATE,
+        Scalar::default(),
+    )?;
+    Ok(warped)
+}
+
+/// Evens out uneven lighting (shadows, reflections on photos) by dividing the image by
+/// its blurred copy. Average brightness of the image is kept.
+pub fn normalize_lighting(gray: &Mat) -> CheatessResult<Mat> {
+    // background is estimated on the downscaled board (4px per square) and blurred over
+    // about three squares, so it doesn't follow colours of squares and pieces
+    let mut small = Mat::default();
+    imgproc::resize(
+        gray,
+        &mut small,
+        Size::new(32, 32),
+        0.0,
+        0.0,
+        imgproc::INTER_AREA,
+    )?;
+    let mut blurred = Mat::default();
+    imgproc::gaussian_blur(
+        &small,
+        &mut blurred,
+        Size::default(),
+        12.0,
+        12.0,
+        opencv::core::BORDER_REFLECT,
+    )?;
+    let mut background = Mat::default();
+    imgproc::resize(
+        &blurred,
+        &mut background,
+        gray.size()?,
+        0.0,
+        0.0,
+        imgproc::INTER_LINEAR,
+    )?;
+
+    let brightness = opencv::core::mean(gray, &Mat::default())?[0];
+    let mut normalized = Mat::default();
+    opencv::core::divide2(gray, &background, &mut normalized, brightness, -1)?;
+    Ok(normalized)
+}
+
+/// Cuts the board from the (gray) screen: warps it when corners are known,
+/// otherwise crops the rectangular region.
+pub fn extract_board(
+    raw: &Mat,
+    coords: &(u32, u32, u32, u32),
+    corners: Option<&Corners>,
+) -> CheatessResult<Mat> {
+    match corners {
+        Some(corners) => normalize_lighting(&warp_board(raw, corners)?),
+        None => crop_mat(raw, coords),
+    }
+}
+
+/// Cuts the board from the coloured screen like `extract_board`, but keeps its colours
+/// (lighting of the warped board isn't evened out).
+pub fn extract_color_board(
+    raw: &Mat,
+    coords: &(u32, u32, u32, u32),
+    corners: Option<&Corners>,
+) -> CheatessResult<Mat> {
+    match corners {
+        Some(corners) => warp_board(raw, corners),
+        None => crop_mat(raw, coords),
+    }
+}
+
+fn overlaps(a: &(u32, u32, u32, u32), b: &(u32, u32, u32, u32)) -> bool {
+    a.0 < b.0 + b.2 && b.0 < a.0 + a.2 && a.1 < b.1 + b.3 && b.1 < a.1 + a.3
+}
+
+/// Checks if the image still looks like a chessboard, i.e. backgrounds of squares
+/// (sampled in their top-left corners, where pieces are rarely drawn) form two
+/// groups of alternating colours. Used to notice that board was moved, scrolled or zoomed.
+pub fn looks_like_board(gray_board: &Mat, geometry: &BoardGeometry) -> CheatessResult<bool> {
+    static MIN_CONTRAST: f64 = 8.0;
+    static MAX_OUTLIERS: usize = 8;
+
+    let (cell_w, cell_h) = geometry.square_size();
+    let bounds = geometry.bounds();
+    if cell_w < 8
+        || cell_h < 8
+        || bounds.x + bounds.width > gray_board.cols()
+        || bounds.y + bounds.height > gray_board.rows()
+    {
+        return Ok(false);
+    }
+
+    let mut samples = [[0.0; 8]; 8];
+    for (row, line) in samples.iter_mut().enumerate() {
+        for (col, sample) in line.iter_mut().enumerate() {
+            let square = geometry.square(row, col);
+            let roi = Rect::new(
+                square.x + cell_w / 16,
+                square.y + cell_h / 16,
+                (cell_w / 8).max(1),
+                (cell_h / 8).max(1),
+            );
+            let patch = Mat::roi(gray_board, roi)?;
+            *sample = opencv::core::mean(&patch, &Mat::default())?[0];
+        }
+    }
+
+    let group_mean = |parity: usize| {
+        samples
+            .iter()
+            .enumerate()
+            .flat_map(|(row, line)| line.iter().enumerate().map(move |(col, v)| (row, col, *v)))
+            .filter(|(row, col, _)| (row + col) % 2 == parity)
+            .map(|(_, _, v)| v)
+            .sum::<f64>()
+            / 32.0
+    };
+    let (mean_even, mean_odd) = (group_mean(0), group_mean(1));
+    if (mean_even - mean_odd).abs() < MIN_CONTRAST {
+        return Ok(false);
+    }
+
+    let mut outliers = 0;
+    for (row, line) in samples.iter().enumerate() {
+        for (col, v) in line.iter().enumerate() {
+            let (own, other) = if (row + col) % 2 == 0 {
+                (mean_even, mean_odd)
+            } else {
+                (mean_odd, mean_even)
+            };
+            if (v - own).abs() > (v - other).abs() {
+                outliers += 1;
+            }
+        }
+    }
     log::trace!(
-        "Board corners parameters: top-left=({},{}) top-right=({},{}) bottom-left=({},{}) bottom-right=({},{})",
-        x_start, y_start,
-        x_start+width,y_start,
-        x_start,y_start+height,
-        x_start+width,y_start+height
+        "Board check: contrast={:.1}, outliers={outliers}",
+        (mean_even - mean_odd).abs()
     );
-    Ok((x_start, y_start, width, height))
+
+    Ok(outliers <= MAX_OUTLIERS)
+}
+
+/// Cuts squares of the board (without frame) and resizes them to the canonical size,
+/// so the same templates can be used for boards of any size.
+pub fn normalize_board(gray_board: &Mat, geometry: &BoardGeometry) -> CheatessResult<Mat> {
+    let bounds = geometry.bounds();
+    let size = 8 * CANONICAL_SQUARE;
+    let interpolation = if bounds.width > size {
+        imgproc::INTER_AREA
+    } else {
+        imgproc::INTER_LINEAR
+    };
+
+    let mut normalized = Mat::default();
+    imgproc::resize(
+        &Mat::roi(gray_board, bounds)?,
+        &mut normalized,
+        Size::new(size, size),
+        0.0,
+        0.0,
+        interpolation,
+    )?;
+    Ok(normalized)
 }
 
 /// Checks if two images have differences in their 8x8 grid cells.
 pub fn are_images_different(gray1: &Mat, gray2: &Mat, threshold: i32) -> CheatessResult<bool> {
-    let cell_w = gray1.cols() / 8;
-    let cell_h = gray1.rows() / 8;
-
-    for row in 0..8 {
-        for col in 0..8 {
-            let x = col * cell_w;
-            let y = row * cell_h;
-
-            let width = if col == 7 { gray1.cols() - x } else { cell_w };
-            let height = if row == 7 { gray1.rows() - y } else { cell_h };
-
-            let roi = Rect::new(x, y, width, height);
-            let patch1 = Mat::roi(gray1, roi)?;
-            let patch2 = Mat::roi(gray2, roi)?;
-
-            let mut thresh1 = Mat::default();
-            imgproc::threshold(
-                &patch1,
-                &mut thresh1,
-                50.0,
-                255.0,
-                imgproc::THRESH_BINARY_INV,
-            )?;
-
-            let mut thresh2 = Mat::default();
-            imgproc::threshold(
-                &patch2,
-                &mut thresh2,
-                50.0,
-                255.0,
-                imgproc::THRESH_BINARY_INV,
-            )?;
-
-            let nonzero1 = opencv::core::count_non_zero(&thresh1)?;
-            let nonzero2 = opencv::core::count_non_zero(&thresh2)?;
-
-            if (nonzero1 > threshold) != (nonzero2 > threshold) {
-                return Ok(true);
-            }
+    let geometry = BoardGeometry::uniform(gray1.cols(), gray1.rows());
+    Ok(!changed_squares(gray1, gray2, &geometry, threshold)?.is_empty())
+}
+
+/// Returns squares (row, column) of the 8x8 grid which got occupied or emptied between
+/// two images, sorted by rows. Empty result means that images don't differ.
+pub fn changed_squares(
+    gray1: &Mat,
+    gray2: &Mat,
+    geometry: &BoardGeometry,
+    threshold: i32,
+) -> CheatessResult<Vec<(usize, usize)>> {
+    let counts1 = dark_pixel_counts(gray1, geometry)?;
+    let counts2 = dark_pixel_counts(gray2, geometry)?;
+
+    Ok((0..64)
+        .map(|i| (i / 8, i % 8))
+        .filter(|&(row, col)| (counts1[row][col] > threshold) != (counts2[row][col] > threshold))
+        .collect())
+}
+
+/// Counts dark pixels (mostly pieces) in every square. Square is treated as occupied by
+/// `changed_squares` when the count is above the difference level.
+pub fn dark_pixel_counts(
+    gray_board: &Mat,
+    geometry: &BoardGeometry,
+) -> CheatessResult<[[i32; 8]; 8]> {
+    let mut counts = [[0; 8]; 8];
+    for (row, line) in counts.iter_mut().enumerate() {
+        for (col, count) in line.iter_mut().enumerate() {
+            let patch = Mat::roi(gray_board, geometry.square(row, col))?;
+
+            let mut thresh = Mat::default();
+            imgproc::threshold(&patch, &mut thresh, 50.0, 255.0, imgproc::THRESH_BINARY_INV)?;
+            *count = opencv::core::count_non_zero(&thresh)?;
         }
     }
-
-    Ok(false)
+    Ok(counts)
 }
 
 pub fn extract_pieces(
     img: &Mat,
+    geometry: &BoardGeometry,
     margin: u8,
     extract_piece_threshold: f64,
     player_color: &Color,
 ) -> CheatessResult<std::collections::HashMap<char, Mat>> {
-    let board_size: i32 = img.rows().min(img.cols());
-    let board_size_f = board_size as f32;
-
-    let mut x_edges = [0i32; 9];
-    let mut y_edges = [0i32; 9];
-
-    for i in 0..=8 {
-        x_edges[i] = ((i as f32) * board_size_f / 8.0).round() as i32;
-        y_edges[i] = ((i as f32) * board_size_f / 8.0).round() as i32;
-    }
-
     let named_fields = match player_color {
         Color::White => &WHITE_NAMED_FIELDS,
         Color::Black => &BLACK_NAMED_FIELDS,
@@ -356,10 +738,12 @@ pub fn extract_pieces(
 
     let mut result = std::collections::HashMap::new();
     for ((col, row), name) in named_fields {
-        let x = x_edges[*col];
-        let y = y_edges[*row];
-        let w = x_edges[col + 1] - x;
-        let h = y_edges[row + 1] - y;
+        let Rect {
+            x,
+            y,
+            width: w,
+            height: h,
+        } = geometry.square(*row, *col);
 
         // Add a margin to the piece extraction area
         let margin = margin as i32;
@@ -397,6 +781,45 @@ pub fn image_buffer_to_gray_mat(img: ImageBuffer<Rgba<u8>, Vec<u8>>) -> Cheatess
     Ok(gray_mat)
 }
 
+/// Converts RGBA image buffer to BGR matrix (colours are needed e.g. to find highlighted squares).
+pub fn image_buffer_to_color_mat(img: ImageBuffer<Rgba<u8>, Vec<u8>>) -> CheatessResult<Mat> {
+    let (width, height) = img.dimensions();
+    let mut mat =
+        Mat::new_rows_cols_with_default(height as i32, width as i32, CV_8UC4, Scalar::all(0.0))?;
+
+    let mat_data = mat.data_bytes_mut()?;
+    mat_data.copy_from_slice(img.as_raw());
+
+    let mut color_mat = Mat::default();
+    imgproc::cvt_color(&mat, &mut color_mat, imgproc::COLOR_RGBA2BGR, 0)?;
+    Ok(color_mat)
+}
+
+pub fn color_to_gray(color: &Mat) -> CheatessResult<Mat> {
+    let mut gray_mat = Mat::default();
+    imgproc::cvt_color(color, &mut gray_mat, imgproc::COLOR_BGR2GRAY, 0)?;
+    Ok(gray_mat)
+}
+
+/// Converts BGR(A) frame (e.g. decoded by OpenCV) to RGBA image buffer.
+pub fn mat_to_image_buffer(mat: &Mat) -> CheatessResult<ImageBuffer<Rgba<u8>, Vec<u8>>> {
+    let code = if mat.channels() == 4 {
+        imgproc::COLOR_BGRA2RGBA
+    } else {
+        imgproc::COLOR_BGR2RGBA
+    };
+
+    let mut rgba = Mat::default();
+    imgproc::cvt_color(mat, &mut rgba, code, 0)?;
+
+    Ok(ImageBuffer::<Rgba<u8>, _>::from_raw(
+        rgba.cols() as u32,
+        rgba.rows() as u32,
+        rgba.data_bytes()?.to_vec(),
+    )
+    .expect("Failed to create ImageBuffer"))
+}
+
 pub fn crop_mat(raw: &Mat, coords: &(u32, u32, u32, u32)) -> CheatessResult<Mat> {
     let roi = Rect {
         x: coords.0 as i32,
@@ -411,7 +834,234 @@ pub fn crop_mat(raw: &Mat, coords: &(u32, u32, u32, u32)) -> CheatessResult<Mat>
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::core::engine::detect_move;
+    use crate::core::render::{
+        random_placement, render_board, RenderOptions, Rng, SpriteSet, Theme,
+    };
+    use crate::core::testing::{render_gray, rendered_templates};
+    use crate::core::tuning::INITIAL_PLACEMENT;
     use opencv::{imgcodecs, imgproc};
+    use rstest::rstest;
+
+    fn uniform_geometry(mat: &Mat) -> BoardGeometry {
+        BoardGeometry::uniform(mat.cols(), mat.rows())
+    }
+
+    #[rstest]
+    fn get_board_quads_finds_every_board() {
+        let sprites = SpriteSet::geometric(32);
+        let options = RenderOptions {
+            size: 256,
+            ..Default::default()
+        };
+        let (board, _) = render_board(INITIAL_PLACEMENT, &sprites, &options).unwrap();
+
+        let mut screen = ImageBuffer::from_pixel(900, 400, Rgba([50, 50, 50, 255]));
+        image::imageops::overlay(&mut screen, &board, 50, 60);
+        image::imageops::overlay(&mut screen, &board, 500, 80);
+        let gray = image_buffer_to_gray_mat(screen).unwrap();
+
+        let quads = get_board_quads(&gray).unwrap();
+        let regions = quads.iter().map(quad_bounding_rect).collect::<Vec<_>>();
+        assert_eq!(regions.len(), 2);
+        assert!(regions[0].0.abs_diff(50) <= 2 && regions[0].1.abs_diff(60) <= 2);
+        assert!(regions[1].0.abs_diff(500) <= 2 && regions[1].1.abs_diff(80) <= 2);
+
+        let largest = get_board_corners(&gray).unwrap();
+        assert!(quads.contains(&largest));
+    }
+
+    #[rstest]
+    #[case(Theme::BROWN, INITIAL_PLACEMENT)]
+    #[case(Theme::GREEN, "r3k2r/8/2n5/3pP3/8/5N2/8/R3K2R")]
+    #[case(Theme::BLUE, "8/8/8/8/8/8/8/8")]
+    fn looks_like_board_on_rendered_board(#[case] theme: Theme, #[case] fen: &str) {
+        let options = RenderOptions {
+            theme,
+            ..Default::default()
+        };
+        let (gray, _) = render_gray(fen, &SpriteSet::geometric(64), &options);
+
+        assert!(looks_like_board(&gray, &uniform_geometry(&gray)).unwrap());
+    }
+
+    #[rstest]
+    fn looks_like_board_on_shifted_region() {
+        let (gray, _) = render_gray(
+            INITIAL_PLACEMENT,
+            &SpriteSet::geometric(64),
+            &Default::default(),
+        );
+        let uniform =
+            Mat::new_rows_cols_with_default(512, 512, opencv::core::CV_8UC1, Scalar::all(200.0))
+                .unwrap();
+        // region shifted by half of the square and shrunk, so samples don't line up with squares
+        let shifted = crop_mat(&gray, &(32, 32, 448, 448)).unwrap();
+
+        assert!(!looks_like_board(&uniform, &uniform_geometry(&uniform)).unwrap());
+        assert!(!looks_like_board(&shifted, &uniform_geometry(&shifted)).unwrap());
+    }
+
+    #[rstest]
+    #[case(448, 1.0)]
+    #[case(512, 1.25)]
+    #[case(640, 1.0)]
+    fn find_all_pieces_on_normalized_boards(#[case] size: u32, #[case] scale: f32) {
+        let sprites = SpriteSet::geometric(64);
+        let pieces = rendered_templates(&sprites, &Default::default());
+
+        let options = RenderOptions {
+            size,
+            scale,
+            ..Default::default()
+        };
+        let fen = "r3k2r/8/2n5/3pP3/8/5N2/8/R3K2R";
+        let (gray, expected) = render_gray(fen, &SpriteSet::geometric(size / 8), &options);
+        let board = normalize_board(&gray, &BoardGeometry::detect(&gray).unwrap()).unwrap();
+
+        assert_eq!(board.cols(), 8 * CANONICAL_SQUARE);
+        assert_eq!(
+            find_all_pieces(&board, &pieces, 0.1, 100.0).unwrap(),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case(Theme::BROWN, 24)]
+    #[case(Theme::GREEN, 40)]
+    fn find_all_pieces_on_framed_board(#[case] theme: Theme, #[case] border: u32) {
+        let sprites = SpriteSet::geometric(64);
+        let options = RenderOptions {
+            theme,
+            border,
+            ..Default::default()
+        };
+        let pieces = rendered_templates(&sprites, &options);
+
+        let fen = "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4";
+        let (gray, expected) = render_gray(fen, &sprites, &options);
+        let geometry = BoardGeometry::detect(&gray).unwrap();
+
+        assert_eq!(
+            geometry.bounds(),
+            Rect::new(border as i32, border as i32, 512, 512)
+        );
+        assert!(looks_like_board(&gray, &geometry).unwrap());
+        assert_eq!(
+            find_all_pieces_with_thresholds(
+                &gray,
+                &geometry,
+                &pieces,
+                &Default::default(),
+                0.1,
+                100.0
+            )
+            .unwrap(),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case(Theme::BROWN, Color::White)]
+    #[case(Theme::GREEN, Color::Black)]
+    #[case(Theme::BLUE, Color::White)]
+    fn find_all_pieces_on_rendered_positions(#[case] theme: Theme, #[case] player_color: Color) {
+        let sprites = SpriteSet::geometric(64);
+        let options = RenderOptions {
+            theme,
+            player_color,
+            ..Default::default()
+        };
+        let pieces = rendered_templates(&sprites, &options);
+
+        let mut rng = Rng::new(7);
+        for seed in 0..100 {
+            let fen = random_placement(&mut rng);
+            let options = RenderOptions {
+                noise: 4,
+                seed,
+                ..options.clone()
+            };
+            let (gray, expected) = render_gray(&fen, &sprites, &options);
+
+            let found = find_all_pieces_with_thresholds(
+                &gray,
+                &BoardGeometry::detect(&gray).unwrap(),
+                &pieces,
+                &Default::default(),
+                0.1,
+                100.0,
+            )
+            .unwrap();
+            assert_eq!(found, expected, "position: {fen}");
+        }
+    }
+
+    #[rstest]
+    fn find_all_pieces_prefers_the_best_match() {
+        let sprites = SpriteSet::geometric(64);
+        let options = RenderOptions::default();
+        let mut pieces = rendered_templates(&sprites, &options);
+
+        // slightly damaged copy of the rook template clears the threshold on the same squares
+        let mut similar = pieces[&'R'].try_clone().unwrap();
+        let center = Rect::new(similar.cols() / 2 - 2, similar.rows() / 2 - 2, 4, 4);
+        let value = 255.0 - *similar.at_2d::<u8>(center.y, center.x).unwrap() as f64;
+        similar
+            .roi_mut(center)
+            .unwrap()
+            .set_to(&Scalar::all(value), &Mat::default())
+            .unwrap();
+        pieces.insert('X', Arc::new(similar));
+
+        let fen = "r3k2r/8/2n5/3pP3/8/5N2/8/R3K2R";
+        let (gray, expected) = render_gray(fen, &sprites, &options);
+        for _ in 0..5 {
+            let found = find_all_pieces(&gray, &pieces, 0.1, 100.0).unwrap();
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[rstest]
+    fn detect_move_on_rendered_boards() {
+        let sprites = SpriteSet::geometric(64);
+        let options = RenderOptions::default();
+        let pieces = rendered_templates(&sprites, &options);
+
+        let (before, _) = render_gray(INITIAL_PLACEMENT, &sprites, &options);
+        let (after, _) = render_gray(
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1",
+            &sprites,
+            &options,
+        );
+        assert_eq!(
+            changed_squares(&before, &after, &uniform_geometry(&before), 500).unwrap(),
+            vec![(4, 4), (6, 4)]
+        );
+        assert!(are_images_different(&before, &after, 500).unwrap());
+        assert!(!are_images_different(&before, &before, 500).unwrap());
+
+        let before = find_all_pieces_with_thresholds(
+            &before,
+            &BoardGeometry::detect(&before).unwrap(),
+            &pieces,
+            &Default::default(),
+            0.1,
+            100.0,
+        )
+        .unwrap();
+        let after = find_all_pieces_with_thresholds(
+            &after,
+            &BoardGeometry::detect(&after).unwrap(),
+            &pieces,
+            &Default::default(),
+            0.1,
+            100.0,
+        )
+        .unwrap();
+        let (mv, _) = detect_move(&before, &after, &Color::White).unwrap();
+        assert_eq!(mv, "e2e4");
+    }
 
     #[test]
     fn get_board_region_from_entire_screenshot() {
@@ -435,4 +1085,146 @@ mod tests {
 
         assert_eq!(final_mat.size().unwrap(), ref_mat.size().unwrap());
     }
+
+    #[test]
+    fn get_board_corners_from_entire_screenshot() {
+        let raw = imgcodecs::imread(
+            "templates/boards/original/entire_board.png",
+            imgcodecs::IMREAD_UNCHANGED,
+        )
+        .unwrap();
+
+        let ref_mat = imgcodecs::imread(
+            "templates/boards/original/gray_cropped.png",
+            imgcodecs::IMREAD_UNCHANGED,
+        )
+        .unwrap();
+
+        let mut gray_mat = Mat::default();
+        imgproc::cvt_color(&raw, &mut gray_mat, imgproc::COLOR_RGBA2GRAY, 0).unwrap();
+
+        let coords = quad_bounding_rect(&get_board_corners(&gray_mat).unwrap());
+        let final_mat = crop_mat(&raw, &coords).unwrap();
+
+        assert_eq!(final_mat.size().unwrap(), ref_mat.size().unwrap());
+    }
+
+    fn skew(gray: &Mat, quad: &Corners) -> Mat {
+        let last = (gray.cols() - 1) as f32;
+        let src = opencv::core::Vector::<Point2f>::from_slice(&[
+            Point2f::new(0.0, 0.0),
+            Point2f::new(last, 0.0),
+            Point2f::new(last, last),
+            Point2f::new(0.0, last),
+        ]);
+        let dst = opencv::core::Vector::<Point2f>::from_slice(quad);
+        let transform =
+            imgproc::get_perspective_transform(&src, &dst, opencv::core::DECOMP_LU).unwrap();
+
+        let mut skewed = Mat::default();
+        imgproc::warp_perspective(
+            gray,
+            &mut skewed,
+            &transform,
+            Size::new(640, 640),
+            imgproc::INTER_LINEAR,
+            opencv::core::BORDER_CONSTANT,
+            Scalar::all(50.0),
+        )
+        .unwrap();
+        skewed
+    }
+
+    #[rstest]
+    fn order_corners_of_rotated_quad() {
+        let points = [
+            Point2f::new(570.0, 600.0),
+            Point2f::new(60.0, 40.0),
+            Point2f::new(40.0, 580.0),
+            Point2f::new(590.0, 70.0),
+        ];
+
+        assert_eq!(
+            order_corners(&points),
+            [points[1], points[3], points[0], points[2]]
+        );
+        assert_eq!(
+            quad_bounding_rect(&order_corners(&points)),
+            (40, 40, 550, 560)
+        );
+    }
+
+    #[rstest]
+    fn find_all_pieces_on_warped_board() {
+        let sprites = SpriteSet::geometric(64);
+        let options = RenderOptions::default();
+        let quad = [
+            Point2f::new(60.0, 40.0),
+            Point2f::new(590.0, 70.0),
+            Point2f::new(570.0, 600.0),
+            Point2f::new(40.0, 580.0),
+        ];
+
+        let (initial, _) = render_gray(INITIAL_PLACEMENT, &sprites, &options);
+        let initial = skew(&initial, &quad);
+        let corners = get_board_corners(&initial).unwrap();
+        for (detected, expected) in corners.iter().zip(quad.iter()) {
+            assert!((detected.x - expected.x).abs() <= 3.0, "{corners:?}");
+            assert!((detected.y - expected.y).abs() <= 3.0, "{corners:?}");
+        }
+
+        let board = warp_board(&initial, &corners).unwrap();
+        assert_eq!(board.cols(), board.rows());
+        let pieces = extract_pieces(
+            &board,
+            &BoardGeometry::detect(&board).unwrap(),
+            5,
+            127.0,
+            &Color::White,
+        )
+        .unwrap()
+        .into_iter()
+        .map(|(c, mat)| (c, Arc::new(mat)))
+        .collect();
+
+        let fen = "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4";
+        let (position, expected) = render_gray(fen, &sprites, &options);
+        let position = warp_board(&skew(&position, &quad), &corners).unwrap();
+
+        let found = find_all_pieces_with_thresholds(
+            &position,
+            &BoardGeometry::detect(&position).unwrap(),
+            &pieces,
+            &Default::default(),
+            0.1,
+            100.0,
+        )
+        .unwrap();
+        assert_eq!(found, expected);
+    }
+
+    #[rstest]
+    fn normalize_lighting_evens_out_gradient() {
+        let (mut gray, _) = render_gray(
+            "8/8/8/8/8/8/8/8",
+            &SpriteSet::geometric(64),
+            &Default::default(),
+        );
+        let cols = gray.cols() as usize;
+        for (i, pixel) in gray.data_bytes_mut().unwrap().iter_mut().enumerate() {
+            let factor = 0.6 + 0.4 * (i % cols) as f64 / cols as f64;
+            *pixel = (*pixel as f64 * factor) as u8;
+        }
+        let brightness_ratio = |mat: &Mat| {
+            let left =
+                opencv::core::mean(&crop_mat(mat, &(0, 0, 64, 512)).unwrap(), &Mat::default());
+            let right =
+                opencv::core::mean(&crop_mat(mat, &(448, 0, 64, 512)).unwrap(), &Mat::default());
+            left.unwrap()[0] / right.unwrap()[0]
+        };
+
+        let normalized = normalize_lighting(&gray).unwrap();
+        assert!(brightness_ratio(&normalized) > brightness_ratio(&gray) + 0.1);
+        assert!(looks_like_board(&normalized, &uniform_geometry(&normalized)).unwrap());
+    }
 }
@@ -0,0 +1,356 @@
+// Recognition of the position square by square. Every square is scored against
+// every template and against an empty square, so besides the winning piece the
+// result tells how sure the recognizer was (score of the runner-up).
+use super::geometry::BoardGeometry;
+use super::procimg::{convert_board_to_bin, Mat};
+use super::procimg::{find_all_pieces, find_all_pieces_with_thresholds};
+use crate::utils::error::CheatessResult;
+use crate::utils::parser::{ImgProcArgs, Recognition};
+use opencv::{
+    core::{min_max_loc, Point, Rect},
+    imgproc,
+    prelude::*,
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Symbol of the empty square.
+pub const EMPTY: char = ' ';
+/// Part of the square (at every side) ignored when checking if it's empty (grid lines, edges of pieces).
+const EMPTY_INSET: f64 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SquareScore {
+    pub piece: char,
+    /// Score of the winner: 0 is a perfect match, lower is better.
+    pub score: f64,
+    pub runner_up: char,
+    pub runner_up_score: f64,
+}
+
+impl SquareScore {
+    /// Difference between scores of the runner-up and the winner (higher is more confident).
+    pub fn confidence(&self) -> f64 {
+        self.runner_up_score - self.score
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecognitionResult {
+    squares: [[SquareScore; 8]; 8],
+}
+
+impl RecognitionResult {
+    /// Array board with winners of all squares.
+    pub fn board(&self) -> [[char; 8]; 8] {
+        self.squares.map(|row| row.map(|square| square.piece))
+    }
+
+    pub fn square(&self, row: usize, col: usize) -> &SquareScore {
+        &self.squares[row][col]
+    }
+
+    /// Returns row, column and score of the square the recognizer is the least sure about.
+    pub fn least_confident(&self) -> (usize, usize, SquareScore) {
+        (0..64)
+            .map(|i| (i / 8, i % 8, self.squares[i / 8][i % 8]))
+            .min_by(|a, b| a.2.confidence().total_cmp(&b.2.confidence()))
+            .expect("board has 64 squares")
+    }
+}
+
+impl std::fmt::Display for RecognitionResult {
+    /// Matrix of winners with their confidence, empty squares are marked with a dot.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for row in &self.squares {
+            let cells = row
+                .iter()
+                .map(|square| {
+                    let piece = if square.piece == EMPTY {
+                        '.'
+                    } else {
+                        square.piece
+                    };
+                    format!("{piece} {:.2}", square.confidence())
+                })
+                .collect::<Vec<String>>();
+            writeln!(f, "{}", cells.join(" | "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Crops every square of the board and scores it against every template and against
+/// an empty square. Unlike `find_all_pieces_with_thresholds`, every square gets exactly
+/// one winner.
+pub fn classify_squares(
+    gray_board: &Mat,
+    geometry: &BoardGeometry,
+    pieces: &HashMap<char, Arc<Mat>>,
+    board_threshold: f64,
+) -> CheatessResult<RecognitionResult> {
+    let bin_board = convert_board_to_bin(gray_board, board_threshold)?;
+
+    let mut squares = [[SquareScore {
+        piece: EMPTY,
+        score: 0.0,
+        runner_up: EMPTY,
+        runner_up_score: 0.0,
+    }; 8]; 8];
+    for (row, squares_row) in squares.iter_mut().enumerate() {
+        for (col, square) in squares_row.iter_mut().enumerate() {
+            *square = classify_square(&bin_board, geometry.square(row, col), pieces)?;
+        }
+    }
+
+    Ok(RecognitionResult { squares })
+}
+
+/// Classifies only the given squares, other squares are copied from the previous board.
+pub fn classify_changed_squares(
+    gray_board: &Mat,
+    geometry: &BoardGeometry,
+    pieces: &HashMap<char, Arc<Mat>>,
+    board_threshold: f64,
+    previous: &[[char; 8]; 8],
+    squares: &[(usize, usize)],
+) -> CheatessResult<[[char; 8]; 8]> {
+    let bin_board = convert_board_to_bin(gray_board, board_threshold)?;
+
+    let mut board = *previous;
+    for &(row, col) in squares {
+        let square = classify_square(&bin_board, geometry.square(row, col), pieces)?;
+        log::trace!("Square ({row}, {col}): {square:?}");
+        board[row][col] = square.piece;
+    }
+    Ok(board)
+}
+
+/// Scores the square of the binary board against every template and an empty square.
+fn classify_square(
+    bin_board: &Mat,
+    square: Rect,
+    pieces: &HashMap<char, Arc<Mat>>,
+) -> CheatessResult<SquareScore> {
+    let cropped = Mat::roi(bin_board, square)?;
+
+    let mut scores = vec![(EMPTY, empty_score(&cropped)?)];
+    for (piece, template) in pieces {
+        scores.push((*piece, template_score(&cropped, template)?));
+    }
+    scores.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+
+    // without templates the empty square has no runner-up, so the confidence is zero
+    let (piece, score) = scores[0];
+    let (runner_up, runner_up_score) = scores.get(1).copied().unwrap_or((piece, score));
+    Ok(SquareScore {
+        piece,
+        score,
+        runner_up,
+        runner_up_score,
+    })
+}
+
+/// Recognizes pieces on the normalized board with the method selected by the user.
+pub fn recognize(
+    board: &Mat,
+    pieces: &HashMap<char, Arc<Mat>>,
+    args: &ImgProcArgs,
+) -> CheatessResult<[[char; 8]; 8]> {
+    let geometry = BoardGeometry::canonical();
+    match args.recognition {
+        Recognition::Sliding => {
+            let piece_thresholds = args.piece_thresholds();
+            if piece_thresholds.is_empty() {
+                find_all_pieces(board, pieces, args.piece_threshold, args.board_threshold)
+            } else {
+                find_all_pieces_with_thresholds(
+                    board,
+                    &geometry,
+                    pieces,
+                    &piece_thresholds,
+                    args.piece_threshold,
+                    args.board_threshold,
+                )
+            }
+        }
+        Recognition::Squares => {
+            let result = classify_squares(board, &geometry, pieces, args.board_threshold)?;
+            let (row, col, square) = result.least_confident();
+            log::debug!("Recognition confidence:\n{result}");
+            log::debug!("Least confident square ({row}, {col}): {square:?}");
+            Ok(result.board())
+        }
+    }
+}
+
+/// Wraps templates, so they can be shared by recognition threads.
+pub fn to_shared(pieces: HashMap<char, Mat>) -> HashMap<char, Arc<Mat>> {
+    pieces
+        .into_iter()
+        .map(|(c, mat)| (c, Arc::new(mat)))
+        .collect()
+}
+
+/// Part of pixels (inside the square) which differ from the dominant color.
+fn empty_score(square: &Mat) -> CheatessResult<f64> {
+    let (dx, dy) = (
+        (square.cols() as f64 * EMPTY_INSET) as i32,
+        (square.rows() as f64 * EMPTY_INSET) as i32,
+    );
+    let inner = Mat::roi(
+        square,
+        Rect::new(dx, dy, square.cols() - 2 * dx, square.rows() - 2 * dy),
+    )?;
+
+    let total = (inner.rows() * inner.cols()) as f64;
+    let white = opencv::core::count_non_zero(&inner)? as f64;
+    Ok(white.min(total - white) / total)
+}
+
+/// The best (lowest) normalized squared difference of the template placed anywhere in the square.
+fn template_score(square: &Mat, template: &Mat) -> CheatessResult<f64> {
+    if template.cols() > square.cols() || template.rows() > square.rows() {
+        return Ok(1.0);
+    }
+
+    let mut matched = Mat::default();
+    imgproc::match_template(
+        square,
+        template,
+        &mut matched,
+        imgproc::TM_SQDIFF_NORMED,
+        &Mat::default(),
+    )?;
+
+    let mut min_val = 0.0;
+    min_max_loc(
+        &matched,
+        Some(&mut min_val),
+        None,
+        None::<&mut Point>,
+        None,
+        &Mat::default(),
+    )?;
+    Ok(if min_val.is_nan() { 1.0 } else { min_val })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::engine::Color;
+    use crate::core::render::{random_placement, RenderOptions, Rng, SpriteSet, Theme};
+    use crate::core::testing::{render_gray, rendered_templates};
+    use crate::core::tuning::INITIAL_PLACEMENT;
+    use clap::Parser;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(Theme::BROWN, Color::White)]
+    #[case(Theme::GREEN, Color::Black)]
+    fn classify_squares_on_rendered_positions(#[case] theme: Theme, #[case] player_color: Color) {
+        let sprites = SpriteSet::geometric(64);
+        let options = RenderOptions {
+            theme,
+            player_color,
+            ..Default::default()
+        };
+        let pieces = rendered_templates(&sprites, &options);
+
+        let mut rng = Rng::new(11);
+        for seed in 0..30 {
+            let fen = random_placement(&mut rng);
+            let options = RenderOptions {
+                noise: 4,
+                seed,
+                ..options.clone()
+            };
+            let (gray, expected) = render_gray(&fen, &sprites, &options);
+
+            let result =
+                classify_squares(&gray, &BoardGeometry::canonical(), &pieces, 100.0).unwrap();
+            assert_eq!(result.board(), expected, "position: {fen}\n{result}");
+            assert!(result.least_confident().2.confidence() > 0.0, "{result}");
+        }
+    }
+
+    #[rstest]
+    #[case(Recognition::Sliding)]
+    #[case(Recognition::Squares)]
+    fn recognize_with_selected_method(#[case] recognition: Recognition) {
+        let sprites = SpriteSet::geometric(64);
+        let options = RenderOptions::default();
+        let pieces = rendered_templates(&sprites, &options);
+        let (gray, expected) = render_gray(
+            "r3k2r/pp3ppp/2n5/3qp3/8/2N2N2/PPP2PPP/R2QK2R",
+            &sprites,
+            &options,
+        );
+        let args = ImgProcArgs {
+            recognition,
+            ..ImgProcArgs::parse_from(["imgproc"])
+        };
+
+        assert_eq!(recognize(&gray, &pieces, &args).unwrap(), expected);
+    }
+
+    #[rstest]
+    fn classify_squares_reports_runner_up() {
+        let sprites = SpriteSet::geometric(64);
+        let options = RenderOptions::default();
+        let pieces = rendered_templates(&sprites, &options);
+        let (gray, _) = render_gray("4k3/8/8/8/8/8/8/4K2R", &sprites, &options);
+
+        let result = classify_squares(&gray, &BoardGeometry::canonical(), &pieces, 100.0).unwrap();
+        let rook = result.square(7, 7);
+        assert_eq!(rook.piece, 'R');
+        assert_ne!(rook.runner_up, 'R');
+        assert!(rook.runner_up_score >= rook.score);
+        assert_eq!(result.square(4, 4).piece, EMPTY);
+    }
+
+    #[rstest]
+    fn classify_only_changed_squares() {
+        let sprites = SpriteSet::geometric(64);
+        let options = RenderOptions::default();
+        let pieces = rendered_templates(&sprites, &options);
+        let (_, previous) = render_gray(INITIAL_PLACEMENT, &sprites, &options);
+        let (after, expected) = render_gray(
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR",
+            &sprites,
+            &options,
+        );
+        let classify = |previous: &[[char; 8]; 8]| {
+            classify_changed_squares(
+                &after,
+                &BoardGeometry::canonical(),
+                &pieces,
+                100.0,
+                previous,
+                &[(4, 4), (6, 4)],
+            )
+            .unwrap()
+        };
+
+        assert_eq!(classify(&previous), expected);
+
+        // squares which weren't classified are kept from the previous board
+        let mut stale = previous;
+        stale[0][0] = 'Q';
+        assert_eq!(classify(&stale)[0][0], 'Q');
+    }
+
+    #[rstest]
+    fn classify_squares_without_templates() {
+        let (gray, _) = render_gray(
+            INITIAL_PLACEMENT,
+            &SpriteSet::geometric(64),
+            &RenderOptions::default(),
+        );
+
+        let result =
+            classify_squares(&gray, &BoardGeometry::canonical(), &HashMap::new(), 100.0).unwrap();
+        assert_eq!(result.board(), [[EMPTY; 8]; 8]);
+        assert_eq!(result.least_confident().2.confidence(), 0.0);
+    }
+}
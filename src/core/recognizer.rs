@@ -0,0 +1,232 @@
+// Long-lived recognizer of pieces for tracking. Instead of spawning a thread for every
+// template on every frame, each template gets a worker thread for the whole session.
+// Binary board and results of template matching are kept between frames, so their
+// buffers are reused. After a move only the changed squares can be searched.
+use super::geometry::BoardGeometry;
+use super::procimg::{
+    best_matches, convert_board_to_bin_into, find_piece_in_squares, find_piece_location_into, Mat,
+};
+use crate::utils::error::CheatessResult;
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+type Scores = [[Option<f64>; 8]; 8];
+
+struct Job {
+    bin_board: Arc<Mat>,
+    /// Squares to search (the whole board when not given).
+    squares: Option<Arc<Vec<(usize, usize)>>>,
+}
+
+struct Worker {
+    /// Jobs of the worker, closed when the recognizer is dropped.
+    jobs: Sender<Job>,
+    handle: JoinHandle<()>,
+}
+
+/// Finds pieces like `find_all_pieces_with_thresholds` with a pool of worker threads.
+pub struct Recognizer {
+    workers: Vec<Worker>,
+    results: Receiver<(char, CheatessResult<Scores>)>,
+    bin_board: Arc<Mat>,
+    board_threshold: f64,
+}
+
+impl Recognizer {
+    /// Starts one worker per template. Pieces listed in `piece_thresholds` are matched
+    /// with their own threshold instead of the global one.
+    pub fn new(
+        pieces: &HashMap<char, Arc<Mat>>,
+        geometry: &BoardGeometry,
+        piece_thresholds: &HashMap<char, f64>,
+        piece_threshold: f64,
+        board_threshold: f64,
+    ) -> Self {
+        let (results_tx, results) = mpsc::channel();
+        let workers = pieces
+            .iter()
+            .map(|(sign, piece)| {
+                let (jobs, jobs_rx) = mpsc::channel::<Job>();
+                let results = results_tx.clone();
+                let piece = Arc::clone(piece);
+                let geometry = geometry.clone();
+                let sign = *sign;
+                let threshold = piece_thresholds
+                    .get(&sign)
+                    .copied()
+                    .unwrap_or(piece_threshold);
+
+                let handle = thread::spawn(move || {
+                    let mut matched = Mat::default();
+                    for Job { bin_board, squares } in jobs_rx {
+                        let scores = match squares {
+                            Some(squares) => find_piece_in_squares(
+                                &bin_board,
+                                &piece,
+                                &geometry,
+                                threshold,
+                                &squares,
+                                &mut matched,
+                            ),
+                            None => find_piece_location_into(
+                                &bin_board,
+                                &piece,
+                                &geometry,
+                                threshold,
+                                &mut matched,
+                            ),
+                        };
+                        // the board is released before the result is sent, so it can be reused
+                        drop(bin_board);
+                        if results.send((sign, scores)).is_err() {
+                            break;
+                        }
+                    }
+                });
+                Worker { jobs, handle }
+            })
+            .collect();
+
+        Recognizer {
+            workers,
+            results,
+            bin_board: Arc::new(Mat::default()),
+            board_threshold,
+        }
+    }
+
+    /// Recognizes pieces on the normalized gray board.
+    pub fn recognize(&mut self, gray_board: &Mat) -> CheatessResult<[[char; 8]; 8]> {
+        self.run(gray_board, None)
+    }
+
+    /// Recognizes pieces only in the given squares, other squares are copied from the
+    /// previous board.
+    pub fn recognize_squares(
+        &mut self,
+        gray_board: &Mat,
+        previous: &[[char; 8]; 8],
+        squares: &[(usize, usize)],
+    ) -> CheatessResult<[[char; 8]; 8]> {
+        let found = self.run(gray_board, Some(Arc::new(squares.to_vec())))?;
+        let mut board = *previous;
+        for &(row, col) in squares {
+            board[row][col] = found[row][col];
+        }
+        Ok(board)
+    }
+
+    fn run(
+        &mut self,
+        gray_board: &Mat,
+        squares: Option<Arc<Vec<(usize, usize)>>>,
+    ) -> CheatessResult<[[char; 8]; 8]> {
+        // workers release the board before sending results, so it is normally not shared here
+        match Arc::get_mut(&mut self.bin_board) {
+            Some(bin_board) => {
+                convert_board_to_bin_into(gray_board, self.board_threshold, bin_board)?
+            }
+            None => {
+                let mut bin_board = Mat::default();
+                convert_board_to_bin_into(gray_board, self.board_threshold, &mut bin_board)?;
+                self.bin_board = Arc::new(bin_board);
+            }
+        }
+
+        for worker in &self.workers {
+            let job = Job {
+                bin_board: Arc::clone(&self.bin_board),
+                squares: squares.clone(),
+            };
+            worker.jobs.send(job).expect("Recognition worker stopped");
+        }
+
+        // every result is received before an error is returned, so none is left for next frame
+        let found = (0..self.workers.len())
+            .map(|_| self.results.recv().expect("Recognition worker stopped"))
+            .collect::<Vec<_>>();
+        let found = found
+            .into_iter()
+            .map(|(sign, scores)| scores.map(|scores| (sign, scores)))
+            .collect::<CheatessResult<Vec<_>>>()?;
+        Ok(best_matches(found))
+    }
+}
+
+impl Drop for Recognizer {
+    fn drop(&mut self) {
+        for Worker { jobs, handle } in self.workers.drain(..) {
+            drop(jobs);
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::engine::Color;
+    use crate::core::procimg::find_all_pieces;
+    use crate::core::render::{random_placement, RenderOptions, Rng, SpriteSet};
+    use crate::core::testing::{render_gray, rendered_templates};
+    use crate::core::tuning::INITIAL_PLACEMENT;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(Color::White)]
+    #[case(Color::Black)]
+    fn recognizer_matches_find_all_pieces(#[case] player_color: Color) {
+        let sprites = SpriteSet::geometric(64);
+        let options = RenderOptions {
+            player_color,
+            ..Default::default()
+        };
+        let geometry = BoardGeometry::canonical();
+        let pieces = rendered_templates(&sprites, &options);
+
+        let mut recognizer = Recognizer::new(&pieces, &geometry, &HashMap::new(), 0.1, 100.0);
+        let mut rng = Rng::new(3);
+        for seed in 0..20 {
+            let fen = random_placement(&mut rng);
+            let options = RenderOptions {
+                noise: 4,
+                seed,
+                ..options.clone()
+            };
+            let (gray, expected) = render_gray(&fen, &sprites, &options);
+
+            let found = recognizer.recognize(&gray).unwrap();
+            assert_eq!(found, expected, "position: {fen}");
+            assert_eq!(found, find_all_pieces(&gray, &pieces, 0.1, 100.0).unwrap());
+        }
+    }
+
+    #[rstest]
+    fn recognize_only_changed_squares() {
+        let sprites = SpriteSet::geometric(64);
+        let options = RenderOptions::default();
+        let (_, previous) = render_gray(INITIAL_PLACEMENT, &sprites, &options);
+        let geometry = BoardGeometry::canonical();
+        let pieces = rendered_templates(&sprites, &options);
+        let mut recognizer = Recognizer::new(&pieces, &geometry, &HashMap::new(), 0.1, 100.0);
+
+        let fen = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1";
+        let (after, expected) = render_gray(fen, &sprites, &options);
+        assert_eq!(
+            recognizer
+                .recognize_squares(&after, &previous, &[(4, 4), (6, 4)])
+                .unwrap(),
+            expected
+        );
+
+        // squares which weren't searched are kept from the previous board
+        let mut stale = previous;
+        stale[0][0] = 'Q';
+        let found = recognizer
+            .recognize_squares(&after, &stale, &[(4, 4), (6, 4)])
+            .unwrap();
+        assert_eq!(found[0][0], 'Q');
+    }
+}
@@ -0,0 +1,570 @@
+// Synthetic board renderer. Draws a position given as FEN with selected square colours
+// and piece sprites, so recognition can be tested without real screenshots.
+
+use super::engine::{board_from_fen, rotate_board, Color};
+use super::labels::{expected_file, expected_rank};
+use super::ocr::{DOTS, GLYPHS, GLYPH_HEIGHT, GLYPH_WIDTH};
+use crate::utils::error::{CheatessError, CheatessResult};
+use image::{imageops, ImageBuffer, Rgba, RgbaImage};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Colour of the frame drawn around the board.
+pub static FRAME: Rgba<u8> = Rgba([48, 46, 43, 255]);
+
+/// Tint of the squares of the last move (alpha is the strength of the tint).
+pub static LAST_MOVE: Rgba<u8> = Rgba([155, 199, 0, 105]);
+/// Tint of the square of the king in check.
+pub static CHECK: Rgba<u8> = Rgba([255, 0, 0, 128]);
+
+pub static PIECES: [char; 12] = ['K', 'Q', 'R', 'B', 'N', 'P', 'k', 'q', 'r', 'b', 'n', 'p'];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Theme {
+    pub light: Rgba<u8>,
+    pub dark: Rgba<u8>,
+}
+
+impl Theme {
+    pub const BROWN: Theme = Theme {
+        light: Rgba([240, 217, 181, 255]),
+        dark: Rgba([181, 136, 99, 255]),
+    };
+    pub const GREEN: Theme = Theme {
+        light: Rgba([238, 238, 210, 255]),
+        dark: Rgba([118, 150, 86, 255]),
+    };
+    pub const BLUE: Theme = Theme {
+        light: Rgba([222, 227, 230, 255]),
+        dark: Rgba([140, 162, 173, 255]),
+    };
+}
+
+/// Small deterministic xorshift generator, good enough for noise and random positions.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed.max(1))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Returns number from range `0..max`.
+    pub fn below(&mut self, max: u64) -> u64 {
+        self.next_u64() % max
+    }
+}
+
+/// Images of all twelve pieces, with transparent background.
+pub struct SpriteSet {
+    sprites: HashMap<char, RgbaImage>,
+}
+
+impl SpriteSet {
+    /// Loads sprites named `<piece>.png` (e.g. `K.png`, `p.png`) from directory.
+    pub fn load(dir: &Path) -> CheatessResult<Self> {
+        let mut sprites = HashMap::new();
+        for piece in PIECES {
+            let path = dir.join(format!("{piece}.png"));
+            sprites.insert(piece, image::open(&path)?.into_rgba8());
+        }
+        Ok(SpriteSet { sprites })
+    }
+
+    /// Generates simple geometric sprites: white pieces are outlined, black pieces are filled.
+    pub fn geometric(size: u32) -> Self {
+        let sprites = PIECES
+            .iter()
+            .map(|&piece| (piece, draw_geometric_piece(piece, size)))
+            .collect();
+        SpriteSet { sprites }
+    }
+
+    fn get(&self, piece: char) -> CheatessResult<&RgbaImage> {
+        self.sprites
+            .get(&piece)
+            .ok_or(CheatessError::SpriteNotFound(piece))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    /// Size of the board side in pixels (before scaling).
+    pub size: u32,
+    pub theme: Theme,
+    /// Side at the bottom of the board.
+    pub player_color: Color,
+    /// Maximum amplitude of uniform noise added to every channel (0 disables noise).
+    pub noise: u8,
+    /// Rendered board is resized by this factor (simulates page zoom).
+    pub scale: f32,
+    /// Width of the frame drawn around the board in pixels (before scaling).
+    pub border: u32,
+    /// Squares (as seen on the image) tinted as the last move.
+    pub highlights: Vec<(usize, usize)>,
+    /// Square (as seen on the image) tinted as the king in check.
+    pub check: Option<(usize, usize)>,
+    /// Draws ranks in the left column and files in the bottom row, like most sites do.
+    pub labels: bool,
+    pub seed: u64,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            size: 512,
+            theme: Theme::BROWN,
+            player_color: Color::White,
+            noise: 0,
+            scale: 1.0,
+            border: 0,
+            highlights: vec![],
+            check: None,
+            labels: false,
+            seed: 1,
+        }
+    }
+}
+
+/// Renders position from FEN. Returns the image together with the array board
+/// exactly as it is seen on the image (so rotated for black player).
+pub fn render_board(
+    fen: &str,
+    sprites: &SpriteSet,
+    options: &RenderOptions,
+) -> CheatessResult<(RgbaImage, [[char; 8]; 8])> {
+    let board = match options.player_color {
+        Color::White => board_from_fen(fen)?,
+        Color::Black => rotate_board(&board_from_fen(fen)?),
+    };
+
+    let square = options.size / 8;
+    let size = square * 8;
+    let mut image = ImageBuffer::from_fn(size, size, |x, y| {
+        if ((x / square) + (y / square)) % 2 == 0 {
+            options.theme.light
+        } else {
+            options.theme.dark
+        }
+    });
+
+    let tinted = options
+        .highlights
+        .iter()
+        .map(|&square| (square, LAST_MOVE))
+        .chain(options.check.map(|square| (square, CHECK)));
+    for ((row, col), tint) in tinted {
+        for y in row as u32 * square..(row as u32 + 1) * square {
+            for x in col as u32 * square..(col as u32 + 1) * square {
+                let pixel = image.get_pixel_mut(x, y);
+                *pixel = blend(*pixel, tint);
+            }
+        }
+    }
+
+    if options.labels {
+        draw_labels(&mut image, square, options);
+    }
+
+    for (row, line) in board.iter().enumerate() {
+        for (col, &piece) in line.iter().enumerate() {
+            if piece == ' ' {
+                continue;
+            }
+            let sprite = sprites.get(piece)?;
+            let sprite = if sprite.dimensions() == (square, square) {
+                sprite.clone()
+            } else {
+                imageops::resize(sprite, square, square, imageops::FilterType::Triangle)
+            };
+            imageops::overlay(
+                &mut image,
+                &sprite,
+                (col as u32 * square) as i64,
+                (row as u32 * square) as i64,
+            );
+        }
+    }
+
+    if options.border > 0 {
+        let framed = size + 2 * options.border;
+        let mut frame = ImageBuffer::from_pixel(framed, framed, FRAME);
+        imageops::overlay(
+            &mut frame,
+            &image,
+            options.border as i64,
+            options.border as i64,
+        );
+        image = frame;
+    }
+
+    if options.noise > 0 {
+        add_noise(&mut image, options.noise, options.seed);
+    }
+
+    if (options.scale - 1.0).abs() > f32::EPSILON {
+        let scaled = ((image.width() as f32) * options.scale).round().max(8.0) as u32;
+        image = imageops::resize(&image, scaled, scaled, imageops::FilterType::Triangle);
+    }
+
+    Ok((image, board))
+}
+
+/// Blends colour with the tint, alpha of the tint is its opacity.
+fn blend(color: Rgba<u8>, tint: Rgba<u8>) -> Rgba<u8> {
+    let alpha = tint[3] as f32 / 255.0;
+    let mix = |a: u8, b: u8| (a as f32 * (1.0 - alpha) + b as f32 * alpha).round() as u8;
+    Rgba([
+        mix(color[0], tint[0]),
+        mix(color[1], tint[1]),
+        mix(color[2], tint[2]),
+        color[3],
+    ])
+}
+
+/// Draws labels in the colour of the other squares: ranks in the top left corner of the
+/// left column, files in the bottom right corner of the bottom row.
+fn draw_labels(image: &mut RgbaImage, square: u32, options: &RenderOptions) {
+    let scale = (square / 20).max(1);
+    let (width, height) = (GLYPH_WIDTH as u32 * scale, GLYPH_HEIGHT as u32 * scale);
+    let margin = (square / 32).max(1);
+    let color = |row: u32, col: u32| {
+        if (row + col) % 2 == 0 {
+            options.theme.dark
+        } else {
+            options.theme.light
+        }
+    };
+
+    for i in 0..8u32 {
+        let rank = expected_rank(i as usize, &options.player_color);
+        let (x, y) = (margin, i * square + margin);
+        draw_glyph(image, rank, (x, y), scale, color(i, 0));
+
+        let file = expected_file(i as usize, &options.player_color);
+        let (x, y) = (
+            (i + 1) * square - margin - width,
+            8 * square - margin - height,
+        );
+        draw_glyph(image, file, (x, y), scale, color(7, i));
+    }
+}
+
+/// Draws text with the built-in font, glyphs are separated by one (scaled) cell.
+pub fn draw_text(
+    image: &mut RgbaImage,
+    text: &str,
+    (x, y): (u32, u32),
+    scale: u32,
+    color: Rgba<u8>,
+) {
+    for (i, glyph) in text.chars().enumerate() {
+        let x = x + i as u32 * (GLYPH_WIDTH as u32 + 1) * scale;
+        draw_glyph(image, glyph, (x, y), scale, color);
+    }
+}
+
+fn draw_glyph(image: &mut RgbaImage, glyph: char, (x, y): (u32, u32), scale: u32, color: Rgba<u8>) {
+    let Some((_, rows)) = GLYPHS.iter().chain(&DOTS).find(|(c, _)| *c == glyph) else {
+        return;
+    };
+    for (gy, row) in rows.iter().enumerate() {
+        for (gx, _) in row.chars().enumerate().filter(|(_, c)| *c == '#') {
+            for i in 0..scale * scale {
+                let px = x + gx as u32 * scale + i % scale;
+                let py = y + gy as u32 * scale + i / scale;
+                image.put_pixel(px, py, color);
+            }
+        }
+    }
+}
+
+/// Generates placement part of FEN with both kings and a random set of other pieces.
+/// Pawns are never placed on the first and last rank.
+pub fn random_placement(rng: &mut Rng) -> String {
+    let mut board = [[' '; 8]; 8];
+    let mut free: Vec<(usize, usize)> = (0..64).map(|i| (i / 8, i % 8)).collect();
+
+    let mut place = |piece: char, rng: &mut Rng, free: &mut Vec<(usize, usize)>| {
+        let is_pawn = piece.eq_ignore_ascii_case(&'p');
+        let candidates: Vec<usize> = (0..free.len())
+            .filter(|&i| !is_pawn || (free[i].0 != 0 && free[i].0 != 7))
+            .collect();
+        let idx = candidates[rng.below(candidates.len() as u64) as usize];
+        let (row, col) = free.swap_remove(idx);
+        board[row][col] = piece;
+    };
+
+    place('K', rng, &mut free);
+    place('k', rng, &mut free);
+    let others = rng.below(30);
+    for _ in 0..others {
+        let piece = PIECES[rng.below(PIECES.len() as u64) as usize];
+        if piece.eq_ignore_ascii_case(&'k') {
+            continue;
+        }
+        place(piece, rng, &mut free);
+    }
+
+    board
+        .iter()
+        .map(|rank| {
+            let mut out = String::new();
+            let mut empty = 0;
+            for &c in rank {
+                if c == ' ' {
+                    empty += 1;
+                    continue;
+                }
+                if empty > 0 {
+                    out.push_str(&empty.to_string());
+                    empty = 0;
+                }
+                out.push(c);
+            }
+            if empty > 0 {
+                out.push_str(&empty.to_string());
+            }
+            out
+        })
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+fn add_noise(image: &mut RgbaImage, amplitude: u8, seed: u64) {
+    let mut rng = Rng::new(seed);
+    let range = 2 * amplitude as u64 + 1;
+    for pixel in image.pixels_mut() {
+        for channel in pixel.0.iter_mut().take(3) {
+            let delta = rng.below(range) as i16 - amplitude as i16;
+            *channel = (*channel as i16 + delta).clamp(0, 255) as u8;
+        }
+    }
+}
+
+type Shape = fn(f32, f32) -> bool;
+
+fn in_rect(x: f32, y: f32, x0: f32, x1: f32, y0: f32, y1: f32) -> bool {
+    x >= x0 && x <= x1 && y >= y0 && y <= y1
+}
+
+fn in_ellipse(x: f32, y: f32, cx: f32, cy: f32, rx: f32, ry: f32) -> bool {
+    ((x - cx) / rx).powi(2) + ((y - cy) / ry).powi(2) <= 1.0
+}
+
+fn in_polygon(x: f32, y: f32, points: &[(f32, f32)]) -> bool {
+    let mut inside = false;
+    let mut j = points.len() - 1;
+    for (i, &(xi, yi)) in points.iter().enumerate() {
+        let (xj, yj) = points[j];
+        if (yi > y) != (yj > y) && x < (xj - xi) * (y - yi) / (yj - yi) + xi {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+fn piece_shape(piece: char) -> Shape {
+    match piece.to_ascii_lowercase() {
+        'p' => |x, y| {
+            in_ellipse(x, y, 0.5, 0.36, 0.14, 0.14)
+                || in_rect(x, y, 0.43, 0.57, 0.45, 0.66)
+                || in_rect(x, y, 0.3, 0.7, 0.64, 0.78)
+        },
+        'r' => |x, y| {
+            in_rect(x, y, 0.28, 0.72, 0.3, 0.8)
+                || (in_rect(x, y, 0.28, 0.72, 0.18, 0.3)
+                    && !in_rect(x, y, 0.38, 0.45, 0.18, 0.3)
+                    && !in_rect(x, y, 0.55, 0.62, 0.18, 0.3))
+        },
+        'n' => |x, y| in_polygon(x, y, &[(0.3, 0.8), (0.72, 0.8), (0.72, 0.18), (0.25, 0.45)]),
+        'b' => {
+            |x, y| in_ellipse(x, y, 0.5, 0.45, 0.15, 0.27) || in_rect(x, y, 0.32, 0.68, 0.7, 0.8)
+        }
+        'q' => |x, y| {
+            in_polygon(
+                x,
+                y,
+                &[
+                    (0.18, 0.2),
+                    (0.35, 0.45),
+                    (0.5, 0.2),
+                    (0.65, 0.45),
+                    (0.82, 0.2),
+                    (0.72, 0.8),
+                    (0.28, 0.8),
+                ],
+            )
+        },
+        _ => |x, y| {
+            in_rect(x, y, 0.45, 0.55, 0.12, 0.4)
+                || in_rect(x, y, 0.35, 0.65, 0.2, 0.28)
+                || in_rect(x, y, 0.3, 0.7, 0.4, 0.8)
+        },
+    }
+}
+
+fn draw_geometric_piece(piece: char, size: u32) -> RgbaImage {
+    let shape = piece_shape(piece);
+    let outline = (size as f32 / 12.0).max(1.0);
+    let inside = |x: f32, y: f32| shape(x / size as f32, y / size as f32);
+
+    ImageBuffer::from_fn(size, size, |px, py| {
+        let (x, y) = (px as f32 + 0.5, py as f32 + 0.5);
+        if !inside(x, y) {
+            return Rgba([0, 0, 0, 0]);
+        }
+        if piece.is_ascii_lowercase() {
+            return Rgba([20, 20, 20, 255]);
+        }
+
+        let is_border = [
+            (-outline, 0.0),
+            (outline, 0.0),
+            (0.0, -outline),
+            (0.0, outline),
+        ]
+        .iter()
+        .any(|(dx, dy)| !inside(x + dx, y + dy));
+        if is_border {
+            Rgba([10, 10, 10, 255])
+        } else {
+            Rgba([250, 250, 250, 255])
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::engine::{BlackView, Board, DefaultPrinter, WhiteView};
+    use crate::core::tuning::INITIAL_PLACEMENT;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(Color::White, Board::<DefaultPrinter, WhiteView>::default_white().raw)]
+    #[case(Color::Black, Board::<DefaultPrinter, BlackView>::default_black().raw)]
+    fn render_initial_position(#[case] player_color: Color, #[case] expected: [[char; 8]; 8]) {
+        let options = RenderOptions {
+            player_color,
+            ..Default::default()
+        };
+        let (image, board) =
+            render_board(INITIAL_PLACEMENT, &SpriteSet::geometric(64), &options).unwrap();
+
+        assert_eq!(image.dimensions(), (512, 512));
+        assert_eq!(board, expected);
+    }
+
+    #[rstest]
+    #[case(Theme::BROWN)]
+    #[case(Theme::GREEN)]
+    #[case(Theme::BLUE)]
+    fn render_empty_squares_with_theme(#[case] theme: Theme) {
+        let options = RenderOptions {
+            theme,
+            size: 400,
+            ..Default::default()
+        };
+        let (image, _) =
+            render_board("8/8/8/8/8/8/8/8", &SpriteSet::geometric(50), &options).unwrap();
+
+        assert_eq!(*image.get_pixel(0, 0), theme.light);
+        assert_eq!(*image.get_pixel(50, 0), theme.dark);
+        assert_eq!(*image.get_pixel(399, 399), theme.light);
+    }
+
+    #[rstest]
+    fn render_piece_on_square() {
+        let (image, board) = render_board(
+            "8/8/8/8/8/8/8/7k",
+            &SpriteSet::geometric(64),
+            &Default::default(),
+        )
+        .unwrap();
+
+        assert_eq!(board[7][7], 'k');
+        // center of the king body is filled with black
+        assert_eq!(
+            *image.get_pixel(7 * 64 + 32, 7 * 64 + 40),
+            Rgba([20, 20, 20, 255])
+        );
+    }
+
+    #[rstest]
+    fn render_with_scale_and_noise() {
+        let options = RenderOptions {
+            scale: 0.75,
+            noise: 10,
+            ..Default::default()
+        };
+        let (image, _) =
+            render_board(INITIAL_PLACEMENT, &SpriteSet::geometric(64), &options).unwrap();
+
+        assert_eq!(image.dimensions(), (384, 384));
+        let pixel = image.get_pixel(200, 200);
+        assert!((pixel[0] as i16 - Theme::BROWN.light[0] as i16).abs() <= 10);
+    }
+
+    #[rstest]
+    fn render_invalid_fen() {
+        let result = render_board("xyz", &SpriteSet::geometric(64), &Default::default());
+
+        assert!(matches!(result, Err(CheatessError::InvalidFen(_))));
+    }
+
+    #[rstest]
+    fn render_with_missing_sprite() {
+        let sprites = SpriteSet {
+            sprites: HashMap::new(),
+        };
+        let result = render_board("8/8/8/8/8/8/8/7k", &sprites, &Default::default());
+
+        assert!(matches!(result, Err(CheatessError::SpriteNotFound('k'))));
+    }
+
+    #[rstest]
+    fn render_with_loaded_sprites() {
+        let dir = std::env::temp_dir().join(format!("cheatess-sprites-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for piece in PIECES {
+            draw_geometric_piece(piece, 64)
+                .save(dir.join(format!("{piece}.png")))
+                .unwrap();
+        }
+
+        let sprites = SpriteSet::load(&dir).unwrap();
+        let (image, _) = render_board(INITIAL_PLACEMENT, &sprites, &Default::default()).unwrap();
+        let (expected, _) = render_board(
+            INITIAL_PLACEMENT,
+            &SpriteSet::geometric(64),
+            &Default::default(),
+        )
+        .unwrap();
+
+        assert_eq!(image, expected);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[rstest]
+    fn random_placement_is_valid_fen() {
+        let mut rng = Rng::new(42);
+        for _ in 0..1000 {
+            let fen = random_placement(&mut rng);
+            let board = board_from_fen(&fen).unwrap();
+
+            let pieces: Vec<char> = board.iter().flatten().copied().collect();
+            assert_eq!(pieces.iter().filter(|&&c| c == 'K').count(), 1);
+            assert_eq!(pieces.iter().filter(|&&c| c == 'k').count(), 1);
+            assert!(!board[0].contains(&'p') && !board[0].contains(&'P'));
+            assert!(!board[7].contains(&'p') && !board[7].contains(&'P'));
+        }
+    }
+}
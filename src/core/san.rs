@@ -0,0 +1,340 @@
+// Moves in standard algebraic notation (e.g. `Nf3`, `exd5`, `O-O`, `e8=Q+`), as sites
+// list them, converted to the UCI notation used by Stockfish and the game record.
+// Boards are arrays seen from white's side.
+use super::engine::{apply_move, board_from_fen, move_squares, Color};
+use super::tuning::INITIAL_PLACEMENT;
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// Pattern of a move (without castling): piece, disambiguation, capture, target square
+/// and promotion.
+static SAN: LazyLock<Result<Regex, regex::Error>> =
+    LazyLock::new(|| Regex::new(r"^([KQRBN])?([a-h])?([1-8])?(x)?([a-h])([1-8])(?:=?([QRBN]))?$"));
+
+const KNIGHT_STEPS: [(i32, i32); 8] = [
+    (-2, -1),
+    (-2, 1),
+    (-1, -2),
+    (-1, 2),
+    (1, -2),
+    (1, 2),
+    (2, -1),
+    (2, 1),
+];
+const KING_STEPS: [(i32, i32); 8] = [
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+    (0, -1),
+    (0, 1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+];
+const ROOK_DIRECTIONS: [(i32, i32); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+const BISHOP_DIRECTIONS: [(i32, i32); 4] = [(-1, -1), (-1, 1), (1, -1), (1, 1)];
+
+/// Checks if the token looks like a move (it still may be illegal in the position).
+pub fn is_san(token: &str) -> bool {
+    let token = token.trim_end_matches(['+', '#']);
+    castling(token).is_some() || SAN.as_ref().is_ok_and(|san| san.is_match(token))
+}
+
+/// Converts the move of the side to UCI. Returns `None` when the move isn't legal or
+/// it's ambiguous.
+pub fn san_to_uci(board: &[[char; 8]; 8], san: &str, side: &Color) -> Option<String> {
+    let san = san.trim_end_matches(['+', '#']);
+    let home = match side {
+        Color::White => 7,
+        Color::Black => 0,
+    };
+    let own = |piece: char| piece != ' ' && piece.is_ascii_uppercase() == (*side == Color::White);
+
+    if let Some(long) = castling(san) {
+        let (rook_col, to_col, path) = if long { (0, 2, 1..4) } else { (7, 6, 5..7) };
+        let (king, rook) = (board[home][4], board[home][rook_col]);
+        let legal = own(king)
+            && king.eq_ignore_ascii_case(&'k')
+            && own(rook)
+            && rook.eq_ignore_ascii_case(&'r')
+            && path.clone().all(|col| board[home][col] == ' ')
+            && [4, (4 + to_col) / 2, to_col]
+                .iter()
+                .all(|&col| !attacked(board, (home, col), &opponent(side)));
+        return legal.then(|| uci((home, 4), (home, to_col), None));
+    }
+
+    let caps = SAN.as_ref().ok()?.captures(san)?;
+    let piece = caps
+        .get(1)
+        .map_or('P', |m| m.as_str().as_bytes()[0] as char);
+    let from_col = caps
+        .get(2)
+        .map(|m| (m.as_str().as_bytes()[0] - b'a') as usize);
+    let from_row = caps
+        .get(3)
+        .map(|m| (b'8' - m.as_str().as_bytes()[0]) as usize);
+    let capture = caps.get(4).is_some();
+    let to = (
+        (b'8' - caps[6].as_bytes()[0]) as usize,
+        (caps[5].as_bytes()[0] - b'a') as usize,
+    );
+    let promotion = caps.get(7).map(|m| m.as_str().to_ascii_lowercase());
+
+    let target = board[to.0][to.1];
+    if own(target) || capture != (target != ' ' || (piece == 'P' && capture)) {
+        return None;
+    }
+    let last_row = if *side == Color::White { 0 } else { 7 };
+    if (piece == 'P' && to.0 == last_row) != promotion.is_some() {
+        return None;
+    }
+
+    let mut candidates = (0..64)
+        .map(|i| (i / 8, i % 8))
+        .filter(|&(row, col)| {
+            let found = board[row][col];
+            own(found)
+                && found.to_ascii_uppercase() == piece
+                && from_col.is_none_or(|c| c == col)
+                && from_row.is_none_or(|r| r == row)
+        })
+        .filter(|&from| match piece {
+            'P' => pawn_reaches(board, from, to, side, capture),
+            _ => attacks(board, from, to),
+        })
+        .map(|from| uci(from, to, promotion.as_deref()))
+        .filter(|mv| {
+            apply_move(board, mv, &Color::White).is_some_and(|after| !in_check(&after, side))
+        });
+
+    match (candidates.next(), candidates.next()) {
+        (Some(mv), None) => Some(mv),
+        _ => None,
+    }
+}
+
+/// Checks if the move in UCI is legal for the side. En passant is accepted whenever
+/// a pawn of the opponent stands next to the capturing pawn (the previous move isn't known).
+pub fn is_legal(board: &[[char; 8]; 8], uci: &str, side: &Color) -> bool {
+    let Some((from, to)) = move_squares(uci, &Color::White) else {
+        return false;
+    };
+    let own = |piece: char| piece != ' ' && piece.is_ascii_uppercase() == (*side == Color::White);
+    let (piece, target) = (board[from.0][from.1], board[to.0][to.1]);
+    if !own(piece) || own(target) {
+        return false;
+    }
+
+    let last_row = if *side == Color::White { 0 } else { 7 };
+    let reaches = match piece.to_ascii_uppercase() {
+        'P' if (to.0 == last_row) != (uci.len() == 5) => false,
+        'P' if from.1 != to.1 && target == ' ' => {
+            let passed = board[from.0][to.1];
+            !own(passed)
+                && passed.eq_ignore_ascii_case(&'p')
+                && pawn_reaches(board, from, to, side, true)
+        }
+        'P' => pawn_reaches(board, from, to, side, from.1 != to.1),
+        _ if uci.len() != 4 => false,
+        'K' if from.1.abs_diff(to.1) == 2 => {
+            let castling = if to.1 < from.1 { "O-O-O" } else { "O-O" };
+            san_to_uci(board, castling, side).as_deref() == Some(uci)
+        }
+        _ => attacks(board, from, to),
+    };
+    reaches && apply_move(board, uci, &Color::White).is_some_and(|after| !in_check(&after, side))
+}
+
+/// Plays the listed moves from the initial position. Returns the moves in UCI and the
+/// final position, unless some move isn't legal.
+pub fn replay(moves: &[&str]) -> Option<(Vec<String>, [[char; 8]; 8])> {
+    let mut board = board_from_fen(INITIAL_PLACEMENT).ok()?;
+    let mut side = Color::White;
+    let mut played = vec![];
+    for san in moves {
+        let mv = san_to_uci(&board, san, &side)?;
+        board = apply_move(&board, &mv, &Color::White)?;
+        side = opponent(&side);
+        played.push(mv);
+    }
+    Some((played, board))
+}
+
+/// Returns `true` for long castling and `false` for the short one.
+fn castling(token: &str) -> Option<bool> {
+    // some sites write castling with zeros
+    match token.replace('0', "O").as_str() {
+        "O-O" => Some(false),
+        "O-O-O" => Some(true),
+        _ => None,
+    }
+}
+
+fn uci(from: (usize, usize), to: (usize, usize), promotion: Option<&str>) -> String {
+    let square = |(row, col): (usize, usize)| format!("{}{}", (b'a' + col as u8) as char, 8 - row);
+    format!("{}{}{}", square(from), square(to), promotion.unwrap_or(""))
+}
+
+fn opponent(side: &Color) -> Color {
+    match side {
+        Color::White => Color::Black,
+        Color::Black => Color::White,
+    }
+}
+
+/// Checks if the pawn can move to the target square (captures include en passant, which
+/// can't be verified without the previous move).
+fn pawn_reaches(
+    board: &[[char; 8]; 8],
+    (row, col): (usize, usize),
+    (to_row, to_col): (usize, usize),
+    side: &Color,
+    capture: bool,
+) -> bool {
+    let (forward, start) = match side {
+        Color::White => (-1, 6),
+        Color::Black => (1, 1),
+    };
+    let rows = to_row as i32 - row as i32;
+    if capture {
+        return rows == forward && to_col.abs_diff(col) == 1;
+    }
+    let path_empty = |steps: i32| {
+        (1..=steps).all(|step| board[(row as i32 + forward * step) as usize][col] == ' ')
+    };
+    to_col == col
+        && ((rows == forward && path_empty(1))
+            || (rows == 2 * forward && row == start && path_empty(2)))
+}
+
+/// Checks if the (non-pawn) piece attacks the square.
+fn attacks(board: &[[char; 8]; 8], from: (usize, usize), to: (usize, usize)) -> bool {
+    let (rows, cols) = (to.0 as i32 - from.0 as i32, to.1 as i32 - from.1 as i32);
+    let slides = |directions: &[(i32, i32)]| {
+        directions.iter().any(|&(dr, dc)| {
+            (1..8).any(|step| {
+                (dr * step, dc * step) == (rows, cols)
+                    && (1..step).all(|between| {
+                        board[(from.0 as i32 + dr * between) as usize]
+                            [(from.1 as i32 + dc * between) as usize]
+                            == ' '
+                    })
+            })
+        })
+    };
+    match board[from.0][from.1].to_ascii_uppercase() {
+        'N' => KNIGHT_STEPS.contains(&(rows, cols)),
+        'K' => KING_STEPS.contains(&(rows, cols)),
+        'R' => slides(&ROOK_DIRECTIONS),
+        'B' => slides(&BISHOP_DIRECTIONS),
+        'Q' => slides(&ROOK_DIRECTIONS) || slides(&BISHOP_DIRECTIONS),
+        _ => false,
+    }
+}
+
+/// Checks if any piece of the side attacks the square.
+fn attacked(board: &[[char; 8]; 8], square: (usize, usize), by: &Color) -> bool {
+    let forward = match by {
+        Color::White => -1,
+        Color::Black => 1,
+    };
+    (0..64).map(|i| (i / 8, i % 8)).any(|(row, col)| {
+        let piece = board[row][col];
+        if piece == ' ' || piece.is_ascii_uppercase() != (*by == Color::White) {
+            return false;
+        }
+        match piece.to_ascii_uppercase() {
+            'P' => square.0 as i32 - row as i32 == forward && square.1.abs_diff(col) == 1,
+            _ => attacks(board, (row, col), square),
+        }
+    })
+}
+
+fn in_check(board: &[[char; 8]; 8], side: &Color) -> bool {
+    let king = match side {
+        Color::White => 'K',
+        Color::Black => 'k',
+    };
+    (0..64)
+        .map(|i| (i / 8, i % 8))
+        .find(|&(row, col)| board[row][col] == king)
+        .is_some_and(|square| attacked(board, square, &opponent(side)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("e4", Some("e2e4"))]
+    #[case("Nf3", Some("g1f3"))]
+    #[case("Nc3", Some("b1c3"))]
+    #[case("e5", None)]
+    #[case("Bc4", None)]
+    #[case("O-O", None)]
+    #[case("Ke2", None)]
+    fn moves_in_initial_position(#[case] san: &str, #[case] uci: Option<&str>) {
+        let board = board_from_fen(INITIAL_PLACEMENT).unwrap();
+
+        assert_eq!(san_to_uci(&board, san, &Color::White).as_deref(), uci);
+    }
+
+    #[rstest]
+    // both knights reach d2, the file tells which one moved
+    #[case("r3k2r/8/8/8/8/5N2/8/RN2K2R w", "Nbd2", Some("b1d2"))]
+    #[case("r3k2r/8/8/8/8/5N2/8/RN2K2R w", "Nd2", None)]
+    #[case("r3k2r/8/8/8/8/5N2/8/RN2K2R w", "0-0", Some("e1g1"))]
+    #[case("r3k2r/8/8/8/8/5N2/8/RN2K2R w", "O-O-O", None)]
+    #[case("r3k2r/8/8/8/8/5N2/8/RN2K2R b", "O-O-O+", Some("e8c8"))]
+    // the knight on d2 is pinned, so only the one on g1 can go to f3
+    #[case("4k3/8/8/8/1b6/8/3N4/4K1N1 w", "Nf3", Some("g1f3"))]
+    #[case("4k3/1P6/8/3pP3/8/8/8/4K3 w", "exd6", Some("e5d6"))]
+    #[case("4k3/1P6/8/3pP3/8/8/8/4K3 w", "b8=Q+", Some("b7b8q"))]
+    #[case("4k3/1P6/8/3pP3/8/8/8/4K3 w", "b8", None)]
+    fn moves_in_position(#[case] fen: &str, #[case] san: &str, #[case] uci: Option<&str>) {
+        let board = board_from_fen(fen).unwrap();
+        let side = match fen.ends_with('w') {
+            true => Color::White,
+            false => Color::Black,
+        };
+
+        assert_eq!(san_to_uci(&board, san, &side).as_deref(), uci);
+    }
+
+    #[rstest]
+    #[case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w", "g1f3", true)]
+    #[case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w", "f1c4", false)]
+    #[case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w", "e7e5", false)]
+    #[case("4k3/8/8/3pP3/8/8/8/4K3 w", "e5d6", true)]
+    #[case("4k3/8/8/4P3/8/8/8/4K3 w", "e5d6", false)]
+    #[case("4k3/1P6/8/8/8/8/8/4K3 w", "b7b8q", true)]
+    #[case("4k3/1P6/8/8/8/8/8/4K3 w", "b7b8", false)]
+    #[case("r3k2r/8/8/8/8/8/8/R3K2R b", "e8c8", true)]
+    #[case("4k3/8/8/8/1b6/8/3N4/4K3 w", "d2f3", false)]
+    fn legal_moves(#[case] fen: &str, #[case] uci: &str, #[case] legal: bool) {
+        let board = board_from_fen(fen).unwrap();
+        let side = match fen.ends_with('w') {
+            true => Color::White,
+            false => Color::Black,
+        };
+
+        assert_eq!(is_legal(&board, uci, &side), legal);
+    }
+
+    #[rstest]
+    fn replay_listed_moves() {
+        let (moves, board) = replay(&["e4", "e5", "Nf3", "Nc6", "Bb5", "a6", "O-O"]).unwrap();
+
+        assert_eq!(
+            moves,
+            ["e2e4", "e7e5", "g1f3", "b8c6", "f1b5", "a7a6", "e1g1"]
+        );
+        assert_eq!(
+            board,
+            board_from_fen("r1bqkbnr/1ppp1ppp/p1n5/1B2p3/4P3/5N2/PPPP1PPP/RNBQ1RK1").unwrap()
+        );
+        assert_eq!(replay(&["e4", "e4"]), None);
+    }
+}
@@ -0,0 +1,246 @@
+// Reading of the panel which sites show next to the board: names (and ratings) of both
+// players in the bars above and below the board and the list of moves in standard
+// algebraic notation right of it. The list lets a session started in the middle of the
+// game get the moves made before and is compared with moves detected on the board.
+use super::clock::{search_regions, CLOCK_PATTERN};
+use super::ocr::{read_lines, text_mask, UNKNOWN};
+use super::procimg::Mat;
+use super::san::is_san;
+use crate::utils::error::CheatessResult;
+use opencv::{core::Rect, prelude::*};
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// Number of the move in the list: `12.` before the move of white, `12...` before the
+/// move of black (when the list starts with it) or just `12`.
+static MOVE_NUMBER: LazyLock<Result<Regex, regex::Error>> =
+    LazyLock::new(|| Regex::new(r"^(\d{1,3})(\.*)$"));
+static RESULT: LazyLock<Result<Regex, regex::Error>> = LazyLock::new(|| Regex::new(r"^(1-0|0-1)$"));
+static RATING: LazyLock<Result<Regex, regex::Error>> =
+    LazyLock::new(|| Regex::new(r"^\(?(\d{3,4})\)?$"));
+/// Clock shown next to the name of the player.
+static CLOCK: LazyLock<Result<Regex, regex::Error>> =
+    LazyLock::new(|| Regex::new(&format!("^(?:{CLOCK_PATTERN})$")));
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Player {
+    pub name: String,
+    pub rating: Option<u32>,
+}
+
+/// Players and moves as listed by the site.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Sidebar {
+    /// Player at the top of the board.
+    pub top: Option<Player>,
+    /// Player at the bottom of the board.
+    pub bottom: Option<Player>,
+    /// Listed moves with their index in the game (0 for the first move of white).
+    pub moves: Vec<(usize, String)>,
+}
+
+impl Sidebar {
+    /// Listed move with the index in the game.
+    pub fn move_at(&self, ply: usize) -> Option<&str> {
+        self.moves
+            .iter()
+            .find(|(i, _)| *i == ply)
+            .map(|(_, san)| san.as_str())
+    }
+
+    /// Moves listed from the start of the game up to the first gap (none when the list
+    /// is scrolled past the first move).
+    pub fn moves_from_start(&self) -> Vec<&str> {
+        (0..).map_while(|ply| self.move_at(ply)).collect()
+    }
+}
+
+/// Reads players and moves around the board region `(x, y, w, h)` of the gray screen.
+pub fn read_sidebar(gray_screen: &Mat, board: &(u32, u32, u32, u32)) -> CheatessResult<Sidebar> {
+    let screen = gray_screen.size()?;
+    let (x, y, w, h) = (
+        board.0 as i32,
+        board.1 as i32,
+        board.2 as i32,
+        board.3 as i32,
+    );
+    let panel = Rect::new(x + w, y, w, h) & Rect::new(0, 0, screen.width, screen.height);
+    let moves = if panel.width > 0 && panel.height > 0 {
+        parse_moves(&read_region(gray_screen, &panel)?)?
+    } else {
+        vec![]
+    };
+
+    let sidebar = Sidebar {
+        top: read_player(gray_screen, &search_regions(board, screen, true))?,
+        bottom: read_player(gray_screen, &search_regions(board, screen, false))?,
+        moves,
+    };
+    log::debug!("Read sidebar: {sidebar:?}");
+    Ok(sidebar)
+}
+
+fn read_region(gray_screen: &Mat, region: &Rect) -> CheatessResult<Vec<String>> {
+    read_lines(&text_mask(&Mat::roi(gray_screen, *region)?)?)
+}
+
+/// Returns the first player found in the regions.
+fn read_player(gray_screen: &Mat, regions: &[Rect]) -> CheatessResult<Option<Player>> {
+    for region in regions {
+        for line in read_region(gray_screen, region)? {
+            if is_move_line(&line)? {
+                continue;
+            }
+            if let Some(player) = parse_player(&line)? {
+                return Ok(Some(player));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Lines of the move list consist only of move numbers, moves and the result.
+fn is_move_line(line: &str) -> CheatessResult<bool> {
+    let number = MOVE_NUMBER.as_ref().map_err(Clone::clone)?;
+    let result = RESULT.as_ref().map_err(Clone::clone)?;
+    let mut tokens = line.split_whitespace().peekable();
+    Ok(tokens.peek().is_some()
+        && line.split_whitespace().any(is_san)
+        && tokens.all(|token| number.is_match(token) || result.is_match(token) || is_san(token)))
+}
+
+/// Collects moves of the list with their index in the game, which is counted from the
+/// move numbers. Moves before the first number are left out.
+pub fn parse_moves(lines: &[String]) -> CheatessResult<Vec<(usize, String)>> {
+    let number = MOVE_NUMBER.as_ref().map_err(Clone::clone)?;
+    let mut moves: Vec<(usize, String)> = vec![];
+    let mut ply = None;
+    for line in lines {
+        if !is_move_line(line)? {
+            continue;
+        }
+        for token in line.split_whitespace() {
+            if let Some(caps) = number.captures(token) {
+                let Some(move_number) = caps[1].parse::<usize>().ok().filter(|n| *n > 0) else {
+                    ply = None;
+                    continue;
+                };
+                let black = caps[2].len() >= 2;
+                ply = Some(2 * (move_number - 1) + black as usize);
+            } else if let Some(index) = ply.filter(|_| is_san(token)) {
+                if moves.iter().all(|(i, _)| *i != index) {
+                    moves.push((index, token.to_string()));
+                }
+                ply = Some(index + 1);
+            }
+        }
+    }
+    Ok(moves)
+}
+
+/// Parses the name with an optional rating (e.g. `Magnus (2850)`), a clock shown next to
+/// them is left out. Names with glyphs which couldn't be read are left out as well.
+pub fn parse_player(line: &str) -> CheatessResult<Option<Player>> {
+    let clock = CLOCK.as_ref().map_err(Clone::clone)?;
+    let rating_pattern = RATING.as_ref().map_err(Clone::clone)?;
+    let mut words = line
+        .split_whitespace()
+        .filter(|word| !clock.is_match(word))
+        .collect::<Vec<_>>();
+
+    let rating = match words.as_slice() {
+        [_, .., last] => rating_pattern
+            .captures(last)
+            .and_then(|caps| caps[1].parse::<u32>().ok()),
+        _ => None,
+    };
+    if rating.is_some() {
+        words.pop();
+    }
+
+    let name = words.join(" ");
+    let readable = name.chars().any(|c| c.is_ascii_alphabetic()) && !name.contains(UNKNOWN);
+    Ok(readable.then_some(Player { name, rating }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::procimg::image_buffer_to_gray_mat;
+    use crate::core::render::draw_text;
+    use image::{ImageBuffer, Rgba};
+    use rstest::rstest;
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[rstest]
+    #[case(&["1. e4 e5", "2. Nf3 Nc6", "3. Bb5"], &[(0, "e4"), (1, "e5"), (2, "Nf3"), (3, "Nc6"), (4, "Bb5")])]
+    #[case(&["Magnus (2850)", "12... O-O", "13. Qxd8+ Rxd8 1-0"], &[(23, "O-O"), (24, "Qxd8+"), (25, "Rxd8")])]
+    #[case(&["e4 e5", "7 Bc4", "10:05"], &[(12, "Bc4")])]
+    fn parse_listed_moves(#[case] read: &[&str], #[case] expected: &[(usize, &str)]) {
+        let moves = parse_moves(&lines(read)).unwrap();
+
+        assert_eq!(
+            moves,
+            expected
+                .iter()
+                .map(|(ply, san)| (*ply, san.to_string()))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[rstest]
+    #[case("Magnus (2850)", Some(("Magnus", Some(2850))))]
+    #[case("hikaru 2790 3:05", Some(("hikaru", Some(2790))))]
+    #[case("GM Magnus", Some(("GM Magnus", None)))]
+    #[case("1500", None)]
+    #[case("ma?nus", None)]
+    fn parse_player_cases(#[case] line: &str, #[case] expected: Option<(&str, Option<u32>)>) {
+        let expected = expected.map(|(name, rating)| Player {
+            name: name.to_string(),
+            rating,
+        });
+
+        assert_eq!(parse_player(line).unwrap(), expected);
+    }
+
+    #[rstest]
+    fn read_sidebar_around_the_board() {
+        let board = (100, 100, 400, 400);
+        let mut screen = ImageBuffer::from_pixel(800, 650, Rgba([40, 40, 40, 255]));
+        for (x, y) in (100..500).flat_map(|x| (100..500).map(move |y| (x, y))) {
+            screen.put_pixel(x, y, Rgba([150, 150, 150, 255]));
+        }
+        let white = Rgba([230, 230, 230, 255]);
+        draw_text(&mut screen, "Magnus (2850) 4:59", (110, 65), 3, white);
+        draw_text(&mut screen, "hikaru 2790", (110, 515), 3, white);
+        for (i, line) in ["1. e4 e5", "2. Nf3 Nc6", "3. Bb5"].iter().enumerate() {
+            draw_text(&mut screen, line, (520, 120 + i as u32 * 22), 2, white);
+        }
+
+        let gray = image_buffer_to_gray_mat(screen).unwrap();
+        let sidebar = read_sidebar(&gray, &board).unwrap();
+
+        assert_eq!(
+            sidebar.top,
+            Some(Player {
+                name: "Magnus".to_string(),
+                rating: Some(2850)
+            })
+        );
+        assert_eq!(
+            sidebar.bottom,
+            Some(Player {
+                name: "hikaru".to_string(),
+                rating: Some(2790)
+            })
+        );
+        assert_eq!(
+            sidebar.moves_from_start(),
+            ["e4", "e5", "Nf3", "Nc6", "Bb5"]
+        );
+        assert_eq!(sidebar.move_at(3), Some("Nc6"));
+    }
+}
@@ -394,7 +394,7 @@ impl Stockfish {
         panic!()
     }
 
-    fn set_fen_position(&mut self, fen: &str, token: bool) {
+    pub fn set_fen_position(&mut self, fen: &str, token: bool) {
         self.prepare_for_new_position(token);
         self._put(&format!("position fen {fen}"));
     }
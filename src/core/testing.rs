@@ -0,0 +1,35 @@
+// Helpers shared by tests which recognize boards drawn by the synthetic renderer.
+
+use super::geometry::BoardGeometry;
+use super::procimg::{extract_pieces, image_buffer_to_gray_mat, Mat};
+use super::recognition::to_shared;
+use super::render::{render_board, RenderOptions, SpriteSet};
+use super::tuning::INITIAL_PLACEMENT;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Renders the position and converts it to gray. The board is returned as drawn, i.e.
+/// seen from the side of `options.player_color`.
+pub fn render_gray(
+    fen: &str,
+    sprites: &SpriteSet,
+    options: &RenderOptions,
+) -> (Mat, [[char; 8]; 8]) {
+    let (image, board) = render_board(fen, sprites, options).unwrap();
+    (image_buffer_to_gray_mat(image).unwrap(), board)
+}
+
+/// Templates extracted from the initial position rendered with given sprites and options.
+pub fn rendered_templates(sprites: &SpriteSet, options: &RenderOptions) -> HashMap<char, Arc<Mat>> {
+    let (initial, _) = render_gray(INITIAL_PLACEMENT, sprites, options);
+    to_shared(
+        extract_pieces(
+            &initial,
+            &BoardGeometry::detect(&initial).unwrap(),
+            5,
+            127.0,
+            &options.player_color,
+        )
+        .unwrap(),
+    )
+}
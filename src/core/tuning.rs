@@ -0,0 +1,435 @@
+// Automatic tuning of image processing parameters against a known position on the
+// screen. Templates (unless given) are extracted and the board is binarized with
+// every combination of parameters from the search space, then piece thresholds are
+// derived from scores of template matching, so `find_all_pieces_with_thresholds`
+// reproduces the position exactly.
+use super::engine::{rotate_board, Color};
+use super::geometry::BoardGeometry;
+use super::procimg::{
+    convert_board_to_bin, dark_pixel_counts, extract_pieces, find_piece_location, Mat,
+};
+use super::recognition::EMPTY;
+use crate::utils::error::CheatessResult;
+use opencv::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::thread;
+
+/// Piece placement of the initial position (default known position).
+pub const INITIAL_PLACEMENT: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR";
+
+/// Matches with worse scores are never accepted, so they don't have to be collected.
+const MAX_SCORE: f64 = 0.5;
+
+/// Values of parameters tried by `tune`.
+#[derive(Debug, Clone)]
+pub struct SearchSpace {
+    pub margins: Vec<u8>,
+    pub extract_piece_thresholds: Vec<f64>,
+    pub board_thresholds: Vec<f64>,
+}
+
+impl Default for SearchSpace {
+    fn default() -> Self {
+        SearchSpace {
+            margins: vec![3, 4, 5, 6, 7],
+            extract_piece_thresholds: vec![110.0, 127.0, 150.0],
+            board_thresholds: vec![70.0, 85.0, 100.0, 115.0, 130.0],
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Tuning {
+    /// `None` when templates were given (not extracted).
+    pub margin: Option<u8>,
+    /// `None` when templates were given (not extracted).
+    pub extract_piece_threshold: Option<f64>,
+    pub board_threshold: f64,
+    pub piece_threshold: f64,
+    /// Pieces for which the global threshold doesn't reproduce the position.
+    pub piece_thresholds: Vec<(char, f64)>,
+    /// `None` when occupied and empty squares can't be told apart by the number of dark pixels.
+    pub difference_level: Option<i32>,
+    /// Smallest gap (among pieces) between the worst wanted and the best unwanted match.
+    pub separation: f64,
+    pub pieces: HashMap<char, Mat>,
+}
+
+/// Range of thresholds of a single piece which reproduce the position: the piece is found
+/// on all its squares (scores are below `threshold`) and on no other square
+/// (scores are at least `threshold`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Window {
+    /// The worst score of the piece on its own squares.
+    wanted: f64,
+    /// The best score of the piece on squares where it would be wrongly recognized.
+    unwanted: f64,
+}
+
+impl Window {
+    fn width(&self) -> f64 {
+        self.unwanted - self.wanted
+    }
+
+    fn middle(&self) -> f64 {
+        (self.wanted + self.unwanted) / 2.0
+    }
+
+    fn contains(&self, threshold: f64) -> bool {
+        self.wanted < threshold && threshold <= self.unwanted
+    }
+}
+
+/// Searches for parameters reproducing the `expected` position (as seen on the screen)
+/// on the normalized board. Templates are extracted from the board (which has to be in
+/// the initial position then) unless `templates` are given. Returns `None` when no
+/// combination works.
+pub fn tune(
+    gray_board: &Mat,
+    expected: &[[char; 8]; 8],
+    player_color: &Color,
+    templates: Option<&HashMap<char, Mat>>,
+    space: &SearchSpace,
+    fallback_piece_threshold: f64,
+) -> CheatessResult<Option<Tuning>> {
+    let geometry = BoardGeometry::canonical();
+    let mut best: Option<Tuning> = None;
+
+    let extraction = match templates {
+        Some(_) => vec![None],
+        None => space
+            .margins
+            .iter()
+            .flat_map(|&m| {
+                space
+                    .extract_piece_thresholds
+                    .iter()
+                    .map(move |&e| Some((m, e)))
+            })
+            .collect(),
+    };
+    for extraction in extraction {
+        let pieces = match extraction {
+            Some((margin, extract_piece_threshold)) => extract_pieces(
+                gray_board,
+                &geometry,
+                margin,
+                extract_piece_threshold,
+                player_color,
+            )?,
+            None => templates
+                .into_iter()
+                .flatten()
+                .map(|(c, mat)| Ok((*c, mat.try_clone()?)))
+                .collect::<CheatessResult<HashMap<_, _>>>()?,
+        };
+        let (margin, extract_piece_threshold) = extraction.unzip();
+        let shared = pieces
+            .iter()
+            .map(|(c, mat)| Ok((*c, Arc::new(mat.try_clone()?))))
+            .collect::<CheatessResult<HashMap<_, _>>>()?;
+
+        for &board_threshold in &space.board_thresholds {
+            let scores = piece_scores(gray_board, &geometry, &shared, board_threshold)?;
+            let Some(windows) = piece_windows(&scores, expected) else {
+                log::debug!(
+                    "margin={margin:?} extract_piece_threshold={extract_piece_threshold:?} board_threshold={board_threshold}: position not reproduced"
+                );
+                continue;
+            };
+
+            let separation = windows
+                .values()
+                .map(Window::width)
+                .fold(f64::INFINITY, f64::min);
+            log::debug!(
+                "margin={margin:?} extract_piece_threshold={extract_piece_threshold:?} board_threshold={board_threshold}: separation {separation:.4}"
+            );
+            if best.as_ref().is_some_and(|b| b.separation >= separation) {
+                continue;
+            }
+
+            let (piece_threshold, piece_thresholds) =
+                choose_thresholds(&windows, fallback_piece_threshold);
+            best = Some(Tuning {
+                margin,
+                extract_piece_threshold,
+                board_threshold,
+                piece_threshold,
+                piece_thresholds,
+                difference_level: None,
+                separation,
+                pieces: pieces
+                    .iter()
+                    .map(|(c, mat)| Ok((*c, mat.try_clone()?)))
+                    .collect::<CheatessResult<_>>()?,
+            });
+        }
+    }
+
+    if let Some(tuning) = best.as_mut() {
+        let counts = dark_pixel_counts(gray_board, &geometry)?;
+        tuning.difference_level = difference_level(&counts, expected);
+    }
+    Ok(best)
+}
+
+/// Returns player color for which the known position (from white's side) matches more
+/// squares of the recognized one (as seen on the screen).
+pub fn infer_orientation(recognized: &[[char; 8]; 8], white_view: &[[char; 8]; 8]) -> Color {
+    let matching = |board: &[[char; 8]; 8]| {
+        (0..64)
+            .filter(|i| board[i / 8][i % 8] == recognized[i / 8][i % 8])
+            .count()
+    };
+    if matching(&rotate_board(white_view)) > matching(white_view) {
+        Color::Black
+    } else {
+        Color::White
+    }
+}
+
+/// Scores of every piece (below `MAX_SCORE`) on every square.
+fn piece_scores(
+    gray_board: &Mat,
+    geometry: &BoardGeometry,
+    pieces: &HashMap<char, Arc<Mat>>,
+    board_threshold: f64,
+) -> CheatessResult<HashMap<char, [[Option<f64>; 8]; 8]>> {
+    let bin_board = Arc::new(convert_board_to_bin(gray_board, board_threshold)?);
+
+    let handles = pieces
+        .iter()
+        .map(|(sign, piece)| {
+            let board = Arc::clone(&bin_board);
+            let piece = Arc::clone(piece);
+            let geometry = geometry.clone();
+            let sign = *sign;
+            thread::spawn(move || {
+                find_piece_location(&board, &piece, &geometry, MAX_SCORE).map(|s| (sign, s))
+            })
+        })
+        .collect::<Vec<_>>();
+
+    handles
+        .into_iter()
+        .map(|handle| handle.join().expect("Failed to find piece location"))
+        .collect()
+}
+
+/// Computes the window of thresholds of every piece. A piece is unwanted on an empty square
+/// and on a square of other piece it beats. Returns `None` when a piece isn't found on its
+/// square or its window is empty.
+fn piece_windows(
+    scores: &HashMap<char, [[Option<f64>; 8]; 8]>,
+    expected: &[[char; 8]; 8],
+) -> Option<HashMap<char, Window>> {
+    let mut windows = HashMap::new();
+    for (&piece, piece_scores) in scores {
+        let mut window = Window {
+            wanted: 0.0,
+            unwanted: MAX_SCORE,
+        };
+        for (row, col) in (0..8).flat_map(|row| (0..8).map(move |col| (row, col))) {
+            let expected_piece = expected[row][col];
+            let score = piece_scores[row][col];
+            if expected_piece == piece {
+                window.wanted = window.wanted.max(score?);
+                continue;
+            }
+
+            let Some(score) = score else {
+                continue;
+            };
+            let beats_expected = expected_piece == EMPTY
+                || scores
+                    .get(&expected_piece)
+                    .and_then(|s| s[row][col])
+                    .is_none_or(|expected_score| score <= expected_score);
+            if beats_expected {
+                window.unwanted = window.unwanted.min(score);
+            }
+        }
+
+        if window.width() <= 0.0 {
+            log::trace!("No threshold of {piece} reproduces the position: {window:?}");
+            return None;
+        }
+        windows.insert(piece, window);
+    }
+    Some(windows)
+}
+
+/// Chooses the global threshold in the middle of the common part of all windows (or keeps
+/// `fallback` when there is none) and per-piece thresholds for windows which don't contain it.
+fn choose_thresholds(windows: &HashMap<char, Window>, fallback: f64) -> (f64, Vec<(char, f64)>) {
+    let common = Window {
+        wanted: windows.values().map(|w| w.wanted).fold(0.0, f64::max),
+        unwanted: windows
+            .values()
+            .map(|w| w.unwanted)
+            .fold(MAX_SCORE, f64::min),
+    };
+    let global = if common.width() > 0.0 {
+        round(common.middle())
+    } else {
+        fallback
+    };
+
+    let mut piece_thresholds = windows
+        .iter()
+        .filter(|(_, window)| !window.contains(global))
+        .map(|(piece, window)| (*piece, round(window.middle())))
+        .collect::<Vec<_>>();
+    piece_thresholds.sort_by_key(|(piece, _)| *piece);
+    (global, piece_thresholds)
+}
+
+/// Difference level in the middle between the most dark pixels of an empty square and the
+/// fewest of an occupied one.
+fn difference_level(counts: &[[i32; 8]; 8], expected: &[[char; 8]; 8]) -> Option<i32> {
+    let squares = (0..64).map(|i| (expected[i / 8][i % 8], counts[i / 8][i % 8]));
+    let empty = squares.clone().filter(|(piece, _)| *piece == EMPTY);
+    let occupied = squares.filter(|(piece, _)| *piece != EMPTY);
+
+    let most_empty = empty.map(|(_, count)| count).max().unwrap_or(0);
+    let fewest_occupied = occupied.map(|(_, count)| count).min()?;
+    (most_empty < fewest_occupied).then_some((most_empty + fewest_occupied) / 2)
+}
+
+/// Rounds the threshold to 4 decimal places, so it's readable when printed.
+fn round(threshold: f64) -> f64 {
+    (threshold * 10000.0).round() / 10000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::procimg::find_all_pieces_with_thresholds;
+    use crate::core::recognition::to_shared;
+    use crate::core::render::{RenderOptions, SpriteSet, Theme};
+    use crate::core::testing::render_gray;
+    use rstest::rstest;
+
+    fn reproduces(tuning: &Tuning, gray: &Mat, expected: &[[char; 8]; 8]) {
+        let found = find_all_pieces_with_thresholds(
+            gray,
+            &BoardGeometry::canonical(),
+            &to_shared(tuning.pieces.clone()),
+            &tuning.piece_thresholds.iter().copied().collect(),
+            tuning.piece_threshold,
+            tuning.board_threshold,
+        )
+        .unwrap();
+        assert_eq!(&found, expected, "{tuning:?}");
+    }
+
+    #[rstest]
+    #[case(Theme::BROWN, Color::White)]
+    #[case(Theme::GREEN, Color::Black)]
+    fn tune_on_initial_position(#[case] theme: Theme, #[case] player_color: Color) {
+        let sprites = SpriteSet::geometric(64);
+        let options = RenderOptions {
+            theme,
+            player_color,
+            noise: 4,
+            ..Default::default()
+        };
+        let (gray, expected) = render_gray(INITIAL_PLACEMENT, &sprites, &options);
+
+        let tuning = tune(
+            &gray,
+            &expected,
+            &player_color,
+            None,
+            &SearchSpace::default(),
+            0.1,
+        )
+        .unwrap()
+        .expect("initial position should be reproduced");
+
+        assert!(tuning.separation > 0.0);
+        assert!(tuning.difference_level.is_some());
+        reproduces(&tuning, &gray, &expected);
+    }
+
+    #[rstest]
+    fn tune_with_templates_on_known_position() {
+        let sprites = SpriteSet::geometric(64);
+        let options = RenderOptions::default();
+        let (initial, _) = render_gray(INITIAL_PLACEMENT, &sprites, &options);
+        let templates = extract_pieces(
+            &initial,
+            &BoardGeometry::canonical(),
+            5,
+            127.0,
+            &Color::White,
+        )
+        .unwrap();
+        let (gray, expected) = render_gray(
+            "r3k2r/pp3ppp/2n5/3qp3/8/2N2Q2/PPP2PPP/R3K2R",
+            &sprites,
+            &options,
+        );
+
+        let tuning = tune(
+            &gray,
+            &expected,
+            &Color::White,
+            Some(&templates),
+            &SearchSpace::default(),
+            0.1,
+        )
+        .unwrap()
+        .expect("position should be reproduced");
+        reproduces(&tuning, &gray, &expected);
+    }
+
+    #[rstest]
+    fn choose_thresholds_with_overrides() {
+        let window = |wanted, unwanted| Window { wanted, unwanted };
+        let mut windows = HashMap::from([('K', window(0.02, 0.3)), ('p', window(0.01, 0.2))]);
+        assert_eq!(choose_thresholds(&windows, 0.1), (0.11, vec![]));
+
+        // pawn and queen have no common threshold, the king accepts the fallback one
+        windows.insert('q', window(0.25, 0.4));
+        assert_eq!(choose_thresholds(&windows, 0.1), (0.1, vec![('q', 0.325)]));
+    }
+
+    #[rstest]
+    #[case(Color::White)]
+    #[case(Color::Black)]
+    fn infer_orientation_of_known_position(#[case] player_color: Color) {
+        let white_view =
+            crate::core::engine::board_from_fen("r3k2r/pp3ppp/2n5/3qp3/8/2N2Q2/PPP2PPP/R3K2R")
+                .unwrap();
+        let mut recognized = match player_color {
+            Color::White => white_view,
+            Color::Black => rotate_board(&white_view),
+        };
+        // a few misrecognized squares don't change the orientation
+        recognized[0][0] = EMPTY;
+        recognized[4][4] = 'Q';
+
+        assert_eq!(infer_orientation(&recognized, &white_view), player_color);
+    }
+
+    #[rstest]
+    #[case(&[(0, 900), (7, 400)], 300, Some(350))]
+    #[case(&[(0, 900), (7, 200)], 300, None)]
+    fn difference_level_cases(
+        #[case] occupied: &[(usize, i32)],
+        #[case] empty_count: i32,
+        #[case] expected: Option<i32>,
+    ) {
+        let mut board = [[EMPTY; 8]; 8];
+        let mut counts = [[empty_count; 8]; 8];
+        for &(row, count) in occupied {
+            board[row][0] = 'K';
+            counts[row][0] = count;
+        }
+
+        assert_eq!(difference_level(&counts, &board), expected);
+    }
+}
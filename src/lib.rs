@@ -1,13 +1,30 @@
 pub mod core;
 pub mod utils;
 
+pub use core::clock;
 pub use core::engine;
+pub use core::geometry;
+pub use core::highlight;
+pub use core::history;
+pub use core::labels;
+pub use core::ocr;
+pub use core::orientation;
 pub use core::procimg;
+pub use core::recognition;
+pub use core::recognizer;
+pub use core::san;
+pub use core::sidebar;
 pub use core::stockfish;
+pub use core::tuning;
 
+pub use utils::calibration;
+pub use utils::debounce;
+pub use utils::library;
 pub use utils::logger;
 pub use utils::monitor;
 pub use utils::parser;
+pub use utils::scheduler;
+pub use utils::source;
 
 #[allow(unused_imports)]
 pub use utils::printer;
@@ -1,10 +1,13 @@
 use std::io;
-use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 mod core;
+mod tracker;
 mod utils;
 
+use opencv::prelude::MatTraitConst;
+use tracker::clear_screen;
+
 fn main() -> utils::error::CheatessResult<()> {
     let env_args: Vec<String> = std::env::args().collect();
     let args = utils::parser::parse_args_from(env_args);
@@ -14,148 +17,283 @@ fn main() -> utils::error::CheatessResult<()> {
     match args.mode {
         utils::parser::Mode::Game => game(args),
         utils::parser::Mode::Test => config_mode(args),
+        utils::parser::Mode::Templates => templates_mode(args),
+        utils::parser::Mode::Tune => tune_mode(args),
     }
 }
 
-fn game(args: utils::parser::CheatessArgs) -> utils::error::CheatessResult<()> {
+fn game(mut args: utils::parser::CheatessArgs) -> utils::error::CheatessResult<()> {
     clear_screen();
 
-    let mut stdout = io::stdout();
-    let mut sf = core::stockfish::Stockfish::new(&args.stockfish.path, args.stockfish.depth);
-    sf.set_config(
-        &args.stockfish.elo.to_string(),
-        &args.stockfish.skill.to_string(),
-        &args.stockfish.hash.to_string(),
-        &args.stockfish.pv.to_string(),
-    )?;
-
-    let monitor =
-        utils::monitor::select_monitor(args.monitor.name).expect("Requested monitor not found");
-    let raw = utils::monitor::capture_entire_screen(&monitor)?; // ~30ms
-    let raw_gray = core::procimg::image_buffer_to_gray_mat(raw)?; // ~5ms
-    let coords = core::procimg::get_board_region(&raw_gray)?; // ~10ms
-
-    let board = core::procimg::crop_mat(&raw_gray, &coords)?; // ~1ms
-    let player_color = core::procimg::detect_player_color(&board)?; // ~0.1ms
-    log::info!("Detected player color: {player_color:?}");
-
-    let base_board: Box<dyn core::engine::AnyBoard> = if args.engine.pretty {
-        core::engine::create_board_default::<core::engine::PrettyPrinter>(&player_color)
+    let calibration = load_calibration(&mut args)?;
+    let mut source = open_source(&args)?;
+    let raw = first_frame(source.as_mut())?; // ~30ms
+    let raw = core::procimg::image_buffer_to_color_mat(raw)?;
+    let raw_gray = core::procimg::color_to_gray(&raw)?; // ~5ms
+    let regions = if let Some(region) = args.proc_image.manual_region() {
+        vec![(region, manual_corners(&args.proc_image))]
+    } else if let Some(calibration) = &calibration {
+        vec![(calibration.region, calibration.corners)]
     } else {
-        core::engine::create_board_default::<core::engine::DefaultPrinter>(&player_color)
+        detect_boards(&raw_gray, &args.proc_image)? // ~10ms
     };
-    base_board.print(&mut stdout);
+    if regions.is_empty() {
+        return Err(utils::error::CheatessError::BoardNotFound);
+    }
 
-    let pieces = core::procimg::extract_pieces(
-        &board,
-        args.proc_image.margin,
-        args.proc_image.extract_piece_threshold,
-        &player_color,
-    )?;
-    let pieces = pieces
+    let multi_board = regions.len() > 1;
+    let pieces = match &args.templates.load {
+        Some(name) => Some(args.templates.library().load(name)?),
+        None => calibration.map(|c| c.pieces),
+    };
+    let mut trackers = regions
         .into_iter()
-        .map(|(c, mat)| (c, Arc::new(mat)))
-        .collect();
-
-    let mut prev_board_mat = board;
-    let mut prev_board_arr = base_board;
-    for (i, sum) in sf.summary(args.stockfish.pv)?.iter().enumerate() {
-        log_stockfish_summary(i, sum);
+        .enumerate()
+        .map(|(i, (coords, corners))| {
+            tracker::BoardTracker::new(
+                i + 1,
+                coords,
+                corners,
+                &raw,
+                &args,
+                multi_board,
+                pieces.as_ref().map(clone_templates).transpose()?,
+            )
+        })
+        .collect::<utils::error::CheatessResult<Vec<_>>>()?;
+
+    // calibration is saved once the board is verified by a detected move
+    let mut save_calibration = args.proc_image.save_calibration.as_ref();
+    if save_calibration.is_some() && multi_board {
+        log::warn!("Calibration can be saved only for a single board");
+        save_calibration = None;
     }
+    if let Some(name) = &args.templates.save {
+        match trackers.as_slice() {
+            [tracker] => args.templates.library().save(name, &tracker.templates()?)?,
+            _ => log::warn!("Templates can be saved only for a single board"),
+        }
+    }
+
+    // recorded sources are analyzed as fast as possible
+    let live = matches!(
+        args.input.source,
+        utils::parser::Source::Monitor | utils::parser::Source::Window
+    );
+    let mut scheduler = utils::scheduler::FrameScheduler::new(
+        args.input.target_fps,
+        args.input.idle_fps,
+        Duration::from_millis(args.input.budget),
+    );
 
     loop {
         let start = Instant::now();
-        let cropped =
-            utils::monitor::get_cropped_screen(&monitor, coords.0, coords.1, coords.2, coords.3)?; // ~15ms
-        let gray_board = core::procimg::image_buffer_to_gray_mat(cropped)?; // ~1ms
-
-        if !core::procimg::are_images_different(
-            &prev_board_mat,
-            &gray_board,
-            args.proc_image.difference_level,
-        )? {
-            continue;
-        }
 
-        let new_raw_board = core::procimg::find_all_pieces(
-            &gray_board,
-            &pieces,
-            args.proc_image.piece_threshold,
-            args.proc_image.board_threshold,
-        )?;
-        log::trace!("Pieces detection: {:?}", start.elapsed());
-        log::trace!(
-            "OpenCV matchTemplate result: {}",
-            utils::printer::raw_board_to_string(&new_raw_board)
-        );
-
-        let detected_move =
-            core::engine::detect_move(prev_board_arr.raw(), &new_raw_board, &player_color);
-
-        match detected_move {
-            Ok((mv, mv_type)) => {
-                log::info!("Detected move: {mv:?} [{mv_type:?}]");
-                sf.make_move(vec![mv])?;
+        // text is read next to the board, so the whole screen is captured then; recorded
+        // frames are kept whole, so a lost board is searched for on the same frame
+        let frame = match trackers.as_slice() {
+            [tracker] if live && tracker.corners.is_none() && !args.proc_image.reads_screen() => {
+                let coords = tracker.coords;
+                match source.next_region(coords.0, coords.1, coords.2, coords.3)? {
+                    Some(cropped) => Some((
+                        None,
+                        vec![core::procimg::image_buffer_to_color_mat(cropped)?],
+                    )), // ~15ms + ~1ms
+                    None => None,
+                }
             }
-            Err(e) => {
-                log::error!("{e}");
-                continue;
+            _ => match source.next_frame()? {
+                Some(frame) => {
+                    let raw = core::procimg::image_buffer_to_color_mat(frame)?;
+                    let boards = trackers
+                        .iter()
+                        .map(|t| t.crop(&raw))
+                        .collect::<utils::error::CheatessResult<Vec<_>>>()?;
+                    Some((Some(raw), boards))
+                }
+                None => None,
+            },
+        };
+
+        let Some((raw, boards)) = frame else {
+            log::info!("No more frames to analyze");
+            if save_calibration.is_some() {
+                log::warn!("Calibration isn't saved, no move was detected");
+            }
+            scheduler.log_stats();
+            trackers.iter().for_each(|t| t.log_history());
+            return Ok(());
+        };
+
+        let mut changed = false;
+        let mut lost = vec![];
+        let mut finished = vec![];
+        for (i, (tracker, board)) in trackers.iter_mut().zip(boards).enumerate() {
+            let screen = raw.as_ref().filter(|_| args.proc_image.reads_screen());
+            let state =
+                tracker.update(board, screen, source.timestamp(), source.is_continuous())?;
+            changed |= state != tracker::BoardState::Unchanged;
+            match state {
+                tracker::BoardState::Unchanged
+                | tracker::BoardState::Changed
+                | tracker::BoardState::Settling => {}
+                tracker::BoardState::Lost => lost.push(i),
+                tracker::BoardState::GameOver => finished.push(i),
             }
         }
-        clear_screen();
 
-        let curr_board: Box<dyn core::engine::AnyBoard> = if args.engine.pretty {
-            core::engine::create_board_from_data::<core::engine::PrettyPrinter>(
-                new_raw_board,
-                &player_color,
+        if let Some(dir) = save_calibration.filter(|_| trackers[0].is_verified()) {
+            utils::calibration::Calibration::new(
+                &args,
+                trackers[0].coords,
+                trackers[0].corners,
+                trackers[0].templates()?,
             )
-        } else {
-            core::engine::create_board_from_data::<core::engine::DefaultPrinter>(
-                new_raw_board,
-                &player_color,
-            )
-        };
-        curr_board.print(&mut stdout);
+            .save(dir)?;
+            save_calibration = None;
+        }
 
-        for (i, sum) in sf.summary(args.stockfish.pv)?.iter().enumerate() {
-            if sum.main_line.is_empty() {
-                log::info!("Game over");
-                return Ok(());
+        if !lost.is_empty() {
+            // only the region of the board was captured from the live screen
+            let raw = match raw {
+                Some(raw) => raw,
+                None => match source.next_frame()? {
+                    Some(frame) => core::procimg::image_buffer_to_color_mat(frame)?,
+                    None => continue,
+                },
+            };
+            let mut quads = core::procimg::get_board_quads(&core::procimg::color_to_gray(&raw)?)?;
+            // boards which are still tracked can't be taken by the lost ones
+            quads.retain(|quad| {
+                let region = core::procimg::quad_bounding_rect(quad);
+                trackers
+                    .iter()
+                    .enumerate()
+                    .all(|(i, t)| lost.contains(&i) || t.coords != region)
+            });
+
+            for i in lost {
+                // every board is taken by a single tracker
+                match closest_board(&quads, &trackers[i].coords) {
+                    Some(index) => {
+                        let quad = quads.remove(index);
+                        trackers[i].relocate(
+                            core::procimg::quad_bounding_rect(&quad),
+                            args.proc_image.perspective.then_some(quad),
+                            &raw,
+                        )?;
+                        // the frame may already show a move made while the board was moved
+                        let screen = Some(&raw).filter(|_| args.proc_image.reads_screen());
+                        let board = trackers[i].crop(&raw)?;
+                        let state = trackers[i].update(
+                            board,
+                            screen,
+                            source.timestamp(),
+                            source.is_continuous(),
+                        )?;
+                        if state == tracker::BoardState::GameOver {
+                            finished.push(i);
+                        }
+                    }
+                    None => log::warn!("Board not found on the screen, trying again"),
+                }
             }
-            log_stockfish_summary(i, sum);
         }
-        prev_board_arr = curr_board;
-        prev_board_mat = gray_board;
-        log::debug!("Cycle time: {:?}", start.elapsed());
+
+        finished.sort_unstable();
+        for i in finished.into_iter().rev() {
+            trackers.remove(i);
+        }
+        if trackers.is_empty() {
+            scheduler.log_stats();
+            return Ok(());
+        }
+
+        let elapsed = start.elapsed();
+        let wait = scheduler.finish_cycle(elapsed, changed);
+        log::debug!("Cycle time: {elapsed:?}, next capture in {wait:?}");
+        if live {
+            std::thread::sleep(wait);
+        }
     }
 }
 
-fn log_stockfish_summary(iter: usize, summary: &core::stockfish::Summary) {
-    fn format_moves(moves: &[String]) -> String {
-        moves
-            .chunks(2)
-            .enumerate()
-            .map(|(i, chunk)| {
-                let m1 = chunk.get(0).cloned().unwrap_or_default();
-                let m2 = chunk.get(1).cloned().unwrap_or_default();
-                format!("{}. {} {}", i + 1, m1, m2)
-            })
-            .collect::<Vec<String>>()
-            .join(" ")
+/// Copies saved templates, so every tracked board gets its own set.
+fn clone_templates(
+    pieces: &std::collections::HashMap<char, core::procimg::Mat>,
+) -> utils::error::CheatessResult<std::collections::HashMap<char, core::procimg::Mat>> {
+    pieces
+        .iter()
+        .map(|(c, mat)| Ok((*c, mat.try_clone()?)))
+        .collect()
+}
+
+/// Loads calibration requested by the user and applies calibrated values to the arguments.
+fn load_calibration(
+    args: &mut utils::parser::CheatessArgs,
+) -> utils::error::CheatessResult<Option<utils::calibration::Calibration>> {
+    let Some(dir) = &args.proc_image.calibration else {
+        return Ok(None);
+    };
+    let calibration = utils::calibration::Calibration::load(dir)?;
+    calibration.apply(args);
+    Ok(Some(calibration))
+}
+
+/// Detects boards on the (gray) screen: all of them or only the largest one. Corners are
+/// kept only if boards should be perspective-corrected.
+fn detect_boards(
+    raw_gray: &core::procimg::Mat,
+    args: &utils::parser::ImgProcArgs,
+) -> utils::error::CheatessResult<Vec<((u32, u32, u32, u32), Option<core::procimg::Corners>)>> {
+    if !args.multi_board && !args.perspective {
+        return Ok(vec![(core::procimg::get_board_region(raw_gray)?, None)]);
     }
+    let quads = if args.multi_board {
+        core::procimg::get_board_quads(raw_gray)?
+    } else {
+        vec![core::procimg::get_board_corners(raw_gray)?]
+    };
 
-    log::info!(
-        "\n\
-    ┌────────────── Stockfish line #{iter} ──────────────────\n\
-    │ Evaluation : {}\n\
-    │ Line       : {}\n\
-    └─────────────────────────────────────────────────────",
-        summary.eval,
-        format_moves(&summary.main_line)
-    );
+    Ok(quads
+        .into_iter()
+        .map(|quad| {
+            (
+                core::procimg::quad_bounding_rect(&quad),
+                args.perspective.then_some(quad),
+            )
+        })
+        .collect())
+}
+
+/// Returns corners of the board given by the user (if any).
+fn manual_corners(args: &utils::parser::ImgProcArgs) -> Option<core::procimg::Corners> {
+    let points = args
+        .corners
+        .as_ref()?
+        .iter()
+        .map(|(x, y)| opencv::core::Point2f::new(*x as f32, *y as f32))
+        .collect::<Vec<_>>();
+    Some(core::procimg::order_corners(&points))
+}
+
+/// Selects board whose center is the closest to the center of the previous region and
+/// returns its index.
+fn closest_board(
+    quads: &[core::procimg::Corners],
+    previous: &(u32, u32, u32, u32),
+) -> Option<usize> {
+    let center =
+        |r: &(u32, u32, u32, u32)| (r.0 as i64 + r.2 as i64 / 2, r.1 as i64 + r.3 as i64 / 2);
+    let (px, py) = center(previous);
+
+    (0..quads.len()).min_by_key(|&i| {
+        let (x, y) = center(&core::procimg::quad_bounding_rect(&quads[i]));
+        (x - px).pow(2) + (y - py).pow(2)
+    })
 }
 
-fn config_mode(args: utils::parser::CheatessArgs) -> utils::error::CheatessResult<()> {
+fn config_mode(mut args: utils::parser::CheatessArgs) -> utils::error::CheatessResult<()> {
     log::info!("Welcome to the interactive test setup for cheatess. Follow the instructions to ensure everything works correctly while playing.");
 
     log::info!("\n[Step 1/7] Collected invoke parameters:");
@@ -163,31 +301,46 @@ fn config_mode(args: utils::parser::CheatessArgs) -> utils::error::CheatessResul
     log::info!("{:?}", args.engine);
     log::info!("{:?}", args.proc_image);
     log::info!("{:?}", args.stockfish);
+    log::info!("{:?}", args.input);
+    let calibration = load_calibration(&mut args)?;
 
     log::info!("\n[Step 2/7] Now you will see the following images: entire screen in grayscale and cropped board from previus image");
     log::info!("To get next image, press '0'");
 
-    let monitor =
-        utils::monitor::select_monitor(args.monitor.name).expect("Requested monitor not found");
-    let raw = utils::monitor::capture_entire_screen(&monitor)?;
+    let mut source = open_source(&args)?;
+    let raw = first_frame(source.as_mut())?;
     let raw_gray = core::procimg::image_buffer_to_gray_mat(raw)?;
     core::procimg::show(&raw_gray, true, "Entire screen")?;
 
-    let coords = core::procimg::get_board_region(&raw_gray)?;
-    let board = core::procimg::crop_mat(&raw_gray, &coords)?;
+    let (coords, corners) = match (args.proc_image.manual_region(), &calibration) {
+        (Some(region), _) => (region, manual_corners(&args.proc_image)),
+        (None, Some(calibration)) => (calibration.region, calibration.corners),
+        (None, None) => detect_boards(&raw_gray, &args.proc_image)?.remove(0),
+    };
+    let board = core::procimg::extract_board(&raw_gray, &coords, corners.as_ref())?;
     core::procimg::show(&board, true, "Cropped board")?;
 
-    let player_color = core::procimg::detect_player_color(&board)?;
-    log::warn!("\n[Step 3/7] Detected player color: {player_color:?}");
+    let raw_geometry = core::geometry::BoardGeometry::detect(&board)?;
+    log::info!("Detected squares: {:?}", raw_geometry.bounds());
+    let board = core::procimg::normalize_board(&board, &raw_geometry)?;
+    let geometry = core::geometry::BoardGeometry::canonical();
+    log::warn!("\n[Step 3/7] Player color:");
+    let orientation = tracker::detect_orientation("", &board, None)?;
+    let player_color = tracker::player_color("", orientation, &args)?;
 
     log::info!("\n[Step 4/7] Now you will see all extracted pieces from board, please check if every is clear");
     log::info!("If image is bad, you can improve it by change imgproc arguments: margin (-m) and extract_piece_threshold (-e)");
-    let pieces = core::procimg::extract_pieces(
-        &board,
-        args.proc_image.margin,
-        args.proc_image.extract_piece_threshold,
-        &player_color,
-    )?;
+    let pieces = match (&args.templates.load, calibration) {
+        (Some(name), _) => args.templates.library().load(name)?,
+        (None, Some(calibration)) => calibration.pieces,
+        (None, None) => core::procimg::extract_pieces(
+            &board,
+            &geometry,
+            args.proc_image.margin,
+            args.proc_image.extract_piece_threshold,
+            &player_color,
+        )?,
+    };
 
     for (sign, mat) in &pieces {
         core::procimg::show(mat, true, &format!("Extracted piece: {sign}"))?;
@@ -198,17 +351,9 @@ fn config_mode(args: utils::parser::CheatessArgs) -> utils::error::CheatessResul
     core::procimg::show(&bin_board, true, "Binary board")?;
 
     log::info!("[Step 6/7] Now check if every piece is correctly placed");
-    let pieces = pieces
-        .into_iter()
-        .map(|(c, mat)| (c, Arc::new(mat)))
-        .collect();
+    let pieces = core::recognition::to_shared(pieces);
 
-    let raw_board = core::procimg::find_all_pieces(
-        &board,
-        &pieces,
-        args.proc_image.piece_threshold,
-        args.proc_image.board_threshold,
-    )?;
+    let raw_board = core::recognition::recognize(&board, &pieces, &args.proc_image)?;
 
     let calc_board: Box<dyn core::engine::AnyBoard> = if args.engine.pretty {
         core::engine::create_board_from_data::<core::engine::PrettyPrinter>(
@@ -231,9 +376,15 @@ fn config_mode(args: utils::parser::CheatessArgs) -> utils::error::CheatessResul
 
     let prev_board = board;
     let prev_board_arr = calc_board;
-    let new_cropped =
-        utils::monitor::get_cropped_screen(&monitor, coords.0, coords.1, coords.2, coords.3)?;
-    let new_board = core::procimg::image_buffer_to_gray_mat(new_cropped)?;
+    let new_frame = source
+        .next_frame()?
+        .ok_or(utils::error::CheatessError::NoMoveDetected)?;
+    let new_board = core::procimg::extract_board(
+        &core::procimg::image_buffer_to_gray_mat(new_frame)?,
+        &coords,
+        corners.as_ref(),
+    )?;
+    let new_board = core::procimg::normalize_board(&new_board, &raw_geometry)?;
 
     if !core::procimg::are_images_different(
         &prev_board,
@@ -244,21 +395,223 @@ fn config_mode(args: utils::parser::CheatessArgs) -> utils::error::CheatessResul
         return Err(utils::error::CheatessError::NoMoveDetected);
     }
 
-    let new_raw_board = core::procimg::find_all_pieces(
-        &new_board,
-        &pieces,
-        args.proc_image.piece_threshold,
-        args.proc_image.board_threshold,
-    )?;
+    let new_raw_board = core::recognition::recognize(&new_board, &pieces, &args.proc_image)?;
 
     let (detected_move, _) =
         core::engine::detect_move(prev_board_arr.raw(), &new_raw_board, &player_color)?;
 
     log::info!("Detected move: {detected_move}");
 
+    let templates = || {
+        pieces
+            .iter()
+            .map(|(c, mat)| Ok((*c, mat.try_clone()?)))
+            .collect::<utils::error::CheatessResult<_>>()
+    };
+    if let Some(dir) = &args.proc_image.save_calibration {
+        utils::calibration::Calibration::new(&args, coords, corners, templates()?).save(dir)?;
+    }
+    if let Some(name) = &args.templates.save {
+        args.templates.library().save(name, &templates()?)?;
+    }
+
     Ok(())
 }
 
-fn clear_screen() {
-    print!("\x1B[2J\x1B[H");
+/// Searches for image processing parameters which recognize the known position on the
+/// screen exactly. Found values are printed as `imgproc` arguments and saved in
+/// calibration (`--save-calibration`) or in the template library (`templates --save`).
+fn tune_mode(mut args: utils::parser::CheatessArgs) -> utils::error::CheatessResult<()> {
+    let calibration = load_calibration(&mut args)?;
+    let mut source = open_source(&args)?;
+    let raw_gray = core::procimg::image_buffer_to_gray_mat(first_frame(source.as_mut())?)?;
+
+    let (coords, corners) = match (args.proc_image.manual_region(), &calibration) {
+        (Some(region), _) => (region, manual_corners(&args.proc_image)),
+        (None, Some(calibration)) => (calibration.region, calibration.corners),
+        (None, None) => detect_boards(&raw_gray, &args.proc_image)?.remove(0),
+    };
+    let board = core::procimg::extract_board(&raw_gray, &coords, corners.as_ref())?;
+    let raw_geometry = core::geometry::BoardGeometry::detect(&board)?;
+    let board = core::procimg::normalize_board(&board, &raw_geometry)?;
+    let geometry = core::geometry::BoardGeometry::canonical();
+
+    let templates = match (&args.templates.load, calibration) {
+        (Some(name), _) => Some(args.templates.library().load(name)?),
+        (None, Some(calibration)) => Some(calibration.pieces),
+        (None, None) => None,
+    };
+    let placement = args
+        .proc_image
+        .known_position
+        .as_deref()
+        .unwrap_or(core::tuning::INITIAL_PLACEMENT);
+    let white_view = core::engine::board_from_fen(placement)?;
+
+    // labels drawn by the site tell the orientation for sure, the position is compared otherwise
+    let labels = core::labels::read_labels(&board, &geometry)?.orientation();
+    let player_color = match (&templates, labels.filter(|o| o.is_sure())) {
+        (_, Some(orientation)) => orientation.player_color,
+        (Some(templates), None) => {
+            let recognized = core::recognition::recognize(
+                &board,
+                &core::recognition::to_shared(clone_templates(templates)?),
+                &args.proc_image,
+            )?;
+            core::tuning::infer_orientation(&recognized, &white_view)
+        }
+        (None, None)
+            if white_view == core::engine::board_from_fen(core::tuning::INITIAL_PLACEMENT)? =>
+        {
+            let orientation = core::orientation::from_initial_position(&board, &geometry)?;
+            tracker::player_color("", orientation, &args)?
+        }
+        (None, None) => return Err(utils::error::CheatessError::TemplatesRequired),
+    };
+    log::info!("Detected player color: {player_color:?}");
+    let expected = match player_color {
+        core::engine::Color::White => white_view,
+        core::engine::Color::Black => core::engine::rotate_board(&white_view),
+    };
+
+    let tuning = core::tuning::tune(
+        &board,
+        &expected,
+        &player_color,
+        templates.as_ref(),
+        &core::tuning::SearchSpace::default(),
+        args.proc_image.piece_threshold,
+    )?
+    .ok_or(utils::error::CheatessError::PositionNotReproduced)?;
+    log::info!(
+        "Position reproduced, separation of matches: {:.4}",
+        tuning.separation
+    );
+
+    let imgproc = &mut args.proc_image;
+    if let Some(margin) = tuning.margin {
+        imgproc.margin = margin;
+    }
+    if let Some(threshold) = tuning.extract_piece_threshold {
+        imgproc.extract_piece_threshold = threshold;
+    }
+    imgproc.board_threshold = tuning.board_threshold;
+    imgproc.piece_threshold = tuning.piece_threshold;
+    imgproc.piece_thresholds = tuning.piece_thresholds.clone();
+    match tuning.difference_level {
+        Some(level) => imgproc.difference_level = level,
+        None => log::warn!(
+            "Empty and occupied squares can't be told apart, keeping difference level {}",
+            imgproc.difference_level
+        ),
+    }
+    println!("imgproc {}", tuned_arguments(imgproc));
+
+    if let Some(name) = &args.templates.save {
+        args.templates.library().save(name, &tuning.pieces)?;
+    }
+    if let Some(dir) = &args.proc_image.save_calibration {
+        utils::calibration::Calibration::new(&args, coords, corners, tuning.pieces).save(dir)?;
+    }
+    Ok(())
+}
+
+/// Formats tuned parameters as `imgproc` arguments.
+fn tuned_arguments(args: &utils::parser::ImgProcArgs) -> String {
+    let mut arguments = format!(
+        "-m {} -e {} -b {} -p {} -d {}",
+        args.margin,
+        args.extract_piece_threshold,
+        args.board_threshold,
+        args.piece_threshold,
+        args.difference_level
+    );
+    if !args.piece_thresholds.is_empty() {
+        let thresholds = args
+            .piece_thresholds
+            .iter()
+            .map(|(piece, threshold)| format!("{piece}={threshold}"))
+            .collect::<Vec<_>>();
+        arguments.push_str(&format!(" --piece-thresholds {}", thresholds.join(",")));
+    }
+    arguments
+}
+
+/// Manages the template library: lists, deletes or exports saved template sets.
+fn templates_mode(args: utils::parser::CheatessArgs) -> utils::error::CheatessResult<()> {
+    let library = args.templates.library();
+
+    if let Some(name) = &args.templates.delete {
+        library.delete(name)?;
+    }
+    if let (Some(name), Some(dir)) = (&args.templates.export, &args.templates.to) {
+        library.export(name, dir)?;
+    }
+    if args.templates.list {
+        for name in library.list()? {
+            println!("{name}");
+        }
+    }
+    Ok(())
+}
+
+fn open_source(
+    args: &utils::parser::CheatessArgs,
+) -> utils::error::CheatessResult<Box<dyn utils::monitor::FrameSource>> {
+    // test mode reads confirmations from the standard input, which is held by the source
+    if args.input.source == utils::parser::Source::Stdin && args.mode == utils::parser::Mode::Test {
+        return Err(utils::error::CheatessError::UnsupportedSource(
+            args.input.source.to_string(),
+            args.mode.to_string(),
+        ));
+    }
+
+    let source: Box<dyn utils::monitor::FrameSource> = match args.input.source {
+        utils::parser::Source::Monitor => {
+            Box::new(utils::monitor::select_monitor(args.monitor.name.clone())?)
+        }
+        utils::parser::Source::Images => Box::new(utils::source::ImageDirSource::new(
+            args.input
+                .path
+                .as_ref()
+                .expect("Source path is checked by the parser"),
+        )?),
+        utils::parser::Source::Watch => Box::new(utils::source::DirectoryWatchSource::new(
+            args.input
+                .path
+                .as_ref()
+                .expect("Source path is checked by the parser"),
+            std::time::Duration::from_millis(args.input.poll),
+        )?),
+        utils::parser::Source::Window => Box::new(utils::monitor::WindowSource::new(
+            utils::monitor::select_window(args.monitor.window.clone(), args.monitor.pid)?,
+        )?),
+        utils::parser::Source::Video => Box::new(utils::source::VideoFileSource::new(
+            args.input
+                .path
+                .as_ref()
+                .expect("Source path is checked by the parser"),
+            args.input.step,
+        )?),
+        utils::parser::Source::Stdin => Box::new(utils::source::RawStreamSource::new(
+            io::stdin().lock(),
+            args.input
+                .width
+                .expect("Frame size is checked by the parser"),
+            args.input
+                .height
+                .expect("Frame size is checked by the parser"),
+            args.input.format,
+            args.input.fps,
+        )),
+    };
+    Ok(source)
+}
+
+fn first_frame(
+    source: &mut dyn utils::monitor::FrameSource,
+) -> utils::error::CheatessResult<utils::monitor::Frame> {
+    source
+        .next_frame()?
+        .ok_or_else(|| utils::error::CheatessError::NoFramesFound("input source".to_string()))
 }
@@ -0,0 +1,947 @@
+// State of a single tracked board: its region on the screen, extracted piece templates,
+// last recognized position, move history and a dedicated Stockfish session.
+use std::collections::HashMap;
+use std::io::{self, IsTerminal, Write};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::core;
+use crate::core::geometry::BoardGeometry;
+use crate::core::highlight::Highlights;
+use crate::core::procimg::{Corners, Mat};
+use crate::utils;
+use crate::utils::error::{CheatessError, CheatessResult};
+use crate::utils::parser::CheatessArgs;
+use opencv::core::Size;
+use opencv::prelude::MatTraitConst;
+
+/// Fewer read labels are not enough to check squares of the board.
+const MIN_LABELS: usize = 4;
+/// The most squares changed by a single move (castling).
+const MAX_CHANGED_SQUARES: usize = 4;
+
+#[derive(Debug, PartialEq)]
+pub enum BoardState {
+    Unchanged,
+    /// Board image changed (even if no valid move was detected).
+    Changed,
+    /// Board image changes (animation, dragged piece) or its recognition isn't settled yet.
+    Settling,
+    /// Cropped region doesn't look like a board anymore (page was scrolled, zoomed or resized).
+    Lost,
+    GameOver,
+}
+
+pub struct BoardTracker<'a> {
+    pub coords: (u32, u32, u32, u32),
+    /// Corners of the board when it is warped instead of cropped (perspective correction).
+    pub corners: Option<Corners>,
+    /// Prefix added to the output when more boards are tracked (empty otherwise).
+    tag: String,
+    args: &'a CheatessArgs,
+    /// Side of the user: given with `--player-color` or the side at the bottom of the board.
+    player_color: core::engine::Color,
+    /// Side at the bottom of the board, i.e. how the board is seen on the screen.
+    bottom: core::engine::Color,
+    pieces: HashMap<char, Arc<Mat>>,
+    /// Worker pool of the sliding recognition (not used by the squares one).
+    recognizer: Option<core::recognizer::Recognizer>,
+    /// Squares of the board inside the cropped image.
+    geometry: BoardGeometry,
+    /// Size of the cropped image (before normalization).
+    board_size: Size,
+    /// Board of the last detected move normalized to the canonical size.
+    prev_board_mat: Mat,
+    /// Last processed frame normalized to the canonical size.
+    last_frame_mat: Mat,
+    /// Board was moved on the screen since the last detected move, so its image can't be
+    /// compared with new frames and the whole board is recognized instead.
+    relocated: bool,
+    debouncer: utils::debounce::FrameDebouncer,
+    /// Clock of live captures, which have no timestamps.
+    started: Instant,
+    prev_board_arr: Box<dyn core::engine::AnyBoard>,
+    history: core::history::GameHistory,
+    sf: core::stockfish::Stockfish,
+    /// Setup of the recognized position whose side to move isn't known yet. Stockfish is
+    /// seeded with it after the first detected move.
+    pending_setup: Option<core::engine::PositionSetup>,
+    /// Index of the next move in the game. Unknown for a position recognized in the middle
+    /// of the game, unless the moves before were read from the move list.
+    ply: Option<usize>,
+}
+
+impl<'a> BoardTracker<'a> {
+    /// Creates tracker for the board in the given region of the (coloured) screen.
+    /// Board has to be in the initial position, unless `--mid-game` is used. Piece
+    /// templates are extracted from the board unless saved ones are given.
+    pub fn new(
+        id: usize,
+        coords: (u32, u32, u32, u32),
+        corners: Option<Corners>,
+        raw: &Mat,
+        args: &'a CheatessArgs,
+        multi_board: bool,
+        pieces: Option<HashMap<char, Mat>>,
+    ) -> CheatessResult<Self> {
+        let tag = if multi_board {
+            format!("[board {id}] ")
+        } else {
+            String::new()
+        };
+
+        let color_board = core::procimg::extract_color_board(raw, &coords, corners.as_ref())?; // ~1ms
+        let raw_board = to_gray(&color_board, corners.is_some())?;
+        let geometry = BoardGeometry::detect(&raw_board)?;
+        let board = core::procimg::normalize_board(&raw_board, &geometry)?;
+        let canonical = BoardGeometry::canonical();
+
+        let (player_color, pieces, raw_position) = if args.engine.mid_game {
+            let pieces =
+                core::recognition::to_shared(pieces.ok_or(CheatessError::TemplatesRequired)?);
+            let position = core::recognition::recognize(&board, &pieces, &args.proc_image)?;
+            let orientation = detect_orientation(&tag, &board, Some(&position))?;
+            let player_color = player_color(&tag, orientation, args)?;
+            (player_color, pieces, Some(position))
+        } else {
+            let orientation = detect_orientation(&tag, &board, None)?; // ~0.5ms
+            let player_color = player_color(&tag, orientation, args)?;
+            let pieces = match pieces {
+                Some(pieces) => pieces,
+                None => core::procimg::extract_pieces(
+                    &board,
+                    &canonical,
+                    args.proc_image.margin,
+                    args.proc_image.extract_piece_threshold,
+                    &player_color,
+                )?,
+            };
+            (player_color, core::recognition::to_shared(pieces), None)
+        };
+
+        let path = args
+            .stockfish
+            .path
+            .as_ref()
+            .expect("Stockfish path is checked by the parser");
+        let mut sf = core::stockfish::Stockfish::new(path, args.stockfish.depth);
+        sf.set_config(
+            &args.stockfish.elo.to_string(),
+            &args.stockfish.skill.to_string(),
+            &args.stockfish.hash.to_string(),
+            &args.stockfish.pv.to_string(),
+        )?;
+
+        let mut pending_setup = None;
+        let base_board: Box<dyn core::engine::AnyBoard> = match raw_position {
+            Some(position) => {
+                let white_view = to_white_view(&position, &player_color);
+                core::engine::validate_position(&white_view)?;
+                let setup = core::engine::PositionSetup {
+                    castling: args
+                        .engine
+                        .castling
+                        .clone()
+                        .unwrap_or_else(|| core::engine::infer_castling(&white_view)),
+                    en_passant: args.engine.en_passant.clone(),
+                };
+
+                let side_to_move = match args.engine.side_to_move {
+                    Some(side) => Some(side_color(side)),
+                    None => {
+                        let color_board = core::procimg::normalize_board(&color_board, &geometry)?;
+                        side_from_highlights(&position, &highlights(&color_board, args)?)
+                    }
+                };
+                match side_to_move {
+                    Some(side) => {
+                        let fen = core::engine::board_to_fen(&white_view, &side, &setup);
+                        log::info!("{tag}Recognized position: {fen}");
+                        sf.set_fen_position(&fen, true);
+                    }
+                    None => {
+                        log::info!(
+                            "{tag}Recognized position, side to move will be inferred from the first move"
+                        );
+                        pending_setup = Some(setup);
+                    }
+                }
+                new_board(args.engine.pretty, position, &player_color)
+            }
+            None if args.engine.pretty => {
+                core::engine::create_board_default::<core::engine::PrettyPrinter>(&player_color)
+            }
+            None => {
+                core::engine::create_board_default::<core::engine::DefaultPrinter>(&player_color)
+            }
+        };
+        let mut history = core::history::GameHistory::default();
+        let mut ply = raw_position.is_none().then_some(0);
+        if args.proc_image.read_sidebar {
+            let sidebar =
+                core::sidebar::read_sidebar(&core::procimg::color_to_gray(raw)?, &coords)?;
+            set_players(&mut history, &sidebar, &player_color);
+            let synced = raw_position.and_then(|position| {
+                listed_moves(&tag, &sidebar, &to_white_view(&position, &player_color))
+            });
+            if let Some(moves) = synced {
+                ply = Some(moves.len());
+                for uci in &moves {
+                    history.push(core::history::MoveRecord {
+                        uci: uci.clone(),
+                        eval: String::new(),
+                        timestamp: None,
+                        clock: None,
+                    });
+                }
+                let fen = format!("{} w KQkq - 0 1", core::tuning::INITIAL_PLACEMENT);
+                sf.set_fen_position(&fen, true);
+                sf.make_move(moves)?;
+                pending_setup = None;
+            }
+        }
+        base_board.print(&mut io::stdout());
+
+        let mut tracker = BoardTracker {
+            coords,
+            corners,
+            tag,
+            args,
+            player_color,
+            bottom: player_color,
+            recognizer: recognizer(&pieces, args),
+            pieces,
+            geometry,
+            board_size: raw_board.size()?,
+            last_frame_mat: board.try_clone()?,
+            prev_board_mat: board,
+            relocated: false,
+            debouncer: debouncer(args),
+            started: Instant::now(),
+            prev_board_arr: base_board,
+            history,
+            sf,
+            pending_setup,
+            ply,
+        };
+        if tracker.pending_setup.is_none() {
+            for (i, sum) in tracker.sf.summary(args.stockfish.pv)?.iter().enumerate() {
+                log_stockfish_summary(&tracker.tag, i, sum);
+            }
+        }
+        Ok(tracker)
+    }
+
+    /// Processes next (gray) image of the board: detects move, updates Stockfish and
+    /// prints the position. Clocks and the sidebar are read from the (coloured) screen
+    /// when it's given. Frames of continuous sources are recognized once the board
+    /// settles, other ones (screenshots) right away.
+    pub fn update(
+        &mut self,
+        color_board: Mat,
+        screen: Option<&Mat>,
+        timestamp: Option<Duration>,
+        continuous: bool,
+    ) -> CheatessResult<BoardState> {
+        let start = Instant::now();
+        let args = self.args;
+
+        if color_board.size()? != self.board_size {
+            log::warn!("{}Board region changed its size", self.tag);
+            return Ok(BoardState::Lost);
+        }
+        let gray_board = to_gray(&color_board, self.corners.is_some())?;
+        let gray_board = core::procimg::normalize_board(&gray_board, &self.geometry)?;
+        let canonical = BoardGeometry::canonical();
+
+        let moved = core::procimg::are_images_different(
+            &self.last_frame_mat,
+            &gray_board,
+            args.proc_image.difference_level,
+        )?;
+        self.last_frame_mat = gray_board.try_clone()?;
+
+        let changed = if self.relocated {
+            (0..64).map(|i| (i / 8, i % 8)).collect()
+        } else {
+            core::procimg::changed_squares(
+                &self.prev_board_mat,
+                &gray_board,
+                &canonical,
+                args.proc_image.difference_level,
+            )?
+        };
+        if changed.is_empty() {
+            self.debouncer.reset();
+            return Ok(BoardState::Unchanged);
+        }
+        let now = timestamp.unwrap_or_else(|| self.started.elapsed());
+        if continuous && !self.debouncer.observe(moved, now) {
+            log::trace!("{}Waiting for the board to settle", self.tag);
+            return Ok(BoardState::Settling);
+        }
+
+        if !core::procimg::looks_like_board(&gray_board, &canonical)? {
+            log::warn!("{}Board region doesn't contain the board anymore", self.tag);
+            return Ok(BoardState::Lost);
+        }
+        if continuous && self.debouncer.accepted() {
+            // position of this stable period was already processed (without a valid move)
+            return Ok(BoardState::Unchanged);
+        }
+
+        let recognized = self.recognize_changes(&gray_board, &changed)?;
+        let new_raw_board = if continuous {
+            let Some(voted) = self.debouncer.vote(recognized) else {
+                return Ok(BoardState::Settling);
+            };
+            voted
+        } else {
+            recognized
+        };
+        log::trace!("{}Pieces detection: {:?}", self.tag, start.elapsed());
+        log::trace!(
+            "{}OpenCV matchTemplate result: {}",
+            self.tag,
+            utils::printer::raw_board_to_string(&new_raw_board)
+        );
+
+        if self.follow_flip(&gray_board, &new_raw_board)?
+            && self.prev_board_arr.raw() == &new_raw_board
+        {
+            if self.tag.is_empty() {
+                clear_screen();
+            }
+            self.prev_board_arr.print(&mut io::stdout());
+            self.set_baseline(gray_board);
+            return Ok(BoardState::Changed);
+        }
+        if self.relocated && self.prev_board_arr.raw() == &new_raw_board {
+            log::debug!("{}No move was made while the board was moved", self.tag);
+            self.set_baseline(gray_board);
+            return Ok(BoardState::Changed);
+        }
+
+        let color_board = core::procimg::normalize_board(&color_board, &self.geometry)?;
+        let highlights = highlights(&color_board, args)?;
+        let (detected_move, new_raw_board) = match core::engine::detect_move(
+            self.prev_board_arr.raw(),
+            &new_raw_board,
+            &self.bottom,
+        ) {
+            Ok((mv, mv_type)) => {
+                log::info!("{}Detected move: {mv:?} [{mv_type:?}]", self.tag);
+                self.cross_check(&mv, &highlights);
+                (mv, new_raw_board)
+            }
+            Err(e) => match self.move_from_highlights(&new_raw_board, &highlights) {
+                Some((mv, board)) => {
+                    log::warn!("{}{e}, move {mv} taken from highlighted squares", self.tag);
+                    (mv, board)
+                }
+                None => {
+                    log::error!("{}{e}", self.tag);
+                    return Ok(BoardState::Changed);
+                }
+            },
+        };
+        let gray_screen = screen.map(core::procimg::color_to_gray).transpose()?;
+        let clock = match gray_screen.as_ref().filter(|_| args.proc_image.read_clocks) {
+            Some(gray_screen) => self.mover_clock(gray_screen, &detected_move)?,
+            None => None,
+        };
+        if let Some(gray_screen) = gray_screen
+            .as_ref()
+            .filter(|_| args.proc_image.read_sidebar)
+        {
+            let sidebar = core::sidebar::read_sidebar(gray_screen, &self.coords)?;
+            set_players(&mut self.history, &sidebar, &self.bottom);
+            self.reconcile(&sidebar, &detected_move);
+        }
+        self.ply = self.ply.map(|ply| ply + 1);
+        if let Some(setup) = self.pending_setup.take() {
+            self.seed_position(&detected_move, &setup);
+        }
+        self.sf.make_move(vec![detected_move.clone()])?;
+
+        if self.tag.is_empty() {
+            clear_screen();
+        }
+
+        let curr_board = new_board(args.engine.pretty, new_raw_board, &self.bottom);
+        curr_board.print(&mut io::stdout());
+
+        let summaries = self.sf.summary(args.stockfish.pv)?;
+        self.history.push(core::history::MoveRecord {
+            uci: detected_move,
+            eval: summaries
+                .first()
+                .map(|sum| sum.eval.clone())
+                .unwrap_or_default(),
+            timestamp,
+            clock,
+        });
+
+        for (i, sum) in summaries.iter().enumerate() {
+            if sum.main_line.is_empty() {
+                log::info!("{}Game over", self.tag);
+                self.log_history();
+                return Ok(BoardState::GameOver);
+            }
+            log_stockfish_summary(&self.tag, i, sum);
+        }
+        self.prev_board_arr = curr_board;
+        self.set_baseline(gray_board);
+        Ok(BoardState::Changed)
+    }
+
+    /// Makes the (normalized) image the one new frames are compared with.
+    fn set_baseline(&mut self, gray_board: Mat) {
+        self.prev_board_mat = gray_board;
+        self.relocated = false;
+    }
+
+    /// Recognizes the board after a move. Only the changed squares are recognized and the
+    /// rest is taken from the last position, unless the change doesn't look like a plain
+    /// move: a capture doesn't change occupancy of the captured piece's square (more squares
+    /// get emptied than occupied) and a flipped board changes many squares at once.
+    fn recognize_changes(
+        &mut self,
+        gray_board: &Mat,
+        changed: &[(usize, usize)],
+    ) -> CheatessResult<[[char; 8]; 8]> {
+        let previous = *self.prev_board_arr.raw();
+        let emptied = changed
+            .iter()
+            .filter(|&&(row, col)| previous[row][col] != core::recognition::EMPTY)
+            .count();
+        let incremental = changed.len() <= MAX_CHANGED_SQUARES && 2 * emptied <= changed.len();
+        log::trace!(
+            "{}Changed squares: {changed:?} (incremental recognition: {incremental})",
+            self.tag
+        );
+
+        match (&mut self.recognizer, incremental) {
+            (Some(recognizer), true) => {
+                recognizer.recognize_squares(gray_board, &previous, changed)
+            }
+            (Some(recognizer), false) => recognizer.recognize(gray_board),
+            (None, true) => core::recognition::classify_changed_squares(
+                gray_board,
+                &BoardGeometry::canonical(),
+                &self.pieces,
+                self.args.proc_image.board_threshold,
+                &previous,
+                changed,
+            ),
+            (None, false) => {
+                core::recognition::recognize(gray_board, &self.pieces, &self.args.proc_image)
+            }
+        }
+    }
+
+    /// Detects that the user flipped the board: labels show the other side at the bottom or
+    /// pieces are exactly rotated. The last position is rotated to the new view, so
+    /// tracking (and detection of a move made in the meantime) continues. The player color
+    /// follows the side at the bottom, unless it's given with `--player-color`.
+    fn follow_flip(
+        &mut self,
+        gray_board: &Mat,
+        recognized: &[[char; 8]; 8],
+    ) -> CheatessResult<bool> {
+        let previous = *self.prev_board_arr.raw();
+        let rotated = core::engine::rotate_board(&previous);
+        let labels = core::labels::read_labels(gray_board, &BoardGeometry::canonical())?;
+        let by_labels = labels
+            .orientation()
+            .is_some_and(|o| o.is_sure() && o.player_color != self.bottom);
+        if !by_labels && (*recognized == previous || *recognized != rotated) {
+            return Ok(false);
+        }
+
+        self.bottom = match self.bottom {
+            core::engine::Color::White => core::engine::Color::Black,
+            core::engine::Color::Black => core::engine::Color::White,
+        };
+        if self.args.engine.player_color.is_none() {
+            self.player_color = self.bottom;
+        }
+        log::info!(
+            "{}Board flipped, {:?} at the bottom, player color: {:?}",
+            self.tag,
+            self.bottom,
+            self.player_color
+        );
+        self.prev_board_arr = new_board(self.args.engine.pretty, rotated, &self.bottom);
+        Ok(true)
+    }
+
+    /// Warns when the detected move doesn't match the last move highlighted by the website.
+    fn cross_check(&self, mv: &str, highlights: &Highlights) {
+        let Some((from, to)) = core::engine::move_squares(mv, &self.bottom) else {
+            return;
+        };
+        let squares = &highlights.last_move;
+        if squares.len() == 2 && !(squares.contains(&from) && squares.contains(&to)) {
+            log::warn!(
+                "{}Detected move {mv} doesn't match highlighted squares {squares:?}",
+                self.tag
+            );
+        }
+    }
+
+    /// Reconstructs the move from the highlighted squares when recognized pieces don't
+    /// give a valid move. Returns the move and the position after it.
+    fn move_from_highlights(
+        &self,
+        recognized: &[[char; 8]; 8],
+        highlights: &Highlights,
+    ) -> Option<(String, [[char; 8]; 8])> {
+        let before = self.prev_board_arr.raw();
+        let mv = core::engine::move_from_squares(
+            before,
+            recognized,
+            &highlights.last_move,
+            &self.bottom,
+        )?;
+        let after = core::engine::apply_move(before, &mv, &self.bottom)?;
+        Some((mv, after))
+    }
+
+    /// Reads the clock of the side which made the detected move. Clock of the side at the
+    /// bottom is below the board.
+    fn mover_clock(&self, gray_screen: &Mat, mv: &str) -> CheatessResult<Option<Duration>> {
+        let clocks = core::clock::read_clocks(gray_screen, &self.coords)?;
+        let mover = core::engine::mover_color(self.prev_board_arr.raw(), mv, &self.bottom)
+            .unwrap_or(self.bottom);
+        let clock = if mover == self.bottom {
+            clocks.bottom
+        } else {
+            clocks.top
+        };
+        if clock.is_none() {
+            log::warn!("{}Clock of {mover:?} not found next to the board", self.tag);
+        }
+        Ok(clock)
+    }
+
+    /// Warns when the detected move differs from the one in the move list of the site. The
+    /// list may lag behind the board, so a move which isn't listed yet is skipped.
+    fn reconcile(&self, sidebar: &core::sidebar::Sidebar, detected: &str) {
+        let Some(ply) = self.ply else {
+            return;
+        };
+        let Some(san) = sidebar.move_at(ply) else {
+            return;
+        };
+        let side = match ply % 2 {
+            0 => core::engine::Color::White,
+            _ => core::engine::Color::Black,
+        };
+        let before = to_white_view(self.prev_board_arr.raw(), &self.bottom);
+        match core::san::san_to_uci(&before, san, &side) {
+            Some(listed) if listed == detected => {
+                log::debug!("{}Detected move {detected} is listed as {san}", self.tag)
+            }
+            Some(listed) => log::warn!(
+                "{}Detected move {detected} differs from {san} ({listed}) in the move list",
+                self.tag
+            ),
+            None => log::warn!(
+                "{}Listed move {san} isn't legal in the position before detected move {detected}",
+                self.tag
+            ),
+        }
+    }
+
+    /// Seeds Stockfish with the recognized position, side to move is the side which
+    /// made the first detected move.
+    fn seed_position(&mut self, mv: &str, setup: &core::engine::PositionSetup) {
+        let side = core::engine::mover_color(self.prev_board_arr.raw(), mv, &self.bottom)
+            .unwrap_or(self.bottom);
+        let white_view = to_white_view(self.prev_board_arr.raw(), &self.bottom);
+        let fen = core::engine::board_to_fen(&white_view, &side, setup);
+        log::info!("{}Recognized position: {fen}", self.tag);
+        self.sf.set_fen_position(&fen, true);
+    }
+
+    /// Cuts the tracked board from the (coloured) screen.
+    pub fn crop(&self, raw: &Mat) -> CheatessResult<Mat> {
+        core::procimg::extract_color_board(raw, &self.coords, self.corners.as_ref())
+    }
+
+    /// Moves tracker to the new region of the (coloured) screen. Templates don't depend on the
+    /// size of the board, so the last known position is kept and tracking just continues:
+    /// the whole board is recognized in the next update and a move made in the meantime
+    /// is detected as usual.
+    pub fn relocate(
+        &mut self,
+        coords: (u32, u32, u32, u32),
+        corners: Option<Corners>,
+        raw: &Mat,
+    ) -> CheatessResult<()> {
+        let color_board = core::procimg::extract_color_board(raw, &coords, corners.as_ref())?;
+        let raw_board = to_gray(&color_board, corners.is_some())?;
+        let geometry = BoardGeometry::detect(&raw_board)?;
+        log::info!(
+            "{}Board moved from {:?} to {coords:?}",
+            self.tag,
+            self.coords
+        );
+
+        self.coords = coords;
+        self.corners = corners;
+        self.board_size = raw_board.size()?;
+        self.last_frame_mat = core::procimg::normalize_board(&raw_board, &geometry)?;
+        self.relocated = true;
+        self.debouncer.reset();
+        self.geometry = geometry;
+        Ok(())
+    }
+
+    /// Checks if recognition of the board was verified: a move was detected (or synced from
+    /// the move list) or the position recognized in the middle of the game is valid.
+    pub fn is_verified(&self) -> bool {
+        self.args.engine.mid_game || !self.history.is_empty()
+    }
+
+    /// Returns copy of piece templates used by the tracker (e.g. to save them in calibration).
+    pub fn templates(&self) -> CheatessResult<HashMap<char, Mat>> {
+        self.pieces
+            .iter()
+            .map(|(c, mat)| Ok((*c, mat.try_clone()?)))
+            .collect()
+    }
+
+    pub fn log_history(&self) {
+        if self.history.is_empty() {
+            return;
+        }
+        log::info!("\n{}Game record:\n{}", self.tag, self.history.annotated());
+    }
+}
+
+/// Orientation of the normalized board. Rank and file labels (when the site draws them)
+/// are combined with the recognized position or, without it, with colours of pieces in
+/// the initial position, so disagreeing sources leave the orientation to be confirmed.
+pub fn detect_orientation(
+    tag: &str,
+    board: &Mat,
+    position: Option<&[[char; 8]; 8]>,
+) -> CheatessResult<Option<core::orientation::Orientation>> {
+    let canonical = BoardGeometry::canonical();
+    let labels = core::labels::read_labels(board, &canonical)?;
+    if labels.count() >= MIN_LABELS && !labels.match_squares() {
+        log::warn!("{tag}Board labels don't match squares, the board may be cropped wrongly");
+    }
+
+    let pieces = match position {
+        Some(position) => core::orientation::from_position(position),
+        None => core::orientation::from_initial_position(board, &canonical)?,
+    };
+    log::debug!(
+        "{tag}Orientation from board labels: {:?}, from pieces: {pieces:?}",
+        labels.orientation()
+    );
+    match core::orientation::combine(&[labels.orientation(), pieces]) {
+        // no evidence (or it cancels out), the piece in the corner is only a guess
+        None if position.is_none() => Ok(Some(core::orientation::Orientation {
+            player_color: core::procimg::detect_player_color(board)?,
+            confidence: 0.0,
+        })),
+        orientation => Ok(orientation),
+    }
+}
+
+fn debouncer(args: &CheatessArgs) -> utils::debounce::FrameDebouncer {
+    utils::debounce::FrameDebouncer::new(
+        args.proc_image.stable_frames,
+        Duration::from_millis(args.proc_image.stable_time),
+        args.proc_image.votes,
+    )
+}
+
+/// Starts workers of the sliding recognition, which are kept for the whole tracking.
+fn recognizer(
+    pieces: &HashMap<char, Arc<Mat>>,
+    args: &CheatessArgs,
+) -> Option<core::recognizer::Recognizer> {
+    let args = &args.proc_image;
+    (args.recognition == utils::parser::Recognition::Sliding).then(|| {
+        core::recognizer::Recognizer::new(
+            pieces,
+            &BoardGeometry::canonical(),
+            &args.piece_thresholds(),
+            args.piece_threshold,
+            args.board_threshold,
+        )
+    })
+}
+
+/// Converts the coloured board to gray, lighting of warped boards is evened out.
+fn to_gray(color_board: &Mat, perspective: bool) -> CheatessResult<Mat> {
+    let gray = core::procimg::color_to_gray(color_board)?;
+    if perspective {
+        core::procimg::normalize_lighting(&gray)
+    } else {
+        Ok(gray)
+    }
+}
+
+/// Finds squares highlighted by the website on the normalized coloured board (unless disabled).
+fn highlights(color_board: &Mat, args: &CheatessArgs) -> CheatessResult<Highlights> {
+    if args.proc_image.ignore_highlights {
+        return Ok(Highlights::default());
+    }
+    let highlights = core::highlight::detect_highlights(color_board, &BoardGeometry::canonical())?;
+    log::trace!("Highlighted squares: {highlights:?}");
+    Ok(highlights)
+}
+
+/// Fills headers of the game record with players read from the sidebar. The player
+/// (`player_color`) is at the bottom of the board.
+fn set_players(
+    history: &mut core::history::GameHistory,
+    sidebar: &core::sidebar::Sidebar,
+    player_color: &core::engine::Color,
+) {
+    let (white, black) = match player_color {
+        core::engine::Color::White => (&sidebar.bottom, &sidebar.top),
+        core::engine::Color::Black => (&sidebar.top, &sidebar.bottom),
+    };
+    for (color, player) in [("White", white), ("Black", black)] {
+        let Some(player) = player else {
+            continue;
+        };
+        history.set_header(color, &player.name);
+        if let Some(rating) = player.rating {
+            history.set_header(&format!("{color}Elo"), &rating.to_string());
+        }
+    }
+}
+
+/// Moves made before the start of the session, read from the move list. They are used
+/// only when they lead to the recognized position (seen from white's side).
+fn listed_moves(
+    tag: &str,
+    sidebar: &core::sidebar::Sidebar,
+    position: &[[char; 8]; 8],
+) -> Option<Vec<String>> {
+    let listed = sidebar.moves_from_start();
+    if listed.is_empty() {
+        return None;
+    }
+    match core::san::replay(&listed) {
+        Some((moves, board)) if board == *position => {
+            log::info!("{tag}Synced {} moves from the move list", moves.len());
+            Some(moves)
+        }
+        Some(_) => {
+            log::warn!(
+                "{tag}Listed moves don't lead to the recognized position, they aren't synced"
+            );
+            None
+        }
+        None => {
+            log::warn!(
+                "{tag}Listed moves can't be played from the initial position, they aren't synced"
+            );
+            None
+        }
+    }
+}
+
+/// Side to move in the position (as seen on the screen): the king in check is on move,
+/// otherwise the side which didn't make the highlighted last move.
+fn side_from_highlights(
+    position: &[[char; 8]; 8],
+    highlights: &Highlights,
+) -> Option<core::engine::Color> {
+    match highlights.check {
+        Some((row, col)) if position[row][col] == 'K' => Some(core::engine::Color::White),
+        Some((row, col)) if position[row][col] == 'k' => Some(core::engine::Color::Black),
+        _ => core::engine::side_to_move_after(position, &highlights.last_move),
+    }
+}
+
+fn new_board(
+    pretty: bool,
+    data: [[char; 8]; 8],
+    player_color: &core::engine::Color,
+) -> Box<dyn core::engine::AnyBoard> {
+    if pretty {
+        core::engine::create_board_from_data::<core::engine::PrettyPrinter>(data, player_color)
+    } else {
+        core::engine::create_board_from_data::<core::engine::DefaultPrinter>(data, player_color)
+    }
+}
+
+/// Array boards are stored as seen on the screen, FEN describes them from white's side.
+fn to_white_view(board: &[[char; 8]; 8], player_color: &core::engine::Color) -> [[char; 8]; 8] {
+    match player_color {
+        core::engine::Color::White => *board,
+        core::engine::Color::Black => core::engine::rotate_board(board),
+    }
+}
+
+/// Returns side at the bottom of the board: given by the user, detected with enough
+/// confidence or confirmed by the user in the terminal.
+pub fn player_color(
+    tag: &str,
+    orientation: Option<core::orientation::Orientation>,
+    args: &CheatessArgs,
+) -> CheatessResult<core::engine::Color> {
+    if let Some(side) = args.engine.player_color {
+        return Ok(side_color(side));
+    }
+    let guess = match orientation {
+        Some(orientation) if orientation.is_sure() => {
+            log::info!(
+                "{tag}Detected player color: {:?} (confidence {:.2})",
+                orientation.player_color,
+                orientation.confidence
+            );
+            return Ok(orientation.player_color);
+        }
+        Some(orientation) => orientation.player_color,
+        None => core::engine::Color::White,
+    };
+
+    // frames of `stdin` source are read from the standard input, so the user can't answer
+    if args.input.source == utils::parser::Source::Stdin || !io::stdin().is_terminal() {
+        log::warn!(
+            "{tag}Player color is uncertain, assuming {guess:?} (use `engine --player-color` to set it)"
+        );
+        return Ok(guess);
+    }
+
+    let (default, other) = match guess {
+        core::engine::Color::White => ("white", "black"),
+        core::engine::Color::Black => ("black", "white"),
+    };
+    print!("{tag}Player color is uncertain. Which side is at the bottom of the board? [{default}/{other}] ");
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    let player_color = match answer.trim().to_lowercase().as_str() {
+        "w" | "white" => core::engine::Color::White,
+        "b" | "black" => core::engine::Color::Black,
+        _ => guess,
+    };
+    log::info!("{tag}Player color: {player_color:?}");
+    Ok(player_color)
+}
+
+fn side_color(side: utils::parser::Side) -> core::engine::Color {
+    match side {
+        utils::parser::Side::White => core::engine::Color::White,
+        utils::parser::Side::Black => core::engine::Color::Black,
+    }
+}
+
+pub fn log_stockfish_summary(tag: &str, iter: usize, summary: &core::stockfish::Summary) {
+    fn format_moves(moves: &[String]) -> String {
+        moves
+            .chunks(2)
+            .enumerate()
+            .map(|(i, chunk)| {
+                let m1 = chunk.get(0).cloned().unwrap_or_default();
+                let m2 = chunk.get(1).cloned().unwrap_or_default();
+                format!("{}. {} {}", i + 1, m1, m2)
+            })
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    log::info!(
+        "\n\
+    ┌────────────── {tag}Stockfish line #{iter} ──────────────────\n\
+    │ Evaluation : {}\n\
+    │ Line       : {}\n\
+    └─────────────────────────────────────────────────────",
+        summary.eval,
+        format_moves(&summary.main_line)
+    );
+}
+
+pub fn clear_screen() {
+    print!("\x1B[2J\x1B[H");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::render::{render_board, SpriteSet};
+    use crate::core::tuning::INITIAL_PLACEMENT;
+    use crate::utils::monitor::FrameSource;
+    use crate::utils::source::ImageDirSource;
+    use rstest::rstest;
+    use std::os::unix::fs::PermissionsExt;
+
+    /// Answers commands like Stockfish, the best move is always `e7e5`.
+    static ENGINE: &str = r#"#!/bin/sh
+echo "Stockfish test engine"
+while read -r cmd args; do
+    case "$cmd" in
+        uci) echo "uciok" ;;
+        isready) echo "readyok" ;;
+        d) echo "Fen: rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1" ;;
+        go) set -- $args; echo "info depth $2 multipv 1 score cp -20 pv e7e5"; echo "bestmove e7e5" ;;
+        quit) exit 0 ;;
+    esac
+done
+"#;
+
+    #[rstest]
+    #[case(false, BoardState::Changed)]
+    // a single frame of a continuous source may show an animated piece
+    #[case(true, BoardState::Settling)]
+    fn replay_screenshots(#[case] continuous: bool, #[case] expected: BoardState) {
+        let dir = std::env::temp_dir().join(format!(
+            "cheatess-tracker-{}-{continuous}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let engine = dir.join("engine.sh");
+        std::fs::write(&engine, ENGINE).unwrap();
+        std::fs::set_permissions(&engine, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let after = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR";
+        for (name, fen) in [("001.png", INITIAL_PLACEMENT), ("002.png", after)] {
+            let (image, _) =
+                render_board(fen, &SpriteSet::geometric(64), &Default::default()).unwrap();
+            image.save(dir.join(name)).unwrap();
+        }
+        let args = utils::parser::parse_args_from([
+            "cheatess-core",
+            "stockfish",
+            "--path",
+            engine.to_str().unwrap(),
+            "engine",
+            "--player-color",
+            "white",
+        ]);
+
+        let mut source = ImageDirSource::new(&dir).unwrap();
+        let mut next_frame = || {
+            core::procimg::image_buffer_to_color_mat(source.next_frame().unwrap().unwrap()).unwrap()
+        };
+        let raw = next_frame();
+        let mut tracker =
+            BoardTracker::new(1, (0, 0, 512, 512), None, &raw, &args, false, None).unwrap();
+        let raw = next_frame();
+        let state = tracker
+            .update(tracker.crop(&raw).unwrap(), None, None, continuous)
+            .unwrap();
+
+        assert_eq!(state, expected);
+        if expected == BoardState::Changed {
+            assert_eq!(
+                tracker.prev_board_arr.raw(),
+                &core::engine::board_from_fen(after).unwrap()
+            );
+            assert!(tracker.history.annotated().starts_with("1. e2e4 "));
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,308 @@
+// Calibration saved after successful board detection. Later runs can load it
+// to skip detection of the board region (and its corners) and extraction of piece templates.
+//
+// Calibration is a directory with `calibration.cfg` (`key=value` lines) and
+// `pieces/<piece>.png` templates.
+use super::error::{CheatessError, CheatessResult};
+use super::library::{load_pieces, save_pieces};
+use super::parser::CheatessArgs;
+use crate::core::procimg::{Corners, Mat};
+use opencv::core::Point2f;
+use std::collections::HashMap;
+use std::path::Path;
+
+const CONFIG_FILE: &str = "calibration.cfg";
+const PIECES_DIR: &str = "pieces";
+
+#[derive(Debug)]
+pub struct Calibration {
+    pub region: (u32, u32, u32, u32),
+    /// Corners of the board, when it's perspective-corrected.
+    pub corners: Option<Corners>,
+    pub monitor: Option<String>,
+    pub margin: u8,
+    pub piece_threshold: f64,
+    /// Thresholds of pieces which override the global one.
+    pub piece_thresholds: Vec<(char, f64)>,
+    pub extract_piece_threshold: f64,
+    pub board_threshold: f64,
+    pub difference_level: i32,
+    pub pieces: HashMap<char, Mat>,
+}
+
+impl Calibration {
+    /// Creates calibration from the current arguments, detected region (with corners) and
+    /// extracted templates.
+    pub fn new(
+        args: &CheatessArgs,
+        region: (u32, u32, u32, u32),
+        corners: Option<Corners>,
+        pieces: HashMap<char, Mat>,
+    ) -> Self {
+        Calibration {
+            region,
+            corners,
+            monitor: args.monitor.name.clone(),
+            margin: args.proc_image.margin,
+            piece_threshold: args.proc_image.piece_threshold,
+            piece_thresholds: args.proc_image.piece_thresholds.clone(),
+            extract_piece_threshold: args.proc_image.extract_piece_threshold,
+            board_threshold: args.proc_image.board_threshold,
+            difference_level: args.proc_image.difference_level,
+            pieces,
+        }
+    }
+
+    pub fn save(&self, dir: &Path) -> CheatessResult<()> {
+        std::fs::create_dir_all(dir)?;
+
+        let (x, y, w, h) = self.region;
+        let mut config = format!("region={x},{y},{w},{h}\n");
+        if let Some(corners) = &self.corners {
+            let points = corners
+                .iter()
+                .map(|point| format!("{},{}", point.x, point.y))
+                .collect::<Vec<_>>();
+            config.push_str(&format!("corners={}\n", points.join(" ")));
+        }
+        if let Some(monitor) = &self.monitor {
+            config.push_str(&format!("monitor={monitor}\n"));
+        }
+        config.push_str(&format!(
+            "margin={}\npiece_threshold={}\nextract_piece_threshold={}\nboard_threshold={}\ndifference_level={}\n",
+            self.margin,
+            self.piece_threshold,
+            self.extract_piece_threshold,
+            self.board_threshold,
+            self.difference_level
+        ));
+        if !self.piece_thresholds.is_empty() {
+            let thresholds = self
+                .piece_thresholds
+                .iter()
+                .map(|(piece, threshold)| format!("{piece}={threshold}"))
+                .collect::<Vec<_>>();
+            config.push_str(&format!("piece_thresholds={}\n", thresholds.join(",")));
+        }
+        std::fs::write(dir.join(CONFIG_FILE), config)?;
+
+        save_pieces(&dir.join(PIECES_DIR), &self.pieces)?;
+        log::info!("Calibration saved to {}", dir.display());
+        Ok(())
+    }
+
+    pub fn load(dir: &Path) -> CheatessResult<Self> {
+        let config = std::fs::read_to_string(dir.join(CONFIG_FILE))?;
+        let values = config
+            .lines()
+            .filter_map(|line| line.split_once('='))
+            .map(|(key, value)| (key.trim(), value.trim()))
+            .collect::<HashMap<&str, &str>>();
+
+        let get = |key: &str| {
+            values
+                .get(key)
+                .copied()
+                .ok_or_else(|| CheatessError::InvalidCalibration(format!("missing `{key}`")))
+        };
+        let invalid = |key: &str| CheatessError::InvalidCalibration(format!("invalid `{key}`"));
+
+        let region = super::parser::parse_region(get("region")?).map_err(|_| invalid("region"))?;
+        let piece_thresholds = match values.get("piece_thresholds") {
+            Some(thresholds) => thresholds
+                .split(',')
+                .map(super::parser::parse_piece_threshold)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| invalid("piece_thresholds"))?,
+            None => vec![],
+        };
+        let corners = values
+            .get("corners")
+            .map(|corners| parse_corners(corners).ok_or_else(|| invalid("corners")))
+            .transpose()?;
+        let pieces = load_pieces(&dir.join(PIECES_DIR)).map_err(|e| match e {
+            CheatessError::InvalidTemplateSet(reason) => CheatessError::InvalidCalibration(reason),
+            e => e,
+        })?;
+
+        log::info!("Calibration loaded from {}", dir.display());
+        Ok(Calibration {
+            region,
+            corners,
+            monitor: values.get("monitor").map(|m| m.to_string()),
+            margin: get("margin")?.parse().map_err(|_| invalid("margin"))?,
+            piece_threshold: get("piece_threshold")?
+                .parse()
+                .map_err(|_| invalid("piece_threshold"))?,
+            piece_thresholds,
+            extract_piece_threshold: get("extract_piece_threshold")?
+                .parse()
+                .map_err(|_| invalid("extract_piece_threshold"))?,
+            board_threshold: get("board_threshold")?
+                .parse()
+                .map_err(|_| invalid("board_threshold"))?,
+            difference_level: get("difference_level")?
+                .parse()
+                .map_err(|_| invalid("difference_level"))?,
+            pieces,
+        })
+    }
+
+    /// Overrides monitor and image processing arguments with calibrated values, unless
+    /// they were given explicitly on the command line.
+    pub fn apply(&self, args: &mut CheatessArgs) {
+        if args.monitor.name.is_none() {
+            args.monitor.name = self.monitor.clone();
+        }
+        let proc_image = &mut args.proc_image;
+        if !proc_image.is_given("margin") {
+            proc_image.margin = self.margin;
+        }
+        if !proc_image.is_given("piece_threshold") {
+            proc_image.piece_threshold = self.piece_threshold;
+        }
+        if !proc_image.is_given("piece_thresholds") {
+            proc_image.piece_thresholds = self.piece_thresholds.clone();
+        }
+        if !proc_image.is_given("extract_piece_threshold") {
+            proc_image.extract_piece_threshold = self.extract_piece_threshold;
+        }
+        if !proc_image.is_given("board_threshold") {
+            proc_image.board_threshold = self.board_threshold;
+        }
+        if !proc_image.is_given("difference_level") {
+            proc_image.difference_level = self.difference_level;
+        }
+    }
+}
+
+/// Parses four corners in `x,y x,y x,y x,y` format.
+fn parse_corners(s: &str) -> Option<Corners> {
+    let points = s
+        .split_whitespace()
+        .map(|point| {
+            let (x, y) = point.split_once(',')?;
+            Some(Point2f::new(x.parse().ok()?, y.parse().ok()?))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    points.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::library::PIECES;
+    use opencv::core::{Scalar, CV_8UC1};
+    use opencv::prelude::*;
+    use rstest::{fixture, rstest};
+    use std::path::PathBuf;
+
+    #[fixture]
+    fn calibration_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "cheatess-calibration-{}-{:?}",
+            std::process::id(),
+            std::thread::current().id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn calibration() -> Calibration {
+        let pieces = PIECES
+            .chars()
+            .enumerate()
+            .map(|(i, piece)| {
+                let value = Scalar::all((i * 20) as f64);
+                (
+                    piece,
+                    Mat::new_rows_cols_with_default(10, 8, CV_8UC1, value).unwrap(),
+                )
+            })
+            .collect();
+
+        Calibration {
+            region: (10, 20, 400, 400),
+            corners: Some([
+                Point2f::new(12.0, 20.5),
+                Point2f::new(410.0, 22.0),
+                Point2f::new(408.5, 418.0),
+                Point2f::new(10.0, 420.0),
+            ]),
+            monitor: Some("HDMI-1".to_string()),
+            margin: 4,
+            piece_threshold: 0.15,
+            piece_thresholds: vec![('K', 0.12), ('p', 0.2)],
+            extract_piece_threshold: 120.0,
+            board_threshold: 90.0,
+            difference_level: 600,
+            pieces,
+        }
+    }
+
+    #[rstest]
+    fn calibration_round_trip(calibration_dir: PathBuf) {
+        let saved = calibration();
+        saved.save(&calibration_dir).unwrap();
+
+        let loaded = Calibration::load(&calibration_dir).unwrap();
+        assert_eq!(loaded.region, saved.region);
+        assert_eq!(loaded.corners, saved.corners);
+        assert_eq!(loaded.monitor, saved.monitor);
+        assert_eq!(loaded.margin, saved.margin);
+        assert_eq!(loaded.piece_threshold, saved.piece_threshold);
+        assert_eq!(loaded.piece_thresholds, saved.piece_thresholds);
+        assert_eq!(
+            loaded.extract_piece_threshold,
+            saved.extract_piece_threshold
+        );
+        assert_eq!(loaded.board_threshold, saved.board_threshold);
+        assert_eq!(loaded.difference_level, saved.difference_level);
+
+        for (piece, mat) in &saved.pieces {
+            let loaded_mat = &loaded.pieces[piece];
+            assert_eq!(loaded_mat.size().unwrap(), mat.size().unwrap());
+            assert_eq!(loaded_mat.data_bytes().unwrap(), mat.data_bytes().unwrap());
+        }
+    }
+
+    #[rstest]
+    fn explicit_arguments_override_calibration() {
+        let mut args = crate::utils::parser::parse_args_from([
+            "cheatess-core",
+            "stockfish",
+            "--path",
+            "stockfish",
+            "imgproc",
+            "--margin",
+            "3",
+        ]);
+        calibration().apply(&mut args);
+
+        assert_eq!(args.proc_image.margin, 3);
+        assert_eq!(args.proc_image.board_threshold, 90.0);
+        assert_eq!(args.monitor.name.as_deref(), Some("HDMI-1"));
+    }
+
+    #[rstest]
+    fn calibration_without_template(calibration_dir: PathBuf) {
+        calibration().save(&calibration_dir).unwrap();
+        std::fs::remove_file(calibration_dir.join(PIECES_DIR).join("q.png")).unwrap();
+
+        let result = Calibration::load(&calibration_dir);
+        assert!(matches!(result, Err(CheatessError::InvalidCalibration(_))));
+    }
+
+    #[rstest]
+    #[case("region=1,2,3\nmargin=5")]
+    #[case("region=1,2,3,4\nmargin=five")]
+    #[case("region=1,2,3,4\nmargin=5\npiece_thresholds=K:0.1")]
+    #[case("region=1,2,3,4\ncorners=1,2 3,4 5,6")]
+    fn calibration_with_invalid_config(calibration_dir: PathBuf, #[case] config: &str) {
+        calibration().save(&calibration_dir).unwrap();
+        std::fs::write(calibration_dir.join(CONFIG_FILE), config).unwrap();
+
+        let result = Calibration::load(&calibration_dir);
+        assert!(matches!(result, Err(CheatessError::InvalidCalibration(_))));
+    }
+}
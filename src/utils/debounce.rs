@@ -0,0 +1,144 @@
+// Debouncing of board changes. While a piece is animated or dragged, consecutive frames
+// differ and recognized positions are garbage, so recognition waits until the board is
+// stable and a position is accepted only when consecutive recognitions agree.
+use std::time::Duration;
+
+pub struct FrameDebouncer {
+    min_frames: usize,
+    min_time: Duration,
+    votes: usize,
+    /// Number of consecutive frames without a change.
+    stable_frames: usize,
+    stable_since: Option<Duration>,
+    /// Recognitions of the current stable period (all of them are equal).
+    candidates: Vec<[[char; 8]; 8]>,
+    /// Position of the current stable period was already returned.
+    accepted: bool,
+}
+
+impl FrameDebouncer {
+    /// Creates debouncer which waits for `min_frames` frames and `min_time` without a change
+    /// and accepts position recognized the same way `votes` times in a row.
+    pub fn new(min_frames: usize, min_time: Duration, votes: usize) -> Self {
+        FrameDebouncer {
+            min_frames,
+            min_time,
+            votes: votes.max(1),
+            stable_frames: 0,
+            stable_since: None,
+            candidates: vec![],
+            accepted: false,
+        }
+    }
+
+    /// Records next frame, `moved` tells if it differs from the previous one and `now` is its
+    /// time (since any fixed point, e.g. the start of the video). Returns true when the board
+    /// is stable long enough to be recognized.
+    pub fn observe(&mut self, moved: bool, now: Duration) -> bool {
+        if moved {
+            self.reset();
+            self.stable_since = Some(now);
+            return false;
+        }
+
+        self.stable_frames += 1;
+        let since = *self.stable_since.get_or_insert(now);
+        self.stable_frames >= self.min_frames && now.saturating_sub(since) >= self.min_time
+    }
+
+    /// Adds position recognized on a stable frame. Returns it once per stable period, when
+    /// the last recognitions agree.
+    pub fn vote(&mut self, board: [[char; 8]; 8]) -> Option<[[char; 8]; 8]> {
+        if self.accepted {
+            return None;
+        }
+        if self.candidates.last().is_some_and(|last| *last != board) {
+            log::debug!("Recognized position differs from the previous frame");
+            self.candidates.clear();
+        }
+
+        self.candidates.push(board);
+        if self.candidates.len() < self.votes {
+            return None;
+        }
+        self.accepted = true;
+        Some(board)
+    }
+
+    /// Position of the current stable period was already returned by `vote`.
+    pub fn accepted(&self) -> bool {
+        self.accepted
+    }
+
+    /// Forgets the current stable period (e.g. when the board changed or a move was accepted).
+    pub fn reset(&mut self) {
+        self.stable_frames = 0;
+        self.stable_since = None;
+        self.candidates.clear();
+        self.accepted = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn board(piece: char) -> [[char; 8]; 8] {
+        let mut board = [[' '; 8]; 8];
+        board[0][0] = piece;
+        board
+    }
+
+    #[rstest]
+    #[case(1, 0, 1)]
+    #[case(3, 0, 3)]
+    #[case(1, 250, 3)]
+    fn debouncer_waits_for_stable_board(
+        #[case] frames: usize,
+        #[case] millis: u64,
+        #[case] expected: usize,
+    ) {
+        let mut debouncer = FrameDebouncer::new(frames, Duration::from_millis(millis), 1);
+        assert!(!debouncer.observe(true, Duration::ZERO));
+
+        let stable_after = (1..=10)
+            .find(|&i| debouncer.observe(false, Duration::from_millis(100) * i as u32))
+            .unwrap();
+        assert_eq!(stable_after, expected);
+    }
+
+    #[rstest]
+    fn debouncer_requires_agreeing_votes() {
+        let mut debouncer = FrameDebouncer::new(1, Duration::ZERO, 2);
+
+        assert_eq!(debouncer.vote(board('P')), None);
+        // one bad frame restarts the voting
+        assert_eq!(debouncer.vote(board('B')), None);
+        assert_eq!(debouncer.vote(board('P')), None);
+        assert_eq!(debouncer.vote(board('P')), Some(board('P')));
+        // position is returned only once in the stable period
+        assert!(debouncer.accepted());
+        assert_eq!(debouncer.vote(board('P')), None);
+
+        debouncer.reset();
+        assert_eq!(debouncer.vote(board('N')), None);
+        assert_eq!(debouncer.vote(board('N')), Some(board('N')));
+    }
+
+    #[rstest]
+    fn debouncer_resets_when_board_moves() {
+        let mut debouncer = FrameDebouncer::new(2, Duration::ZERO, 2);
+        let now = Duration::ZERO;
+
+        debouncer.observe(false, now);
+        assert!(debouncer.observe(false, now));
+        assert_eq!(debouncer.vote(board('Q')), None);
+
+        assert!(!debouncer.observe(true, now));
+        assert!(!debouncer.observe(false, now));
+        assert!(debouncer.observe(false, now));
+        assert_eq!(debouncer.vote(board('Q')), None);
+        assert_eq!(debouncer.vote(board('Q')), Some(board('Q')));
+    }
+}
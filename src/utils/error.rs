@@ -7,6 +7,12 @@ pub enum CheatessError {
     #[error("Monitor not found")]
     MonitorNotFound,
 
+    #[error("Window not found")]
+    WindowNotFound,
+
+    #[error("Chessboard not found")]
+    BoardNotFound,
+
     #[error("Xcap error: {0}")]
     XcapError(#[from] xcap::XCapError),
 
@@ -24,4 +30,37 @@ pub enum CheatessError {
 
     #[error("Regex error: {0}")]
     RegexError(#[from] regex::Error),
+
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Image error: {0}")]
+    ImageError(#[from] image::ImageError),
+
+    #[error("No frames found in {0}")]
+    NoFramesFound(String),
+
+    #[error("Source {0} can't be used in {1} mode")]
+    UnsupportedSource(String, String),
+
+    #[error("Invalid FEN: {0}")]
+    InvalidFen(String),
+
+    #[error("Sprite not found for piece: {0}")]
+    SpriteNotFound(char),
+
+    #[error("Invalid calibration: {0}")]
+    InvalidCalibration(String),
+
+    #[error("Invalid template set: {0}")]
+    InvalidTemplateSet(String),
+
+    #[error("Template set not found: {0}")]
+    TemplateSetNotFound(String),
+
+    #[error("Piece templates are required to recognize a position other than the initial one")]
+    TemplatesRequired,
+
+    #[error("No tuned parameters reproduce the known position")]
+    PositionNotReproduced,
 }
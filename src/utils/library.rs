@@ -0,0 +1,218 @@
+// Named sets of piece templates (e.g. "lichess-cburnett-brown") stored on disk,
+// so templates extracted once can be reused on later launches.
+//
+// Every set is a directory `<root>/<name>` with `<piece>.png` templates.
+use super::error::{CheatessError, CheatessResult};
+use crate::core::procimg::Mat;
+use opencv::{core::Vector, imgcodecs, prelude::*};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+pub const PIECES: &str = "KQRBNPkqrbnp";
+
+pub struct TemplateLibrary {
+    root: PathBuf,
+}
+
+impl TemplateLibrary {
+    pub fn new(root: &Path) -> Self {
+        TemplateLibrary {
+            root: root.to_path_buf(),
+        }
+    }
+
+    /// Library in `$XDG_DATA_HOME/cheatess/templates` (or `~/.local/share/cheatess/templates`).
+    pub fn default_root() -> PathBuf {
+        let data = std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share"))
+            })
+            .unwrap_or_else(|| PathBuf::from("."));
+        data.join("cheatess").join("templates")
+    }
+
+    pub fn save(&self, name: &str, pieces: &HashMap<char, Mat>) -> CheatessResult<()> {
+        save_pieces(&self.set_dir(name)?, pieces)?;
+        log::info!("Templates saved as '{name}' in {}", self.root.display());
+        Ok(())
+    }
+
+    pub fn load(&self, name: &str) -> CheatessResult<HashMap<char, Mat>> {
+        let dir = self.existing_set_dir(name)?;
+        let pieces = load_pieces(&dir)?;
+        log::info!("Templates '{name}' loaded from {}", self.root.display());
+        Ok(pieces)
+    }
+
+    /// Returns names of all sets in the library, sorted alphabetically.
+    pub fn list(&self) -> CheatessResult<Vec<String>> {
+        if !self.root.is_dir() {
+            return Ok(vec![]);
+        }
+
+        let mut names = std::fs::read_dir(&self.root)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.is_dir())
+            .filter_map(|path| path.file_name()?.to_str().map(String::from))
+            .collect::<Vec<_>>();
+        names.sort();
+        Ok(names)
+    }
+
+    pub fn delete(&self, name: &str) -> CheatessResult<()> {
+        std::fs::remove_dir_all(self.existing_set_dir(name)?)?;
+        log::info!("Templates '{name}' deleted");
+        Ok(())
+    }
+
+    /// Copies the set to `<destination>/<name>` (e.g. to share it or put it into another library).
+    pub fn export(&self, name: &str, destination: &Path) -> CheatessResult<PathBuf> {
+        let target = destination.join(name);
+        save_pieces(&target, &self.load(name)?)?;
+        log::info!("Templates '{name}' exported to {}", target.display());
+        Ok(target)
+    }
+
+    fn set_dir(&self, name: &str) -> CheatessResult<PathBuf> {
+        let valid = !name.is_empty()
+            && !name.starts_with('.')
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c));
+        if !valid {
+            return Err(CheatessError::InvalidTemplateSet(name.to_string()));
+        }
+        Ok(self.root.join(name))
+    }
+
+    fn existing_set_dir(&self, name: &str) -> CheatessResult<PathBuf> {
+        let dir = self.set_dir(name)?;
+        if !dir.is_dir() {
+            return Err(CheatessError::TemplateSetNotFound(name.to_string()));
+        }
+        Ok(dir)
+    }
+}
+
+/// Writes every template as `<dir>/<piece>.png`.
+pub fn save_pieces(dir: &Path, pieces: &HashMap<char, Mat>) -> CheatessResult<()> {
+    std::fs::create_dir_all(dir)?;
+    for (piece, mat) in pieces {
+        let path = dir.join(format!("{piece}.png"));
+        if !imgcodecs::imwrite(&path.display().to_string(), mat, &Vector::new())? {
+            return Err(
+                std::io::Error::other(format!("Failed to write {}", path.display())).into(),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Reads templates of all pieces from `<dir>/<piece>.png`.
+pub fn load_pieces(dir: &Path) -> CheatessResult<HashMap<char, Mat>> {
+    let mut pieces = HashMap::new();
+    for piece in PIECES.chars() {
+        let path = dir.join(format!("{piece}.png"));
+        let mat = imgcodecs::imread(&path.display().to_string(), imgcodecs::IMREAD_GRAYSCALE)?;
+        if mat.empty() {
+            return Err(CheatessError::InvalidTemplateSet(format!(
+                "missing template {}",
+                path.display()
+            )));
+        }
+        pieces.insert(piece, mat);
+    }
+    Ok(pieces)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opencv::core::{Scalar, CV_8UC1};
+    use rstest::{fixture, rstest};
+
+    #[fixture]
+    fn library() -> TemplateLibrary {
+        let root = std::env::temp_dir().join(format!(
+            "cheatess-library-{}-{:?}",
+            std::process::id(),
+            std::thread::current().id()
+        ));
+        let _ = std::fs::remove_dir_all(&root);
+        TemplateLibrary::new(&root)
+    }
+
+    fn pieces() -> HashMap<char, Mat> {
+        PIECES
+            .chars()
+            .enumerate()
+            .map(|(i, piece)| {
+                let value = Scalar::all((i * 20) as f64);
+                (
+                    piece,
+                    Mat::new_rows_cols_with_default(6, 5, CV_8UC1, value).unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[rstest]
+    fn library_round_trip(library: TemplateLibrary) {
+        assert!(library.list().unwrap().is_empty());
+
+        library.save("lichess-cburnett-brown", &pieces()).unwrap();
+        library.save("chess.com-neo-green", &pieces()).unwrap();
+        assert_eq!(
+            library.list().unwrap(),
+            vec!["chess.com-neo-green", "lichess-cburnett-brown"]
+        );
+
+        let loaded = library.load("lichess-cburnett-brown").unwrap();
+        for (piece, mat) in pieces() {
+            assert_eq!(
+                loaded[&piece].data_bytes().unwrap(),
+                mat.data_bytes().unwrap()
+            );
+        }
+
+        library.delete("chess.com-neo-green").unwrap();
+        assert_eq!(library.list().unwrap(), vec!["lichess-cburnett-brown"]);
+    }
+
+    #[rstest]
+    fn library_export(library: TemplateLibrary) {
+        library.save("lichess", &pieces()).unwrap();
+        let destination = library.root.join("..").join(format!(
+            "cheatess-export-{}-{:?}",
+            std::process::id(),
+            std::thread::current().id()
+        ));
+
+        let exported = library.export("lichess", &destination).unwrap();
+        assert_eq!(exported, destination.join("lichess"));
+        assert_eq!(load_pieces(&exported).unwrap().len(), 12);
+    }
+
+    #[rstest]
+    #[case("../outside")]
+    #[case("")]
+    #[case(".hidden")]
+    #[case("with space")]
+    fn library_rejects_invalid_names(library: TemplateLibrary, #[case] name: &str) {
+        let result = library.save(name, &pieces());
+        assert!(matches!(result, Err(CheatessError::InvalidTemplateSet(_))));
+    }
+
+    #[rstest]
+    fn library_missing_set(library: TemplateLibrary) {
+        assert!(matches!(
+            library.load("unknown"),
+            Err(CheatessError::TemplateSetNotFound(_))
+        ));
+        assert!(matches!(
+            library.delete("unknown"),
+            Err(CheatessError::TemplateSetNotFound(_))
+        ));
+    }
+}
@@ -1,5 +1,10 @@
+pub mod calibration;
+pub mod debounce;
 pub mod error;
+pub mod library;
 pub mod logger;
 pub mod monitor;
 pub mod parser;
 pub mod printer;
+pub mod scheduler;
+pub mod source;
@@ -1,6 +1,41 @@
 use crate::utils::error::{CheatessError, CheatessResult};
 use image::{ImageBuffer, Rgba};
-pub use xcap::Monitor;
+use std::time::Duration;
+pub use xcap::{Monitor, Window};
+
+pub type Frame = ImageBuffer<Rgba<u8>, Vec<u8>>;
+
+/// Anything that can feed screenshots to the recognition loop.
+/// `None` means that the source is exhausted (e.g. all replayed files were consumed).
+pub trait FrameSource {
+    fn next_frame(&mut self) -> CheatessResult<Option<Frame>>;
+
+    /// Returns next frame cropped to the given region. Sources which can capture
+    /// only a part of the screen should override it.
+    fn next_region(
+        &mut self,
+        x_start: u32,
+        y_start: u32,
+        width: u32,
+        height: u32,
+    ) -> CheatessResult<Option<Frame>> {
+        Ok(self
+            .next_frame()?
+            .map(|frame| crop_frame(&frame, x_start, y_start, width, height)))
+    }
+
+    /// Position of the last returned frame in the recording, if the source is a recording.
+    fn timestamp(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Checks if frames follow each other closely (live capture, video), so animated or
+    /// dragged pieces are seen on some of them. Screenshots show settled boards, one per
+    /// position, so they don't have to be debounced.
+    fn is_continuous(&self) -> bool {
+        true
+    }
+}
 
 pub trait MonitorLike {
     fn name(&self) -> CheatessResult<String>;
@@ -51,6 +86,191 @@ fn select_monitor_from_iterable<M: MonitorLike>(
     Err(CheatessError::MonitorNotFound)
 }
 
+pub trait WindowLike {
+    fn title(&self) -> CheatessResult<String>;
+    fn pid(&self) -> CheatessResult<u32>;
+    fn is_minimized(&self) -> CheatessResult<bool>;
+}
+
+impl WindowLike for Window {
+    fn title(&self) -> CheatessResult<String> {
+        Ok(self.title()?)
+    }
+
+    fn pid(&self) -> CheatessResult<u32> {
+        Ok(self.pid()?)
+    }
+
+    fn is_minimized(&self) -> CheatessResult<bool> {
+        Ok(self.is_minimized()?)
+    }
+}
+
+/// Selects a window whose title contains given text (case insensitive) and/or
+/// which belongs to the process with given PID. Minimized windows are skipped.
+pub fn select_window(title: Option<String>, pid: Option<u32>) -> CheatessResult<Window> {
+    select_window_from_iterable(title, pid, Window::all()?)
+}
+
+fn select_window_from_iterable<W: WindowLike>(
+    title: Option<String>,
+    pid: Option<u32>,
+    windows: Vec<W>,
+) -> CheatessResult<W> {
+    if title.is_none() && pid.is_none() {
+        return Err(CheatessError::WindowNotFound);
+    }
+    let title = title.map(|t| t.to_lowercase());
+
+    let window = windows.into_iter().find(|w| {
+        let title_matches = title.as_ref().is_none_or(|t| {
+            w.title()
+                .map(|wt| wt.to_lowercase().contains(t))
+                .unwrap_or(false)
+        });
+        let pid_matches = pid.is_none_or(|p| w.pid().map(|wp| wp == p).unwrap_or(false));
+
+        title_matches && pid_matches && !w.is_minimized().unwrap_or(true)
+    });
+
+    match window {
+        Some(w) => {
+            log::info!("used window: {}", w.title().unwrap_or_default());
+            Ok(w)
+        }
+        None => Err(CheatessError::WindowNotFound),
+    }
+}
+
+/// Translates rect relative to the window's top-left corner into coordinates of the monitor
+/// on which the window is displayed. Returns `None` if the rect is not fully visible on the monitor.
+pub fn window_to_monitor_rect(
+    window_pos: (i32, i32),
+    monitor_pos: (i32, i32),
+    monitor_size: (u32, u32),
+    rect: (u32, u32, u32, u32),
+) -> Option<(u32, u32, u32, u32)> {
+    let x = window_pos.0 - monitor_pos.0 + rect.0 as i32;
+    let y = window_pos.1 - monitor_pos.1 + rect.1 as i32;
+
+    if x < 0 || y < 0 || x as u32 + rect.2 > monitor_size.0 || y as u32 + rect.3 > monitor_size.1 {
+        return None;
+    }
+    Some((x as u32, y as u32, rect.2, rect.3))
+}
+
+/// Captures the content of a single application window. All coordinates passed to
+/// `next_region` are relative to the window, so the board region stays valid when the
+/// window is moved. Regions are grabbed from the monitor the window is currently on
+/// (which is much faster than capturing the whole window), unless the window is
+/// partially off-screen.
+pub struct WindowSource {
+    window: Window,
+    monitor: Monitor,
+    geometry: (i32, i32, u32, u32),
+}
+
+impl WindowSource {
+    pub fn new(window: Window) -> CheatessResult<Self> {
+        let monitor = window.current_monitor()?;
+        let geometry = (window.x()?, window.y()?, window.width()?, window.height()?);
+
+        Ok(WindowSource {
+            window,
+            monitor,
+            geometry,
+        })
+    }
+
+    fn refresh_geometry(&mut self) -> CheatessResult<()> {
+        let geometry = (
+            self.window.x()?,
+            self.window.y()?,
+            self.window.width()?,
+            self.window.height()?,
+        );
+
+        if geometry != self.geometry {
+            if (geometry.2, geometry.3) != (self.geometry.2, self.geometry.3) {
+                log::warn!(
+                    "Window resized from {}x{} to {}x{}",
+                    self.geometry.2,
+                    self.geometry.3,
+                    geometry.2,
+                    geometry.3
+                );
+            } else {
+                log::debug!("Window moved to ({}, {})", geometry.0, geometry.1);
+            }
+            self.monitor = self.window.current_monitor()?;
+            self.geometry = geometry;
+        }
+        Ok(())
+    }
+}
+
+impl FrameSource for WindowSource {
+    fn next_frame(&mut self) -> CheatessResult<Option<Frame>> {
+        self.refresh_geometry()?;
+        let capture = self.window.capture_image()?;
+
+        Ok(Some(
+            ImageBuffer::<Rgba<u8>, _>::from_raw(
+                capture.width(),
+                capture.height(),
+                capture.into_vec(),
+            )
+            .expect("Failed to create ImageBuffer"),
+        ))
+    }
+
+    fn next_region(
+        &mut self,
+        x_start: u32,
+        y_start: u32,
+        width: u32,
+        height: u32,
+    ) -> CheatessResult<Option<Frame>> {
+        self.refresh_geometry()?;
+
+        let on_monitor = window_to_monitor_rect(
+            (self.geometry.0, self.geometry.1),
+            (self.monitor.x()?, self.monitor.y()?),
+            (self.monitor.width()?, self.monitor.height()?),
+            (x_start, y_start, width, height),
+        );
+
+        match on_monitor {
+            Some((x, y, w, h)) => Ok(Some(get_cropped_screen(&self.monitor, x, y, w, h)?)),
+            None => Ok(self
+                .next_frame()?
+                .map(|frame| crop_frame(&frame, x_start, y_start, width, height))),
+        }
+    }
+}
+
+impl FrameSource for Monitor {
+    fn next_frame(&mut self) -> CheatessResult<Option<Frame>> {
+        Ok(Some(capture_entire_screen(self)?))
+    }
+
+    fn next_region(
+        &mut self,
+        x_start: u32,
+        y_start: u32,
+        width: u32,
+        height: u32,
+    ) -> CheatessResult<Option<Frame>> {
+        Ok(Some(get_cropped_screen(
+            self, x_start, y_start, width, height,
+        )?))
+    }
+}
+
+pub fn crop_frame(frame: &Frame, x_start: u32, y_start: u32, width: u32, height: u32) -> Frame {
+    image::imageops::crop_imm(frame, x_start, y_start, width, height).to_image()
+}
+
 pub fn capture_entire_screen(monitor: &Monitor) -> CheatessResult<ImageBuffer<Rgba<u8>, Vec<u8>>> {
     let capture = monitor.capture_image()?;
 
@@ -95,6 +315,26 @@ mod tests {
         }
     }
 
+    struct MockWindow {
+        title: String,
+        pid: u32,
+        is_minimized: bool,
+    }
+
+    impl WindowLike for MockWindow {
+        fn title(&self) -> CheatessResult<String> {
+            Ok(self.title.clone())
+        }
+
+        fn pid(&self) -> CheatessResult<u32> {
+            Ok(self.pid)
+        }
+
+        fn is_minimized(&self) -> CheatessResult<bool> {
+            Ok(self.is_minimized)
+        }
+    }
+
     #[fixture]
     fn monitors() -> Vec<MockMonitor> {
         vec![
@@ -134,4 +374,87 @@ mod tests {
         let err = selected_monitor.err().unwrap();
         assert!(matches!(err, CheatessError::MonitorNotFound));
     }
+
+    #[fixture]
+    fn windows() -> Vec<MockWindow> {
+        vec![
+            MockWindow {
+                title: "Terminal".to_string(),
+                pid: 100,
+                is_minimized: false,
+            },
+            MockWindow {
+                title: "Play chess - lichess.org - Mozilla Firefox".to_string(),
+                pid: 200,
+                is_minimized: true,
+            },
+            MockWindow {
+                title: "Analysis board - lichess.org - Mozilla Firefox".to_string(),
+                pid: 200,
+                is_minimized: false,
+            },
+            MockWindow {
+                title: "Chess.com - Chromium".to_string(),
+                pid: 300,
+                is_minimized: false,
+            },
+        ]
+    }
+
+    #[rstest]
+    #[case(
+        Some("LICHESS"),
+        None,
+        "Analysis board - lichess.org - Mozilla Firefox"
+    )]
+    #[case(None, Some(300), "Chess.com - Chromium")]
+    #[case(
+        Some("firefox"),
+        Some(200),
+        "Analysis board - lichess.org - Mozilla Firefox"
+    )]
+    fn test_select_window(
+        windows: Vec<MockWindow>,
+        #[case] title: Option<&str>,
+        #[case] pid: Option<u32>,
+        #[case] expected: &str,
+    ) {
+        let selected_window =
+            select_window_from_iterable(title.map(str::to_string), pid, windows).unwrap();
+        assert_eq!(selected_window.title().unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case(Some("chess"), Some(100))]
+    #[case(Some("xxx"), None)]
+    #[case(None, None)]
+    fn test_select_nonexisting_window(
+        windows: Vec<MockWindow>,
+        #[case] title: Option<&str>,
+        #[case] pid: Option<u32>,
+    ) {
+        let selected_window = select_window_from_iterable(title.map(str::to_string), pid, windows);
+
+        assert!(matches!(
+            selected_window.err().unwrap(),
+            CheatessError::WindowNotFound
+        ));
+    }
+
+    #[rstest]
+    #[case((100, 50), (0, 0), (10, 20, 300, 300), Some((110, 70, 300, 300)))]
+    #[case((2020, 50), (1920, 0), (10, 20, 300, 300), Some((110, 70, 300, 300)))]
+    #[case((-50, 0), (0, 0), (10, 20, 300, 300), None)]
+    #[case((1800, 0), (0, 0), (10, 20, 300, 300), None)]
+    fn test_window_to_monitor_rect(
+        #[case] window_pos: (i32, i32),
+        #[case] monitor_pos: (i32, i32),
+        #[case] rect: (u32, u32, u32, u32),
+        #[case] expected: Option<(u32, u32, u32, u32)>,
+    ) {
+        assert_eq!(
+            window_to_monitor_rect(window_pos, monitor_pos, (1920, 1080), rect),
+            expected
+        );
+    }
 }
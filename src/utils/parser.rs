@@ -1,5 +1,6 @@
+use clap::parser::ValueSource;
 use clap::Parser;
-use clap::{Args, FromArgMatches, Subcommand, ValueEnum};
+use clap::{Args, CommandFactory, FromArgMatches, Subcommand, ValueEnum};
 use clap_verbosity_flag::{InfoLevel, Verbosity};
 
 #[derive(Parser, Debug, Clone)]
@@ -28,21 +29,31 @@ enum Subparser {
     Stockfish(ReClap<StockfishArgs, Self>),
     Imgproc(ReClap<ImgProcArgs, Self>),
     Engine(ReClap<EngineArgs, Self>),
+    Input(ReClap<InputArgs, Self>),
+    Templates(ReClap<TemplatesArgs, Self>),
 }
 
 #[derive(Debug, Clone, Parser)]
-/// Monitor configuration. Allows to specify monitor to use
+/// Monitor configuration. Allows to specify monitor (or application window) to use
 pub struct MonitorArgs {
     #[arg(short, long, default_value = None)]
     pub name: Option<String>,
+
+    #[arg(short, long, default_value = None)]
+    /// Part of the title of the captured window (`window` source)
+    pub window: Option<String>,
+
+    #[arg(long, default_value = None)]
+    /// PID of the process which owns the captured window (`window` source)
+    pub pid: Option<u32>,
 }
 
 #[derive(Debug, Clone, Args)]
 /// Stockfish configuration. Allows to setup stockfish engine parameters
 pub struct StockfishArgs {
-    #[arg(short, long)]
-    /// Path to the stockfish binary
-    pub path: std::path::PathBuf,
+    #[arg(short, long, default_value = None)]
+    /// Path to the stockfish binary (required in all modes except `templates`)
+    pub path: Option<std::path::PathBuf>,
 
     #[arg(short, long, default_value_t = 1700)]
     /// Elo rating of the stockfish engine
@@ -76,6 +87,10 @@ pub struct ImgProcArgs {
     /// Threshold for detecting pieces in the image during game
     pub piece_threshold: f64,
 
+    #[arg(long, value_delimiter = ',', value_parser = parse_piece_threshold)]
+    /// Thresholds of single pieces overriding the global one, e.g. `K=0.12,p=0.08`
+    pub piece_thresholds: Vec<(char, f64)>,
+
     #[arg(short, long, default_value_t = 127.0)]
     /// Threshold for extracting pieces from the image
     pub extract_piece_threshold: f64,
@@ -87,6 +102,139 @@ pub struct ImgProcArgs {
     #[arg(short, long, default_value_t = 500)]
     /// Sensitivity level to check if any change has occurred on the two boards
     pub difference_level: i32,
+
+    #[arg(long, default_value_t = 1)]
+    /// Number of unchanged frames required before the board is recognized (animations, dragged pieces)
+    pub stable_frames: usize,
+
+    #[arg(long, default_value_t = 0)]
+    /// Time in milliseconds the board has to be unchanged before it is recognized
+    pub stable_time: u64,
+
+    #[arg(long, default_value_t = 2)]
+    /// Number of consecutive equal recognitions required to accept the position
+    pub votes: usize,
+
+    #[arg(long, default_value_t = false)]
+    /// Track every board visible on the screen (each with its own Stockfish session)
+    pub multi_board: bool,
+
+    #[arg(long, default_value = None, value_parser = parse_region, conflicts_with = "corners")]
+    /// Region of the board on the screen as `x,y,w,h` (skips board detection)
+    pub region: Option<(u32, u32, u32, u32)>,
+
+    #[arg(long, default_value = None, num_args = 4, value_parser = parse_point)]
+    /// Corners of the board on the screen as four `x,y` points (skips board detection)
+    pub corners: Option<Vec<(u32, u32)>>,
+
+    #[arg(long, default_value_t = Recognition::Sliding)]
+    /// Method of recognizing pieces on the board
+    pub recognition: Recognition,
+
+    #[arg(long, default_value_t = false)]
+    /// Don't use squares highlighted by the website (last move, check) to detect moves
+    pub ignore_highlights: bool,
+
+    #[arg(long, default_value_t = false)]
+    /// Read clocks shown next to the board and record them with moves (`%clk` in the game record)
+    pub read_clocks: bool,
+
+    #[arg(long, default_value_t = false)]
+    /// Read player names and the move list shown next to the board: they fill headers of the game record, moves made before the start are synced (with `--mid-game`) and detected moves are checked against the list
+    pub read_sidebar: bool,
+
+    #[arg(long, default_value_t = false)]
+    /// Warp the detected board to a square top-down image and even out its lighting (photos, skewed captures)
+    pub perspective: bool,
+
+    #[arg(long, default_value = None)]
+    /// Piece placement (FEN) of the position on the screen used to tune thresholds (`tune` mode, default: initial position)
+    pub known_position: Option<String>,
+
+    #[arg(long, default_value = None)]
+    /// Directory with saved calibration to load (skips board detection and pieces extraction)
+    pub calibration: Option<std::path::PathBuf>,
+
+    #[arg(long, default_value = None)]
+    /// Directory where calibration is saved after the first detected move
+    pub save_calibration: Option<std::path::PathBuf>,
+
+    #[arg(skip)]
+    /// Arguments given on the command line (the others have default values)
+    pub given: Vec<String>,
+}
+
+impl ImgProcArgs {
+    /// Returns region of the board given explicitly by the user (if any).
+    pub fn manual_region(&self) -> Option<(u32, u32, u32, u32)> {
+        if let Some(region) = self.region {
+            return Some(region);
+        }
+        let corners = self.corners.as_ref()?;
+        let (min_x, max_x) = (
+            corners.iter().map(|c| c.0).min()?,
+            corners.iter().map(|c| c.0).max()?,
+        );
+        let (min_y, max_y) = (
+            corners.iter().map(|c| c.1).min()?,
+            corners.iter().map(|c| c.1).max()?,
+        );
+        Some((min_x, min_y, max_x - min_x, max_y - min_y))
+    }
+
+    /// Checks if the whole screen is needed besides the board (to read text next to it).
+    pub fn reads_screen(&self) -> bool {
+        self.read_clocks || self.read_sidebar
+    }
+
+    /// Checks if the argument (e.g. `margin`) was given on the command line.
+    pub fn is_given(&self, id: &str) -> bool {
+        self.given.iter().any(|given| given == id)
+    }
+
+    /// Returns thresholds of pieces which override the global `piece_threshold`.
+    pub fn piece_thresholds(&self) -> std::collections::HashMap<char, f64> {
+        self.piece_thresholds.iter().copied().collect()
+    }
+}
+
+/// Parses threshold of a single piece in `<piece>=<threshold>` format.
+pub fn parse_piece_threshold(s: &str) -> Result<(char, f64), String> {
+    let invalid = || format!("{s}: expected `<piece>=<threshold>`, e.g. `K=0.12`");
+    let (piece, threshold) = s.split_once('=').ok_or_else(invalid)?;
+    let piece = match piece.trim().chars().collect::<Vec<_>>()[..] {
+        [piece] if super::library::PIECES.contains(piece) => piece,
+        _ => return Err(invalid()),
+    };
+    let threshold = threshold.trim().parse::<f64>().map_err(|_| invalid())?;
+    Ok((piece, threshold))
+}
+
+fn parse_numbers(s: &str, expected: usize) -> Result<Vec<u32>, String> {
+    let numbers = s
+        .split(',')
+        .map(|n| n.trim().parse::<u32>())
+        .collect::<Result<Vec<u32>, _>>()
+        .map_err(|e| format!("{s}: {e}"))?;
+    if numbers.len() != expected {
+        return Err(format!("{s}: expected {expected} comma separated numbers"));
+    }
+    Ok(numbers)
+}
+
+/// Parses region in `x,y,w,h` format.
+pub fn parse_region(s: &str) -> Result<(u32, u32, u32, u32), String> {
+    let n = parse_numbers(s, 4)?;
+    if n[2] == 0 || n[3] == 0 {
+        return Err(format!("{s}: region can't be empty"));
+    }
+    Ok((n[0], n[1], n[2], n[3]))
+}
+
+/// Parses point in `x,y` format.
+pub fn parse_point(s: &str) -> Result<(u32, u32), String> {
+    let n = parse_numbers(s, 2)?;
+    Ok((n[0], n[1]))
 }
 
 #[derive(Debug, Clone, Parser)]
@@ -95,6 +243,218 @@ pub struct EngineArgs {
     #[arg(short, long, default_value_t = false)]
     /// Whether to use chess pieces in terminal or letters
     pub pretty: bool,
+
+    #[arg(long, default_value_t = false)]
+    /// Recognize the current position instead of assuming the initial one (requires saved templates)
+    pub mid_game: bool,
+
+    #[arg(long, default_value = None)]
+    /// Side at the bottom of the board (detected if not given)
+    pub player_color: Option<Side>,
+
+    #[arg(long, default_value = None)]
+    /// Side to move in the recognized position (inferred from the first detected move if not given)
+    pub side_to_move: Option<Side>,
+
+    #[arg(long, default_value = None, value_parser = parse_castling)]
+    /// Castling rights in the recognized position, e.g. `KQkq` or `-` (inferred from kings and rooks if not given)
+    pub castling: Option<String>,
+
+    #[arg(long, default_value = "-", value_parser = parse_en_passant)]
+    /// En passant target square in the recognized position, e.g. `e3`
+    pub en_passant: String,
+}
+
+/// Parses castling rights in FEN format.
+pub fn parse_castling(s: &str) -> Result<String, String> {
+    let valid = s == "-"
+        || (!s.is_empty()
+            && s.chars().all(|c| "KQkq".contains(c))
+            && s.chars().collect::<std::collections::HashSet<_>>().len() == s.len());
+    if !valid {
+        return Err(format!("{s}: expected `-` or combination of `KQkq`"));
+    }
+    Ok(s.to_string())
+}
+
+/// Parses en passant target square (on the 3rd or 6th rank).
+pub fn parse_en_passant(s: &str) -> Result<String, String> {
+    let valid = s == "-" || matches!(s.as_bytes(), [b'a'..=b'h', b'3' | b'6']);
+    if !valid {
+        return Err(format!(
+            "{s}: expected `-` or square on the 3rd or 6th rank"
+        ));
+    }
+    Ok(s.to_string())
+}
+
+#[derive(Debug, Clone, Parser)]
+/// Input configuration. Allows to analyze frames from other sources than a monitor
+pub struct InputArgs {
+    #[arg(short, long, default_value_t = Source::Monitor)]
+    /// Source of analyzed frames
+    pub source: Source,
+
+    #[arg(short, long, default_value = None, required_if_eq_any([("source", "images"), ("source", "watch"), ("source", "video")]))]
+    /// Path to the directory with PNG screenshots or to the video file (required by `images`, `watch` and `video` sources)
+    pub path: Option<std::path::PathBuf>,
+
+    #[arg(long, default_value_t = 100)]
+    /// Interval in milliseconds between checks for new screenshots (`watch` source)
+    pub poll: u64,
+
+    #[arg(long, default_value_t = 1)]
+    /// Analyze only every n-th frame of the video (`video` source)
+    pub step: usize,
+
+    #[arg(long, default_value = None, required_if_eq("source", "stdin"), value_parser = clap::value_parser!(u32).range(1..))]
+    /// Width of frames in the raw stream (`stdin` source)
+    pub width: Option<u32>,
+
+    #[arg(long, default_value = None, required_if_eq("source", "stdin"), value_parser = clap::value_parser!(u32).range(1..))]
+    /// Height of frames in the raw stream (`stdin` source)
+    pub height: Option<u32>,
+
+    #[arg(long, default_value_t = 30.0)]
+    /// Frame rate of the raw stream (`stdin` source)
+    pub fps: f64,
+
+    #[arg(long, default_value_t = PixelFormat::Rgba)]
+    /// Pixel format of the raw stream (`stdin` source)
+    pub format: PixelFormat,
+
+    #[arg(long, default_value_t = 10.0)]
+    /// Frame rate of the capture right after a change on the board (`monitor` and `window` sources)
+    pub target_fps: f64,
+
+    #[arg(long, default_value_t = 2.0)]
+    /// Lowest frame rate of the capture while the board is idle (`monitor` and `window` sources)
+    pub idle_fps: f64,
+
+    #[arg(long, default_value_t = 100)]
+    /// Time budget of a single cycle in milliseconds, longer cycles are reported as dropped frames
+    pub budget: u64,
+}
+
+#[derive(Debug, Clone, Parser)]
+/// Template library configuration. Allows to reuse piece templates saved for a site and theme
+pub struct TemplatesArgs {
+    #[arg(long, default_value = None)]
+    /// Directory of the template library (default: `~/.local/share/cheatess/templates`)
+    pub dir: Option<std::path::PathBuf>,
+
+    #[arg(short, long, default_value = None)]
+    /// Name of the template set to use instead of extracting pieces from the board
+    pub load: Option<String>,
+
+    #[arg(short, long, default_value = None)]
+    /// Name under which extracted templates are saved (e.g. `lichess-cburnett-brown`)
+    pub save: Option<String>,
+
+    #[arg(long, default_value_t = false)]
+    /// List saved template sets (`templates` mode)
+    pub list: bool,
+
+    #[arg(long, default_value = None)]
+    /// Name of the template set to delete (`templates` mode)
+    pub delete: Option<String>,
+
+    #[arg(long, default_value = None, requires = "to")]
+    /// Name of the template set to export (`templates` mode)
+    pub export: Option<String>,
+
+    #[arg(long, default_value = None)]
+    /// Directory where the exported template set is copied
+    pub to: Option<std::path::PathBuf>,
+}
+
+impl TemplatesArgs {
+    /// Returns the template library selected by the user.
+    pub fn library(&self) -> super::library::TemplateLibrary {
+        match &self.dir {
+            Some(dir) => super::library::TemplateLibrary::new(dir),
+            None => super::library::TemplateLibrary::new(
+                &super::library::TemplateLibrary::default_root(),
+            ),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, ValueEnum, Default)]
+pub enum Recognition {
+    /// Slide every template over the whole board
+    #[default]
+    Sliding,
+    /// Score every square against every template and an empty square
+    Squares,
+}
+
+impl std::fmt::Display for Recognition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Recognition::Sliding => "sliding",
+            Recognition::Squares => "squares",
+        };
+        write!(f, "{s}")
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, ValueEnum)]
+pub enum Side {
+    White,
+    Black,
+}
+
+impl std::fmt::Display for Side {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Side::White => "white",
+            Side::Black => "black",
+        };
+        write!(f, "{s}")
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, ValueEnum, Default)]
+pub enum PixelFormat {
+    #[default]
+    Rgba,
+    Bgr,
+}
+
+impl std::fmt::Display for PixelFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            PixelFormat::Rgba => "rgba",
+            PixelFormat::Bgr => "bgr",
+        };
+        write!(f, "{s}")
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, ValueEnum, Default)]
+pub enum Source {
+    #[default]
+    Monitor,
+    Images,
+    Watch,
+    Video,
+    Window,
+    Stdin,
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Source::Monitor => "monitor",
+            Source::Images => "images",
+            Source::Watch => "watch",
+            Source::Video => "video",
+            Source::Window => "window",
+            Source::Stdin => "stdin",
+        };
+        write!(f, "{s}")
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, ValueEnum, Default)]
@@ -102,6 +462,8 @@ pub enum Mode {
     #[default]
     Game,
     Test,
+    Templates,
+    Tune,
 }
 
 impl std::fmt::Display for Mode {
@@ -109,6 +471,8 @@ impl std::fmt::Display for Mode {
         let s = match self {
             Mode::Game => "game",
             Mode::Test => "test",
+            Mode::Templates => "templates",
+            Mode::Tune => "tune",
         };
         write!(f, "{s}")
     }
@@ -122,23 +486,35 @@ pub struct CheatessArgs {
     pub stockfish: StockfishArgs,
     pub proc_image: ImgProcArgs,
     pub engine: EngineArgs,
+    pub input: InputArgs,
+    pub templates: TemplatesArgs,
 }
 
 pub fn parse_args_from<I: IntoIterator<Item = T>, T: Into<String>>(iterator: I) -> CheatessArgs {
     let mut updated: Vec<String> = iterator.into_iter().map(Into::into).collect();
 
-    for subparser in ["monitor", "stockfish", "imgproc", "engine"] {
+    for subparser in [
+        "monitor",
+        "stockfish",
+        "imgproc",
+        "engine",
+        "input",
+        "templates",
+    ] {
         if !updated.contains(&subparser.to_string()) {
             updated.push(subparser.to_string());
         }
     }
 
-    let args = RawArgs::parse_from(updated);
+    let matches = RawArgs::command().get_matches_from(updated);
+    let args = RawArgs::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
 
     let mut monitor: Option<MonitorArgs> = None;
     let mut stockfish: Option<StockfishArgs> = None;
     let mut proc_image: Option<ImgProcArgs> = None;
     let mut engine: Option<EngineArgs> = None;
+    let mut input: Option<InputArgs> = None;
+    let mut templates: Option<TemplatesArgs> = None;
 
     let mut next = args.subparser;
     while let Some(sub) = next {
@@ -159,19 +535,59 @@ pub fn parse_args_from<I: IntoIterator<Item = T>, T: Into<String>>(iterator: I)
                 engine = Some(rec.inner);
                 (rec.next).map(|d| *d)
             }
+            Subparser::Input(rec) => {
+                input = Some(rec.inner);
+                (rec.next).map(|d| *d)
+            }
+            Subparser::Templates(rec) => {
+                templates = Some(rec.inner);
+                (rec.next).map(|d| *d)
+            }
         }
     }
 
+    // engine is started only in game mode
+    let stockfish = stockfish.expect("Stockfish hasn't been extracted");
+    if stockfish.path.is_none() && args.mode == Mode::Game {
+        RawArgs::command()
+            .error(
+                clap::error::ErrorKind::MissingRequiredArgument,
+                "the following required argument was not provided: stockfish --path <PATH>",
+            )
+            .exit();
+    }
+
     CheatessArgs {
         monitor: monitor.expect("Monitor hasn't been extracted"),
-        stockfish: stockfish.expect("Stockfish hasn't been extracted"),
-        proc_image: proc_image.expect("ImgProc hasn't been extracted"),
+        stockfish,
+        proc_image: ImgProcArgs {
+            given: given_args(&matches, "imgproc"),
+            ..proc_image.expect("ImgProc hasn't been extracted")
+        },
         engine: engine.expect("Engine hasn't been extracted"),
+        input: input.expect("Input hasn't been extracted"),
+        templates: templates.expect("Templates hasn't been extracted"),
         verbose: args.verbose,
         mode: args.mode,
     }
 }
 
+/// Returns ids of arguments of the subparser which were given on the command line.
+fn given_args(matches: &clap::ArgMatches, subparser: &str) -> Vec<String> {
+    let mut next = matches.subcommand();
+    while let Some((name, sub)) = next {
+        if name == subparser {
+            return sub
+                .ids()
+                .filter(|id| sub.value_source(id.as_str()) == Some(ValueSource::CommandLine))
+                .map(|id| id.to_string())
+                .collect();
+        }
+        next = sub.subcommand();
+    }
+    vec![]
+}
+
 // Implementation for many subcommands for clap
 // https://github.com/clap-rs/clap/issues/2222#issuecomment-2524152894
 
@@ -220,3 +636,129 @@ where
         unimplemented!()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("10,20,400,400", Ok((10, 20, 400, 400)))]
+    #[case(" 1, 2, 3, 4", Ok((1, 2, 3, 4)))]
+    #[case("1,2,3", Err(()))]
+    #[case("1,2,0,4", Err(()))]
+    #[case("a,b,c,d", Err(()))]
+    fn parse_region_cases(#[case] input: &str, #[case] expected: Result<(u32, u32, u32, u32), ()>) {
+        assert_eq!(parse_region(input).map_err(|_| ()), expected);
+    }
+
+    #[rstest]
+    #[case(&["imgproc", "--region", "5,6,300,300"], Some((5, 6, 300, 300)))]
+    #[case(&["imgproc", "--corners", "10,12", "110,10", "112,108", "12,110"], Some((10, 10, 102, 100)))]
+    #[case(&["imgproc"], None)]
+    fn manual_region_cases(#[case] args: &[&str], #[case] expected: Option<(u32, u32, u32, u32)>) {
+        let args = ImgProcArgs::parse_from(args);
+
+        assert_eq!(args.manual_region(), expected);
+    }
+
+    #[rstest]
+    #[case(&["input", "--source", "images"], false)]
+    #[case(&["input", "--source", "video"], false)]
+    #[case(&["input", "--source", "watch", "--path", "shots"], true)]
+    #[case(&["input", "--source", "monitor"], true)]
+    #[case(&["input", "--source", "stdin", "--width", "640"], false)]
+    #[case(&["input", "--source", "stdin", "--width", "640", "--height", "0"], false)]
+    #[case(&["input", "--source", "stdin", "--width", "640", "--height", "480"], true)]
+    fn input_arguments_required_by_source(#[case] args: &[&str], #[case] valid: bool) {
+        assert_eq!(InputArgs::try_parse_from(args).is_ok(), valid);
+    }
+
+    #[rstest]
+    #[case("KQkq", true)]
+    #[case("Kq", true)]
+    #[case("-", true)]
+    #[case("KKq", false)]
+    #[case("", false)]
+    #[case("KX", false)]
+    fn parse_castling_cases(#[case] input: &str, #[case] valid: bool) {
+        assert_eq!(parse_castling(input).is_ok(), valid);
+    }
+
+    #[rstest]
+    #[case("e3", true)]
+    #[case("h6", true)]
+    #[case("-", true)]
+    #[case("e4", false)]
+    #[case("i3", false)]
+    fn parse_en_passant_cases(#[case] input: &str, #[case] valid: bool) {
+        assert_eq!(parse_en_passant(input).is_ok(), valid);
+    }
+
+    #[rstest]
+    #[case("K=0.12", Ok(('K', 0.12)))]
+    #[case(" p = 0.08", Ok(('p', 0.08)))]
+    #[case("X=0.1", Err(()))]
+    #[case("Kq=0.1", Err(()))]
+    #[case("K", Err(()))]
+    #[case("K=low", Err(()))]
+    fn parse_piece_threshold_cases(#[case] input: &str, #[case] expected: Result<(char, f64), ()>) {
+        assert_eq!(parse_piece_threshold(input).map_err(|_| ()), expected);
+    }
+
+    #[rstest]
+    fn piece_thresholds_list() {
+        let args = ImgProcArgs::parse_from(["imgproc", "--piece-thresholds", "K=0.12,q=0.08"]);
+
+        assert_eq!(args.piece_thresholds(), [('K', 0.12), ('q', 0.08)].into());
+    }
+
+    #[rstest]
+    fn given_imgproc_args() {
+        let args = parse_args_from([
+            "cheatess-core",
+            "stockfish",
+            "--path",
+            "stockfish",
+            "engine",
+            "--mid-game",
+            "imgproc",
+            "--margin",
+            "3",
+            "-b",
+            "90",
+        ]);
+
+        assert!(args.proc_image.is_given("margin"));
+        assert!(args.proc_image.is_given("board_threshold"));
+        assert!(!args.proc_image.is_given("piece_threshold"));
+    }
+
+    #[rstest]
+    fn templates_mode_without_stockfish_path() {
+        let args = parse_args_from([
+            "cheatess-core",
+            "--mode",
+            "templates",
+            "templates",
+            "--export",
+            "lichess",
+            "--to",
+            "/tmp",
+        ]);
+
+        assert_eq!(args.mode, Mode::Templates);
+        assert_eq!(args.templates.export.as_deref(), Some("lichess"));
+        assert_eq!(args.stockfish.path, None);
+    }
+
+    #[rstest]
+    #[case(Mode::Test)]
+    #[case(Mode::Tune)]
+    fn modes_without_engine_dont_need_stockfish_path(#[case] mode: Mode) {
+        let args = parse_args_from(["cheatess-core", "--mode", mode.to_string().as_str()]);
+
+        assert_eq!(args.mode, mode);
+        assert_eq!(args.stockfish.path, None);
+    }
+}
@@ -0,0 +1,151 @@
+// Pacing of the capture loop. Board is polled with the target frame rate right
+// after a change and the polling slows down (up to the idle frame rate) while
+// nothing happens on the board.
+use std::time::{Duration, Instant};
+
+/// Interval multiplier applied after every cycle without a change.
+const BACKOFF: f64 = 1.25;
+/// How often statistics are logged during the session.
+const REPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SchedulerStats {
+    pub frames: u64,
+    pub dropped: u64,
+    pub fps: f64,
+}
+
+pub struct FrameScheduler {
+    min_interval: Duration,
+    max_interval: Duration,
+    interval: Duration,
+    budget: Duration,
+    frames: u64,
+    dropped: u64,
+    started: Instant,
+    last_report: Instant,
+}
+
+impl FrameScheduler {
+    /// Creates scheduler polling with `target_fps` after a change and backing off to
+    /// `idle_fps` when the board is idle. Cycles longer than `budget` are counted as dropped.
+    pub fn new(target_fps: f64, idle_fps: f64, budget: Duration) -> Self {
+        let min_interval = fps_to_interval(target_fps);
+        let max_interval = fps_to_interval(idle_fps).max(min_interval);
+        let now = Instant::now();
+
+        FrameScheduler {
+            min_interval,
+            max_interval,
+            interval: min_interval,
+            budget,
+            frames: 0,
+            dropped: 0,
+            started: now,
+            last_report: now,
+        }
+    }
+
+    /// Current interval between the starts of two cycles.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Records finished cycle and returns how long to wait before the next one.
+    pub fn finish_cycle(&mut self, elapsed: Duration, changed: bool) -> Duration {
+        self.frames += 1;
+        if elapsed > self.budget {
+            self.dropped += 1;
+            log::debug!("Cycle exceeded budget: {elapsed:?} > {:?}", self.budget);
+        }
+
+        self.interval = if changed {
+            self.min_interval
+        } else {
+            self.interval.mul_f64(BACKOFF).min(self.max_interval)
+        };
+
+        if self.last_report.elapsed() >= REPORT_INTERVAL {
+            self.last_report = Instant::now();
+            self.log_stats();
+        }
+        self.interval.saturating_sub(elapsed)
+    }
+
+    pub fn stats(&self) -> SchedulerStats {
+        let wall = self.started.elapsed().as_secs_f64();
+        SchedulerStats {
+            frames: self.frames,
+            dropped: self.dropped,
+            fps: if wall > 0.0 {
+                self.frames as f64 / wall
+            } else {
+                0.0
+            },
+        }
+    }
+
+    pub fn log_stats(&self) {
+        let stats = self.stats();
+        log::info!(
+            "Analyzed {} frames ({:.1} fps), dropped {} frames",
+            stats.frames,
+            stats.fps,
+            stats.dropped
+        );
+    }
+}
+
+fn fps_to_interval(fps: f64) -> Duration {
+    Duration::from_nanos((1e9 / fps.max(0.01)).round() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::{fixture, rstest};
+
+    #[fixture]
+    fn scheduler() -> FrameScheduler {
+        FrameScheduler::new(10.0, 2.0, Duration::from_millis(50))
+    }
+
+    #[rstest]
+    fn scheduler_backs_off_when_idle(mut scheduler: FrameScheduler) {
+        assert_eq!(scheduler.interval(), Duration::from_millis(100));
+
+        let mut previous = scheduler.interval();
+        for _ in 0..5 {
+            scheduler.finish_cycle(Duration::from_millis(10), false);
+            assert!(scheduler.interval() > previous);
+            previous = scheduler.interval();
+        }
+        for _ in 0..20 {
+            scheduler.finish_cycle(Duration::from_millis(10), false);
+        }
+        assert_eq!(scheduler.interval(), Duration::from_millis(500));
+    }
+
+    #[rstest]
+    fn scheduler_speeds_up_after_change(mut scheduler: FrameScheduler) {
+        for _ in 0..20 {
+            scheduler.finish_cycle(Duration::from_millis(10), false);
+        }
+
+        let wait = scheduler.finish_cycle(Duration::from_millis(30), true);
+        assert_eq!(scheduler.interval(), Duration::from_millis(100));
+        assert_eq!(wait, Duration::from_millis(70));
+    }
+
+    #[rstest]
+    fn scheduler_counts_dropped_frames(mut scheduler: FrameScheduler) {
+        scheduler.finish_cycle(Duration::from_millis(20), true);
+        let wait = scheduler.finish_cycle(Duration::from_millis(150), true);
+        scheduler.finish_cycle(Duration::from_millis(60), false);
+
+        let stats = scheduler.stats();
+        assert_eq!(wait, Duration::ZERO);
+        assert_eq!(stats.frames, 3);
+        assert_eq!(stats.dropped, 2);
+    }
+}
@@ -0,0 +1,396 @@
+// Frame sources which don't need a live X session: replay of recorded
+// screenshots, watching a directory for new ones, decoding video files
+// and reading raw frames from a stream (e.g. ffmpeg pipe).
+use super::monitor::{Frame, FrameSource};
+use super::parser::PixelFormat;
+use crate::core::procimg::{mat_to_image_buffer, Mat};
+use crate::utils::error::{CheatessError, CheatessResult};
+use opencv::{prelude::*, videoio};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Number of polls in which a new screenshot may fail to load (e.g. it's still written)
+/// before it is skipped for good.
+const MAX_LOAD_ATTEMPTS: usize = 10;
+
+fn is_png(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("png"))
+}
+
+/// Returns all PNG files from directory, sorted by file name.
+fn list_png_files(dir: &Path) -> CheatessResult<Vec<PathBuf>> {
+    let mut files = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_file() && is_png(path))
+        .collect::<Vec<_>>();
+    files.sort();
+    Ok(files)
+}
+
+fn load_frame(path: &Path) -> CheatessResult<Frame> {
+    log::trace!("Loading frame from {}", path.display());
+    Ok(image::open(path)?.into_rgba8())
+}
+
+/// Replays an ordered (by file name) directory of PNG screenshots.
+/// Every call to `next_frame` returns the next screenshot.
+pub struct ImageDirSource {
+    files: VecDeque<PathBuf>,
+}
+
+impl ImageDirSource {
+    pub fn new(dir: &Path) -> CheatessResult<Self> {
+        let files = list_png_files(dir)?;
+        if files.is_empty() {
+            return Err(CheatessError::NoFramesFound(dir.display().to_string()));
+        }
+        log::info!(
+            "Replaying {} screenshots from {}",
+            files.len(),
+            dir.display()
+        );
+
+        Ok(ImageDirSource {
+            files: files.into(),
+        })
+    }
+}
+
+impl FrameSource for ImageDirSource {
+    fn next_frame(&mut self) -> CheatessResult<Option<Frame>> {
+        match self.files.pop_front() {
+            Some(path) => Ok(Some(load_frame(&path)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn is_continuous(&self) -> bool {
+        false
+    }
+}
+
+/// Waits for new PNG screenshots in the directory. Files which were present
+/// before the source was created are ignored.
+pub struct DirectoryWatchSource {
+    dir: PathBuf,
+    seen: HashSet<PathBuf>,
+    /// Failed attempts to load new screenshots.
+    failures: HashMap<PathBuf, usize>,
+    poll_interval: Duration,
+}
+
+impl DirectoryWatchSource {
+    pub fn new(dir: &Path, poll_interval: Duration) -> CheatessResult<Self> {
+        let seen = list_png_files(dir)?.into_iter().collect();
+        log::info!("Waiting for new screenshots in {}", dir.display());
+
+        Ok(DirectoryWatchSource {
+            dir: dir.to_path_buf(),
+            seen,
+            failures: HashMap::new(),
+            poll_interval,
+        })
+    }
+}
+
+impl FrameSource for DirectoryWatchSource {
+    fn next_frame(&mut self) -> CheatessResult<Option<Frame>> {
+        loop {
+            for path in list_png_files(&self.dir)? {
+                if self.seen.contains(&path) {
+                    continue;
+                }
+
+                // File might be still written by another process, so try again in the next poll
+                // (newer files are still delivered meanwhile).
+                match load_frame(&path) {
+                    Ok(frame) => {
+                        self.seen.insert(path);
+                        return Ok(Some(frame));
+                    }
+                    Err(e) => {
+                        let failures = self.failures.entry(path.clone()).or_default();
+                        *failures += 1;
+                        if *failures < MAX_LOAD_ATTEMPTS {
+                            log::debug!("Skipping {} for now: {e}", path.display());
+                            continue;
+                        }
+                        log::warn!("Skipping {}, it can't be loaded: {e}", path.display());
+                        self.failures.remove(&path);
+                        self.seen.insert(path);
+                    }
+                }
+            }
+            std::thread::sleep(self.poll_interval);
+        }
+    }
+
+    fn is_continuous(&self) -> bool {
+        false
+    }
+}
+
+/// Decodes video file (any container supported by OpenCV videoio, e.g. mp4/webm)
+/// frame by frame. Only every `step`-th frame is decoded, the rest is skipped.
+pub struct VideoFileSource {
+    capture: videoio::VideoCapture,
+    step: usize,
+    timestamp: Option<Duration>,
+}
+
+impl VideoFileSource {
+    pub fn new(path: &Path, step: usize) -> CheatessResult<Self> {
+        let capture =
+            videoio::VideoCapture::from_file(&path.display().to_string(), videoio::CAP_ANY)?;
+        if !capture.is_opened()? {
+            return Err(CheatessError::NoFramesFound(path.display().to_string()));
+        }
+
+        log::info!(
+            "Decoding video {} ({} frames, {} fps)",
+            path.display(),
+            capture.get(videoio::CAP_PROP_FRAME_COUNT)?,
+            capture.get(videoio::CAP_PROP_FPS)?
+        );
+
+        Ok(VideoFileSource {
+            capture,
+            step: step.max(1),
+            timestamp: None,
+        })
+    }
+}
+
+impl FrameSource for VideoFileSource {
+    fn next_frame(&mut self) -> CheatessResult<Option<Frame>> {
+        for _ in 1..self.step {
+            if !self.capture.grab()? {
+                return Ok(None);
+            }
+        }
+
+        let mut mat = Mat::default();
+        if !self.capture.read(&mut mat)? || mat.empty() {
+            return Ok(None);
+        }
+        let msec = self.capture.get(videoio::CAP_PROP_POS_MSEC)?;
+        self.timestamp = Some(Duration::from_secs_f64(msec.max(0.0) / 1000.0));
+
+        Ok(Some(mat_to_image_buffer(&mat)?))
+    }
+
+    fn timestamp(&self) -> Option<Duration> {
+        self.timestamp
+    }
+}
+
+/// Reads raw, uncompressed frames of fixed size from a stream, e.g. produced by
+/// `ffmpeg -i <input> -f rawvideo -pix_fmt rgba -`. Frame rate is used only
+/// to compute timestamps of frames.
+pub struct RawStreamSource<R: Read> {
+    reader: R,
+    width: u32,
+    height: u32,
+    format: PixelFormat,
+    fps: f64,
+    buffer: Vec<u8>,
+    frames_read: u64,
+}
+
+impl<R: Read> RawStreamSource<R> {
+    pub fn new(reader: R, width: u32, height: u32, format: PixelFormat, fps: f64) -> Self {
+        let bytes_per_pixel = match format {
+            PixelFormat::Rgba => 4,
+            PixelFormat::Bgr => 3,
+        };
+        log::info!("Reading raw {format} stream {width}x{height} @ {fps} fps");
+
+        RawStreamSource {
+            reader,
+            width,
+            height,
+            format,
+            fps,
+            buffer: vec![0; (width * height * bytes_per_pixel) as usize],
+            frames_read: 0,
+        }
+    }
+}
+
+impl<R: Read> FrameSource for RawStreamSource<R> {
+    fn next_frame(&mut self) -> CheatessResult<Option<Frame>> {
+        match self.reader.read_exact(&mut self.buffer) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        self.frames_read += 1;
+
+        let rgba = match self.format {
+            PixelFormat::Rgba => self.buffer.clone(),
+            PixelFormat::Bgr => self
+                .buffer
+                .chunks_exact(3)
+                .flat_map(|bgr| [bgr[2], bgr[1], bgr[0], 255])
+                .collect(),
+        };
+
+        Ok(Some(
+            Frame::from_raw(self.width, self.height, rgba).expect("Failed to create ImageBuffer"),
+        ))
+    }
+
+    fn timestamp(&self) -> Option<Duration> {
+        if self.frames_read == 0 || self.fps <= 0.0 {
+            return None;
+        }
+        Some(Duration::from_secs_f64(
+            (self.frames_read - 1) as f64 / self.fps,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+    use rstest::{fixture, rstest};
+
+    fn save_frame(dir: &Path, name: &str, value: u8) {
+        Frame::from_pixel(4, 4, Rgba([value, value, value, 255]))
+            .save(dir.join(name))
+            .unwrap();
+    }
+
+    /// Temporary directory removed when the test ends.
+    struct TempDir(PathBuf);
+
+    impl std::ops::Deref for TempDir {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[fixture]
+    fn screenshots_dir() -> TempDir {
+        let dir = std::env::temp_dir().join(format!(
+            "cheatess-source-{}-{:?}",
+            std::process::id(),
+            std::thread::current().id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        save_frame(&dir, "002.png", 20);
+        save_frame(&dir, "001.png", 10);
+        save_frame(&dir, "003.png", 30);
+        std::fs::write(dir.join("notes.txt"), "not an image").unwrap();
+        TempDir(dir)
+    }
+
+    #[rstest]
+    fn image_dir_source_replays_files_in_order(screenshots_dir: TempDir) {
+        let mut source = ImageDirSource::new(&screenshots_dir).unwrap();
+
+        for expected in [10, 20, 30] {
+            let frame = source.next_frame().unwrap().unwrap();
+            assert_eq!(frame.get_pixel(0, 0)[0], expected);
+        }
+        assert!(source.next_frame().unwrap().is_none());
+    }
+
+    #[rstest]
+    fn image_dir_source_crops_region(screenshots_dir: TempDir) {
+        let mut source = ImageDirSource::new(&screenshots_dir).unwrap();
+
+        let frame = source.next_region(1, 1, 2, 3).unwrap().unwrap();
+        assert_eq!(frame.dimensions(), (2, 3));
+    }
+
+    #[rstest]
+    fn image_dir_source_fails_on_empty_dir(screenshots_dir: TempDir) {
+        let empty = screenshots_dir.join("empty");
+        std::fs::create_dir_all(&empty).unwrap();
+
+        let result = ImageDirSource::new(&empty);
+        assert!(matches!(result, Err(CheatessError::NoFramesFound(_))));
+    }
+
+    #[rstest]
+    fn directory_watch_source_returns_only_new_files(screenshots_dir: TempDir) {
+        let mut source =
+            DirectoryWatchSource::new(&screenshots_dir, Duration::from_millis(1)).unwrap();
+        save_frame(&screenshots_dir, "004.png", 40);
+
+        let frame = source.next_frame().unwrap().unwrap();
+        assert_eq!(frame.get_pixel(0, 0)[0], 40);
+    }
+
+    #[rstest]
+    fn directory_watch_source_skips_broken_file(screenshots_dir: TempDir) {
+        let mut source =
+            DirectoryWatchSource::new(&screenshots_dir, Duration::from_millis(1)).unwrap();
+        let broken = screenshots_dir.join("004.png");
+        std::fs::write(&broken, "not an image").unwrap();
+        save_frame(&screenshots_dir, "005.png", 50);
+
+        // newer screenshot isn't held back while the broken one is retried
+        let frame = source.next_frame().unwrap().unwrap();
+        assert_eq!(frame.get_pixel(0, 0)[0], 50);
+        assert_eq!(source.failures.get(&broken), Some(&1));
+        assert!(!source.seen.contains(&broken));
+
+        source
+            .failures
+            .insert(broken.clone(), MAX_LOAD_ATTEMPTS - 1);
+        save_frame(&screenshots_dir, "006.png", 60);
+
+        let frame = source.next_frame().unwrap().unwrap();
+        assert_eq!(frame.get_pixel(0, 0)[0], 60);
+        assert!(source.seen.contains(&broken));
+    }
+
+    #[rstest]
+    #[case(PixelFormat::Rgba, vec![1, 2, 3, 255, 4, 5, 6, 255, 7, 8, 9, 255, 10, 11, 12, 255])]
+    #[case(PixelFormat::Bgr, vec![3, 2, 1, 6, 5, 4, 9, 8, 7, 12, 11, 10])]
+    fn raw_stream_source_reads_frames(#[case] format: PixelFormat, #[case] stream: Vec<u8>) {
+        let mut source = RawStreamSource::new(std::io::Cursor::new(stream), 2, 1, format, 2.0);
+
+        let first = source.next_frame().unwrap().unwrap();
+        assert_eq!(first.dimensions(), (2, 1));
+        assert_eq!(first.as_raw(), &vec![1, 2, 3, 255, 4, 5, 6, 255]);
+        assert_eq!(source.timestamp(), Some(Duration::ZERO));
+
+        let second = source.next_frame().unwrap().unwrap();
+        assert_eq!(second.as_raw(), &vec![7, 8, 9, 255, 10, 11, 12, 255]);
+        assert_eq!(source.timestamp(), Some(Duration::from_millis(500)));
+
+        assert!(source.next_frame().unwrap().is_none());
+    }
+
+    #[rstest]
+    fn raw_stream_source_ignores_incomplete_frame() {
+        let mut source = RawStreamSource::new(
+            std::io::Cursor::new(vec![0; 10]),
+            2,
+            2,
+            PixelFormat::Rgba,
+            30.0,
+        );
+
+        assert!(source.next_frame().unwrap().is_none());
+        assert!(source.timestamp().is_none());
+    }
+}